@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+use chrono::{DateTime, Local, TimeZone};
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use walkdir::WalkDir;
+use zip::ZipArchive;
+use tracing::warn;
+
+use crate::scan::SymlinkMode;
+
+
+/// Extensions of archive files that can be mounted like directories.
+pub const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "pak"];
+
+type SharedArchive = Arc<Mutex<ZipArchive<File>>>;
+
+/// Parsed archives with the modification time they were parsed at, so an archive's directory is
+/// only read again when the archive changes.
+static OPEN_ARCHIVES: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, SharedArchive)>>> = OnceLock::new();
+
+pub fn is_archive(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => ARCHIVE_EXTENSIONS.iter().any(|a| ext.eq_ignore_ascii_case(a)) && path.is_file(),
+        None => false
+    }
+}
+
+/// Splits a path pointing into an archive, like `content/base.pak/textures/grid.png`, into the
+/// archive and the entry name. `None` for paths that exist on disk.
+pub fn split_archive_path(path: &Path) -> Option<(&Path, String)> {
+    if path.exists() {
+        return None;
+    }
+    let archive = path.ancestors().skip(1).find(|a| is_archive(a))?;
+    let entry = path.strip_prefix(archive).ok()?.to_string_lossy().replace("\\", "/");
+    Some((archive, entry))
+}
+
+fn open(archive: &Path) -> io::Result<SharedArchive> {
+    let modified = std::fs::metadata(archive)?.modified()?;
+    let mut cache = OPEN_ARCHIVES.get_or_init(Default::default).lock();
+    if let Some((time, shared)) = cache.get(archive) {
+        if *time == modified {
+            return Ok(shared.clone());
+        }
+    }
+    let shared = Arc::new(Mutex::new(ZipArchive::new(File::open(archive)?)?));
+    cache.insert(archive.to_path_buf(), (modified, shared.clone()));
+    Ok(shared)
+}
+
+/// Reads a whole file, from inside an archive if the path points into one.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    match split_archive_path(path) {
+        Some((archive, name)) => {
+            let shared = open(archive)?;
+            let mut archive = shared.lock();
+            let mut file = archive.by_name(&name)?;
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        },
+        None => std::fs::read(path)
+    }
+}
+
+/// `Path::exists`, looking inside archives.
+pub fn exists(path: &Path) -> bool {
+    match split_archive_path(path) {
+        Some((archive, name)) => open(archive).is_ok_and(|a| a.lock().by_name(&name).is_ok()),
+        None => path.exists()
+    }
+}
+
+/// Modification time of a file, using the entry's own timestamp for files inside archives.
+pub fn modified(path: &Path) -> io::Result<DateTime<Local>> {
+    match split_archive_path(path) {
+        Some((archive, name)) => {
+            let shared = open(archive)?;
+            let mut archive = shared.lock();
+            let time = archive.by_name(&name)?.last_modified();
+            Local.with_ymd_and_hms(time.year() as i32, time.month() as u32, time.day() as u32,
+                                   time.hour() as u32, time.minute() as u32, time.second() as u32)
+                 .earliest()
+                 .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid archive entry timestamp"))
+        },
+        None => Ok(DateTime::<Local>::from(std::fs::metadata(path)?.modified()?))
+    }
+}
+
+/// Every file below `start` with its size. Walks into archives when `start` is one or points
+/// into one. `symlinks` decides which links on disk are walked.
+pub fn walk_files(start: &Path, symlinks: SymlinkMode) -> Box<dyn Iterator<Item = (PathBuf, u64)>> {
+    let (archive, prefix) = match is_archive(start) {
+        true => (start, String::new()),
+        false => match split_archive_path(start) {
+            Some(split) => split,
+            None => {
+                let files = WalkDir::new(start).follow_links(symlinks == SymlinkMode::Follow)
+                                               .into_iter()
+                                               .filter_map(|e| match e {
+                                                   Ok(e) => Some(e),
+                                                   Err(e) => {
+                                                       if let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) {
+                                                           warn!(path = %path.display(), ancestor = %ancestor.display(), "Skipping symlink loop");
+                                                       }
+                                                       None
+                                                   }
+                                               })
+                                               .filter(move |e| match (symlinks, e.path_is_symlink()) {
+                                                   (_, false) | (SymlinkMode::Follow, true) => true,
+                                                   (SymlinkMode::FilesOnly, true) => e.path().is_file(),
+                                                   (SymlinkMode::Skip, true) => false,
+                                               })
+                                               .filter(|e| !e.file_type().is_dir())
+                                               .map(|e| {
+                                                   let size = e.metadata().map(|m| m.len()).unwrap_or(0);
+                                                   (e.into_path(), size)
+                                               });
+                return Box::new(files);
+            }
+        }
+    };
+    match list_files(archive, &prefix) {
+        Ok(files) => Box::new(files.into_iter()),
+        Err(e) => {
+            warn!(path = %archive.display(), error = %e, "Failed to read archive");
+            Box::new(std::iter::empty())
+        }
+    }
+}
+
+fn list_files(archive_path: &Path, prefix: &str) -> io::Result<Vec<(PathBuf, u64)>> {
+    let shared = open(archive_path)?;
+    let mut archive = shared.lock();
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        // entries that would land outside the archive, like `../x`, are skipped
+        let name = match (file.is_dir(), file.enclosed_name()) {
+            (false, Some(name)) => name.to_string_lossy().replace("\\", "/"),
+            _ => continue
+        };
+        if prefix.is_empty() || name == prefix || name.starts_with(&format!("{}/", prefix)) {
+            files.push((archive_path.join(&name), file.size()));
+        }
+    }
+    Ok(files)
+}
@@ -1,19 +1,41 @@
+use std::path::Path;
 use chrono::{DateTime, Local};
 use hashbrown::HashMap;
-use crate::texture::TextureMetadata;
+use serde::{Serialize, Deserialize};
+use parking_lot::Mutex;
+use crate::texture::{TextureMetadata, TextureOverride};
+use crate::source::SourceBytes;
+use crate::arena::SlotId;
 
 
-#[derive(Debug)]
+/// Directory structure of the content tree. Assets themselves live in the registry's arena.
+#[derive(Debug, Serialize, Deserialize)]
 pub enum FileTreeNode {
     Directory(HashMap<String, FileTreeNode>),
-    File(Asset),
+    File(SlotId),
+}
+
+/// One entry in a directory listing, see `AssetRegistry::list_directory`.
+#[derive(Debug, Clone)]
+pub struct DirEntryInfo<'a> {
+    pub name: String,
+    /// Path relative to the content root.
+    pub path: String,
+    /// `None` for subdirectories.
+    pub asset: Option<&'a Asset>,
+}
+
+impl DirEntryInfo<'_> {
+    pub fn is_directory(&self) -> bool {
+        self.asset.is_none()
+    }
 }
 
 
 // Asset types / internals /////////////////////////////////////////////////////////////////////////
 
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum AssetData {
     Texture(TextureAssetData)
 }
@@ -24,33 +46,122 @@ impl AssetData {
             AssetData::Texture(_) => AssetType::Texture,
         }
     }
+
+    /// Bytes of decoded data currently held in memory.
+    pub fn cpu_bytes(&self) -> usize {
+        match self {
+            AssetData::Texture(tex) => tex.data.lock().as_ref().map_or(0, |d| d.len()),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AssetType {
     Texture
 }
 
 
+// Asset UIDs //////////////////////////////////////////////////////////////////////////////////////
+
+
+/// Stable identifier of an asset, kept when the asset is moved, renamed or reprocessed. Serialized
+/// as a plain integer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AssetId(pub u64);
+
+impl AssetId {
+    /// Never assigned to an asset, e.g. for "no asset" in serialized data.
+    pub const NIL: AssetId = AssetId(0);
+
+    pub fn is_nil(&self) -> bool {
+        *self == Self::NIL
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.is_nil()
+    }
+}
+
+impl std::fmt::Display for AssetId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl std::fmt::Debug for AssetId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AssetId({:016x})", self.0)
+    }
+}
+
+impl std::str::FromStr for AssetId {
+    type Err = std::num::ParseIntError;
+
+    /// Parses the hex form written by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(AssetId)
+    }
+}
+
+impl From<u64> for AssetId {
+    fn from(uid: u64) -> Self {
+        AssetId(uid)
+    }
+}
+
+/// Derives a stable UID from an asset's path relative to the content root (with `/` separators).
+/// This is a 64-bit FNV-1a hash, so the same path produces the same UID across runs and machines.
+pub fn uid_from_path(relative_path: &str) -> AssetId {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    AssetId(relative_path.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME)))
+}
+
+/// Hashes a source file's contents (xxHash64), used to tell real edits apart from files that were merely touched.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    twox_hash::XxHash64::oneshot(0, bytes)
+}
+
+pub fn hash_file(path: &Path) -> std::io::Result<u64> {
+    Ok(content_hash(&SourceBytes::read(path)?))
+}
+
+
 // Asset main struct ///////////////////////////////////////////////////////////////////////////////
 
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Asset {
     pub path: String,
     pub timestamp: DateTime<Local>,
-    pub uid: u64,
-    pub thumbnail_id: Option<u64>,
+    pub uid: AssetId,
+    pub content_hash: u64,
+    /// Size of the source file in bytes.
+    pub source_size: u64,
+    pub thumbnail_id: Option<AssetId>,
+    /// Free-form labels, copied from the sidecar on import.
+    pub tags: Vec<String>,
+    /// Paths of the assets this one references, relative to the content root.
+    pub dependencies: Vec<String>,
+    /// Registered at runtime with `AssetRegistry::register_asset` instead of imported from a file.
+    /// Its data only lives in memory, so it isn't scanned, reimported or cooked.
+    pub generated: bool,
     pub data: AssetData,
 }
 
 impl Asset {
-    pub fn new(path: &str, timestamp: DateTime<Local>, uid: u64, thumbnail_id: Option<u64>, data: AssetData) -> Self {
+    pub fn new(path: &str, timestamp: DateTime<Local>, uid: AssetId, content_hash: u64, source_size: u64, thumbnail_id: Option<AssetId>, data: AssetData) -> Self {
         Self {
             path: path.to_string(),
             timestamp,
             uid,
+            content_hash,
+            source_size,
             thumbnail_id,
+            tags: Vec::new(),
+            dependencies: Vec::new(),
+            generated: false,
             data
         }
     }
@@ -60,14 +171,19 @@ impl Asset {
 // Specific asset inner types //////////////////////////////////////////////////////////////////////
 
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TextureAssetData {
     pub settings: TextureMetadata,
-    pub data: Vec<u8>,
+    /// Decoded RGBA8 pixels. `None` until the texture is first requested, and again after upload
+    /// unless the registry keeps CPU copies. Never persisted, since it can be decoded from the source.
+    #[serde(skip)]
+    pub data: Mutex<Option<Vec<u8>>>,
+    /// Settings for specific platforms and quality tiers, by name. Only used when cooking.
+    pub overrides: HashMap<String, TextureOverride>,
 }
 
 impl TextureAssetData {
-    pub fn new(settings: TextureMetadata, data: Vec<u8>) -> Self {
-        Self { settings, data }
+    pub fn new(settings: TextureMetadata, data: Option<Vec<u8>>) -> Self {
+        Self { settings, data: Mutex::new(data), overrides: HashMap::new() }
     }
 }
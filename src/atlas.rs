@@ -0,0 +1,228 @@
+//! Texture atlases packed at runtime, for UI and icon heavy games that want few binds without an
+//! offline atlas step.
+//!
+//! Textures are copied into one big sRGB RGBA8 image as they're inserted, placed on shelves: rows
+//! as tall as the first texture put on them, filled left to right. Space is only reused once a
+//! whole shelf is empty again, so atlases with a lot of churn should be `clear`ed now and then.
+
+use std::sync::Arc;
+use hashbrown::HashMap;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::device::Queue;
+use vulkano::format::{ClearValue, R8G8B8A8Srgb};
+use vulkano::image::{Dimensions, ImageUsage, StorageImage};
+use vulkano::sync::GpuFuture;
+
+use crate::asset::{AssetData, AssetId};
+use crate::error::UploadError;
+use crate::registry::AssetRegistry;
+
+
+/// A region of an atlas, in UVs from 0 to 1 across the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    /// Maps a UV on the original texture to the atlas.
+    pub fn remap(&self, uv: [f32; 2]) -> [f32; 2] {
+        [
+            self.min[0] + uv[0] * (self.max[0] - self.min[0]),
+            self.min[1] + uv[1] * (self.max[1] - self.min[1]),
+        ]
+    }
+}
+
+
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next region on the shelf goes.
+    x: u32,
+    regions: usize,
+}
+
+#[derive(Debug)]
+struct Region {
+    shelf: usize,
+    offset: [u32; 2],
+    size: [u32; 2],
+    /// Asset version the region was copied from, see `AssetRegistry::versions`.
+    version: u64,
+}
+
+/// A square atlas filled with registry textures at runtime, see the module docs. Uploads are added
+/// to the registry's `flush_uploads`.
+pub struct DynamicAtlas {
+    pub size: u32,
+    /// Empty pixels around each region, so filtering doesn't bleed neighbours in.
+    pub padding: u32,
+    image: Arc<StorageImage<R8G8B8A8Srgb>>,
+    queue: Arc<Queue>,
+    shelves: Vec<Shelf>,
+    regions: HashMap<AssetId, Region>,
+}
+
+impl DynamicAtlas {
+    /// Creates an empty atlas of `size` by `size` pixels, cleared to transparent black.
+    pub fn new(registry: &AssetRegistry, size: u32, padding: u32) -> Result<Self, UploadError> {
+        let queue = match &registry.queue {
+            Some(queue) => queue.clone(),
+            None => return Err(UploadError::Headless("atlas".to_string()))
+        };
+        let image_error = |source| UploadError::ImageCreation { path: "atlas".to_string(), source };
+        let command_error = |reason: &dyn std::fmt::Display| UploadError::Commands { path: "atlas".to_string(), reason: reason.to_string() };
+        let device = queue.device();
+        let usage = ImageUsage { transfer_destination: true, sampled: true, ..ImageUsage::none() };
+        let image = StorageImage::with_usage(device.clone(), Dimensions::Dim2d { width: size, height: size }, R8G8B8A8Srgb,
+            usage, Some(queue.family())).map_err(image_error)?;
+        let command_buffer = AutoCommandBufferBuilder::new(device.clone(), queue.family())
+            .map_err(|e| command_error(&e))?
+            .clear_color_image(image.clone(), ClearValue::Float([0.0; 4]))
+            .map_err(|e| command_error(&e))?
+            .build().map_err(|e| command_error(&e))?;
+        let future = command_buffer.execute(queue.clone()).map_err(|e| command_error(&e))?;
+        future.flush().map_err(|source| UploadError::Submit { path: "atlas".to_string(), source })?;
+        registry.pending_uploads.push(Box::new(future));
+        Ok(Self { size, padding, image, queue, shelves: Vec::new(), regions: HashMap::new() })
+    }
+
+    /// The atlas image, for binding it.
+    pub fn image(&self) -> &Arc<StorageImage<R8G8B8A8Srgb>> {
+        &self.image
+    }
+
+    /// Copies a texture into the atlas, returning where it went. Textures already in the atlas
+    /// aren't copied again unless their asset changed since.
+    pub fn insert(&mut self, registry: &AssetRegistry, path: &str) -> Result<UvRect, UploadError> {
+        let relative_path = registry.resolve(path);
+        let asset = match registry.get_asset(&relative_path) {
+            Some(asset) => asset,
+            None => return Err(UploadError::NotFound(relative_path.into()))
+        };
+        let version = registry.versions.get(&asset.uid).copied().unwrap_or(0);
+        let (pixels, size) = match &asset.data {
+            AssetData::Texture(tex_data) => {
+                if let Some(region) = self.regions.get(&asset.uid).filter(|region| region.version == version) {
+                    return Ok(self.uv_rect(region));
+                }
+                (registry.decode_pixels(&relative_path, tex_data)?, tex_data.settings.max_ingame_size)
+            }
+        };
+        if pixels.len() != size[0] as usize * size[1] as usize * 4 {
+            return Err(UploadError::SizeMismatch(relative_path.into()));
+        }
+        // changed textures keep their region if they still fit it
+        let offset = match self.regions.get(&asset.uid) {
+            Some(region) if region.size[0] >= size[0] && region.size[1] >= size[1] => region.offset,
+            _ => {
+                self.remove(asset.uid);
+                match self.allocate(size) {
+                    Some((shelf, offset)) => {
+                        self.regions.insert(asset.uid, Region { shelf, offset, size, version });
+                        offset
+                    },
+                    None => return Err(UploadError::AtlasFull(relative_path.into()))
+                }
+            }
+        };
+        self.upload(registry, &pixels, offset, size, relative_path.as_str())?;
+        let region = self.regions.get_mut(&asset.uid).unwrap();
+        region.size = size;
+        region.version = version;
+        Ok(self.uv_rect(&self.regions[&asset.uid]))
+    }
+
+    /// Where a texture is in the atlas, if it's in it.
+    pub fn get(&self, uid: AssetId) -> Option<UvRect> {
+        self.regions.get(&uid).map(|region| self.uv_rect(region))
+    }
+
+    /// Frees a texture's region. Returns whether it was in the atlas.
+    pub fn remove(&mut self, uid: AssetId) -> bool {
+        let region = match self.regions.remove(&uid) {
+            Some(region) => region,
+            None => return false
+        };
+        let shelf = &mut self.shelves[region.shelf];
+        shelf.regions -= 1;
+        if shelf.regions == 0 {
+            shelf.x = 0;
+        }
+        true
+    }
+
+    /// Frees every region. The image keeps its old pixels until they're overwritten.
+    pub fn clear(&mut self) {
+        self.regions.clear();
+        self.shelves.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    fn uv_rect(&self, region: &Region) -> UvRect {
+        let size = self.size as f32;
+        UvRect {
+            min: [region.offset[0] as f32 / size, region.offset[1] as f32 / size],
+            max: [(region.offset[0] + region.size[0]) as f32 / size, (region.offset[1] + region.size[1]) as f32 / size],
+        }
+    }
+
+    /// Finds room for a region, on the shelf it wastes the least height on, else on a new one.
+    fn allocate(&mut self, size: [u32; 2]) -> Option<(usize, [u32; 2])> {
+        let [width, height] = [size[0] + self.padding * 2, size[1] + self.padding * 2];
+        let atlas_size = self.size;
+        let best = self.shelves.iter().enumerate()
+            .filter(|(_, shelf)| shelf.height >= height && shelf.x + width <= atlas_size)
+            .min_by_key(|(_, shelf)| shelf.height - height)
+            .map(|(i, _)| i);
+        let index = match best {
+            Some(index) => index,
+            None => {
+                let y = self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height);
+                if y + height > atlas_size || width > atlas_size {
+                    return None;
+                }
+                self.shelves.push(Shelf { y, height, x: 0, regions: 0 });
+                self.shelves.len() - 1
+            }
+        };
+        let shelf = &mut self.shelves[index];
+        let offset = [shelf.x + self.padding, shelf.y + self.padding];
+        shelf.x += width;
+        shelf.regions += 1;
+        Some((index, offset))
+    }
+
+    fn upload(&self, registry: &AssetRegistry, pixels: &[u8], offset: [u32; 2], size: [u32; 2], path: &str) -> Result<(), UploadError> {
+        let command_error = |reason: &dyn std::fmt::Display| UploadError::Commands { path: path.to_string(), reason: reason.to_string() };
+        let device = self.queue.device();
+        let buffer = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::transfer_source(), pixels.iter().cloned())
+            .map_err(|e| UploadError::ImageCreation { path: path.to_string(), source: e.into() })?;
+        let command_buffer = AutoCommandBufferBuilder::new(device.clone(), self.queue.family())
+            .map_err(|e| command_error(&e))?
+            .copy_buffer_to_image_dimensions(buffer, self.image.clone(), [offset[0], offset[1], 0], [size[0], size[1], 1], 0, 1, 0)
+            .map_err(|e| command_error(&e))?
+            .build().map_err(|e| command_error(&e))?;
+        let future = command_buffer.execute(self.queue.clone()).map_err(|e| command_error(&e))?;
+        future.flush().map_err(|source| UploadError::Submit { path: path.to_string(), source })?;
+        registry.pending_uploads.push(Box::new(future));
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for DynamicAtlas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynamicAtlas").field("size", &self.size).field("regions", &self.regions.len()).finish()
+    }
+}
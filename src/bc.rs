@@ -0,0 +1,214 @@
+//! Block compression of RGBA8 pixels into BC1 (DXT1) and BC3 (DXT5), done when cooking so the
+//! blocks can be uploaded as they are. Each 4x4 block of texels gets two endpoint colors and picks
+//! one of the colors between them per texel, with the endpoints fit along the block's principal
+//! axis. Blocks past the edge of a texture repeat its last row and column.
+
+use crate::mips::{compressed_size, mip_count, mip_size};
+
+
+/// Bytes in a BC1 block.
+pub const BC1_BLOCK_BYTES: usize = 8;
+/// Bytes in a BC3 block, an alpha block followed by a BC1 color block.
+pub const BC3_BLOCK_BYTES: usize = 16;
+
+/// How texels are stored in a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// BC1 colors without alpha.
+    Bc1,
+    /// BC1 with texels below half alpha stored as transparent black.
+    Bc1Cutout,
+    /// BC3, with alpha interpolated separately from the colors.
+    Bc3,
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc1Cutout => BC1_BLOCK_BYTES,
+            BlockFormat::Bc3 => BC3_BLOCK_BYTES,
+        }
+    }
+}
+
+/// Compresses a mip chain as `mips::generate_mips` lays it out, level by level, to the block data
+/// `upload_blocks` takes. `None` if `chain` doesn't match `size`.
+pub fn compress_mips(chain: &[u8], size: [u32; 2], format: BlockFormat) -> Option<Vec<u8>> {
+    let levels = mip_count(size);
+    let total = (0..levels).map(|mip| compressed_size(mip_size(size, mip), format.block_bytes())).sum();
+    let mut blocks = Vec::with_capacity(total);
+    let mut offset = 0;
+    for mip in 0..levels {
+        let level_size = mip_size(size, mip);
+        let length = (level_size[0] * level_size[1]) as usize * 4;
+        blocks.extend(compress(chain.get(offset..offset + length)?, level_size, format)?);
+        offset += length;
+    }
+    match offset == chain.len() {
+        true => Some(blocks),
+        false => None
+    }
+}
+
+/// Compresses one level of tightly packed RGBA8 pixels. `None` if `pixels` doesn't match `size`.
+pub fn compress(pixels: &[u8], size: [u32; 2], format: BlockFormat) -> Option<Vec<u8>> {
+    let [width, height] = size;
+    if pixels.len() != (width * height) as usize * 4 || width == 0 || height == 0 {
+        return None;
+    }
+    let mut blocks = Vec::with_capacity(compressed_size(size, format.block_bytes()));
+    for block_y in 0..height.div_ceil(4) {
+        for block_x in 0..width.div_ceil(4) {
+            let mut texels = [[0u8; 4]; 16];
+            for (i, texel) in texels.iter_mut().enumerate() {
+                let x = (block_x * 4 + i as u32 % 4).min(width - 1);
+                let y = (block_y * 4 + i as u32 / 4).min(height - 1);
+                let start = ((y * width + x) * 4) as usize;
+                texel.copy_from_slice(&pixels[start..start + 4]);
+            }
+            match format {
+                BlockFormat::Bc1 => blocks.extend_from_slice(&color_block(&texels, false)),
+                BlockFormat::Bc1Cutout => blocks.extend_from_slice(&color_block(&texels, true)),
+                BlockFormat::Bc3 => {
+                    blocks.extend_from_slice(&alpha_block(&texels));
+                    blocks.extend_from_slice(&color_block(&texels, false));
+                }
+            }
+        }
+    }
+    Some(blocks)
+}
+
+/// A BC1 block. With `cutout`, blocks with texels below half alpha use the three color mode, where
+/// the last index is transparent black.
+fn color_block(texels: &[[u8; 4]; 16], cutout: bool) -> [u8; 8] {
+    let transparent: Vec<bool> = texels.iter().map(|texel| cutout && texel[3] < 128).collect();
+    let opaque: Vec<[f32; 3]> = texels.iter().zip(transparent.iter())
+        .filter(|(_, transparent)| !**transparent)
+        .map(|(texel, _)| [texel[0] as f32, texel[1] as f32, texel[2] as f32])
+        .collect();
+    let three_color = transparent.contains(&true);
+    let (mut color0, mut color1) = match opaque.is_empty() {
+        true => (0, 0),
+        false => {
+            let (low, high) = fit_endpoints(&opaque);
+            (pack_565(high), pack_565(low))
+        }
+    };
+    // the order of the endpoints picks the mode, so they're swapped to match
+    match three_color {
+        true if color0 > color1 => std::mem::swap(&mut color0, &mut color1),
+        false if color0 < color1 => std::mem::swap(&mut color0, &mut color1),
+        _ => {}
+    }
+    let (end0, end1) = (unpack_565(color0), unpack_565(color1));
+    let palette: Vec<[f32; 3]> = match three_color {
+        true => vec![end0, end1, lerp3(end0, end1, 1.0 / 2.0)],
+        false => vec![end0, end1, lerp3(end0, end1, 1.0 / 3.0), lerp3(end0, end1, 2.0 / 3.0)],
+    };
+    let mut indices = 0u32;
+    for (i, texel) in texels.iter().enumerate() {
+        let index = match (transparent[i], color0 == color1 && !three_color) {
+            (true, _) => 3,
+            // both endpoints are the same color, the palette has nothing else to offer
+            (false, true) => 0,
+            (false, false) => nearest(&palette, |entry| distance3(entry, [texel[0] as f32, texel[1] as f32, texel[2] as f32])),
+        };
+        indices |= (index as u32) << (i * 2);
+    }
+    let mut block = [0u8; 8];
+    block[0..2].copy_from_slice(&color0.to_le_bytes());
+    block[2..4].copy_from_slice(&color1.to_le_bytes());
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+/// A BC3 alpha block, using the eight value mode between the block's lowest and highest alpha.
+fn alpha_block(texels: &[[u8; 4]; 16]) -> [u8; 8] {
+    let alpha0 = texels.iter().map(|texel| texel[3]).max().unwrap_or(255);
+    let alpha1 = texels.iter().map(|texel| texel[3]).min().unwrap_or(255);
+    let mut palette = [alpha0 as f32, alpha1 as f32, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    for (i, value) in palette.iter_mut().enumerate().skip(2) {
+        let weight = (i - 1) as f32 / 7.0;
+        *value = (alpha0 as f32 * (1.0 - weight) + alpha1 as f32 * weight).round();
+    }
+    let mut indices = 0u64;
+    if alpha0 != alpha1 {
+        for (i, texel) in texels.iter().enumerate() {
+            let index = nearest(&palette, |entry| (entry - texel[3] as f32).abs());
+            indices |= (index as u64) << (i * 3);
+        }
+    }
+    let mut block = [0u8; 8];
+    block[0] = alpha0;
+    block[1] = alpha1;
+    block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+/// The lowest and highest colors along the principal axis of `colors`, found by power iteration on
+/// their covariance.
+fn fit_endpoints(colors: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    let count = colors.len() as f32;
+    let mut mean = [0.0; 3];
+    for color in colors {
+        for c in 0..3 {
+            mean[c] += color[c] / count;
+        }
+    }
+    let mut covariance = [[0.0f32; 3]; 3];
+    for color in colors {
+        let offset = [color[0] - mean[0], color[1] - mean[1], color[2] - mean[2]];
+        for row in 0..3 {
+            for column in 0..3 {
+                covariance[row][column] += offset[row] * offset[column];
+            }
+        }
+    }
+    let mut axis = [1.0f32, 1.0, 1.0];
+    for _ in 0..8 {
+        let next = [0, 1, 2].map(|row| (0..3).map(|column| covariance[row][column] * axis[column]).sum::<f32>());
+        let length = next.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if length < f32::EPSILON {
+            break;
+        }
+        axis = next.map(|value| value / length);
+    }
+    let project = |color: &[f32; 3]| (0..3).map(|c| (color[c] - mean[c]) * axis[c]).sum::<f32>();
+    let (low, high) = colors.iter().map(project).fold((f32::MAX, f32::MIN), |(low, high), t| (low.min(t), high.max(t)));
+    let along = |t: f32| [0, 1, 2].map(|c| (mean[c] + axis[c] * t).clamp(0.0, 255.0));
+    (along(low), along(high))
+}
+
+fn pack_565(color: [f32; 3]) -> u16 {
+    let red = (color[0] * 31.0 / 255.0).round() as u16;
+    let green = (color[1] * 63.0 / 255.0).round() as u16;
+    let blue = (color[2] * 31.0 / 255.0).round() as u16;
+    (red << 11) | (green << 5) | blue
+}
+
+fn unpack_565(color: u16) -> [f32; 3] {
+    let red = (color >> 11) & 31;
+    let green = (color >> 5) & 63;
+    let blue = color & 31;
+    [red as f32 * 255.0 / 31.0, green as f32 * 255.0 / 63.0, blue as f32 * 255.0 / 31.0]
+}
+
+fn lerp3(from: [f32; 3], to: [f32; 3], weight: f32) -> [f32; 3] {
+    [0, 1, 2].map(|c| from[c] + (to[c] - from[c]) * weight)
+}
+
+fn distance3(a: &[f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum()
+}
+
+/// Index of the entry of `palette` with the smallest `distance`.
+fn nearest<T>(palette: &[T], distance: impl Fn(&T) -> f32) -> usize {
+    palette.iter().enumerate()
+        .map(|(index, entry)| (index, distance(entry)))
+        .fold((0, f32::MAX), |best, (index, distance)| match distance < best.1 {
+            true => (index, distance),
+            false => best
+        })
+        .0
+}
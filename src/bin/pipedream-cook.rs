@@ -0,0 +1,247 @@
+//! Scans a content directory and cooks it into a pack, without a GPU. Prints a JSON report to
+//! stdout and exits with 1 if any asset failed, or 2 if the cook couldn't run at all.
+//!
+//! With `--watch` it keeps running afterwards, cooking changed assets into the derived cache and
+//! printing a report line for each round of changes.
+
+use std::convert::TryFrom;
+use std::process::exit;
+use std::time::Duration;
+use serde_json::json;
+
+use pipedream::{RegistryConfig, AssetRegistryError, CookOptions, CookProfile, CookReport, PackKey, DerivedCache, RemoteCache, ScanReport, CancellationToken, GcOptions};
+use pipedream::pack::PackCompression;
+use pipedream::texture::TextureSize;
+
+
+const USAGE: &str = "\
+usage: pipedream-cook <content-dir> <output.pack> [options]
+       pipedream-cook <content-dir> [output.pack] --watch --derived-cache <dir> [options]
+
+options:
+    --database <path>       registry database to load and update, so unchanged files aren't reprocessed
+    --derived-cache <dir>   keep cooked data here, so unchanged assets aren't cooked again
+    --remote-cache <url>    share the derived cache through an HTTP server, see RemoteCache
+    --no-upload             only download from the remote cache, never upload to it
+    --gc                    after cooking, delete derived data the content no longer cooks to
+    --watch                 keep running and cook changes into the derived cache as they happen
+    --patch <previous.pack> only write assets that changed since a previous pack
+    --compression <codec>   none, deflate, lz4 or zstd (default zstd)
+    --platform <name>       platform to cook for, with its presets if it's pc or mobile (default pc)
+    --max-texture-size <n>  scale down textures bigger than n pixels, a power of two from 8 to 8192
+    --tiers <a,b,...>       quality tiers to cook variants for, from the sidecars' texture_overrides
+    --key <hex>             encrypt entries with a 32 byte key, given as 64 hex digits
+    --manifest <path>       write a JSON manifest of every cooked asset, for checking the packs later";
+
+struct Args {
+    content_dir: String,
+    output: Option<String>,
+    database: Option<String>,
+    manifest: Option<String>,
+    derived_cache: Option<String>,
+    remote_cache: Option<RemoteCache>,
+    patch: Option<String>,
+    watch: bool,
+    gc: bool,
+    options: CookOptions,
+}
+
+fn parse_compression(name: &str) -> Result<PackCompression, String> {
+    match name {
+        "none" => Ok(PackCompression::None),
+        "deflate" => Ok(PackCompression::Deflate),
+        "lz4" => Ok(PackCompression::Lz4),
+        "zstd" => Ok(PackCompression::Zstd),
+        _ => Err(format!("unknown compression '{}'", name))
+    }
+}
+
+fn parse_profile(platform: &str) -> CookProfile {
+    match platform {
+        "pc" => CookProfile::pc(),
+        "mobile" => CookProfile::mobile(),
+        _ => CookProfile::new(platform)
+    }
+}
+
+fn parse_texture_size(pixels: &str) -> Result<TextureSize, String> {
+    pixels.parse().ok()
+        .and_then(TextureSize::from_pixels)
+        .ok_or_else(|| format!("invalid texture size '{}'", pixels))
+}
+
+fn parse_key(hex: &str) -> Result<PackKey, String> {
+    let mut key = [0u8; 32];
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("key must be 64 hex digits".to_string());
+    }
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| "key must be 64 hex digits".to_string())?;
+    }
+    Ok(PackKey::new(key))
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut database = None;
+    let mut manifest = None;
+    let mut derived_cache = None;
+    let mut remote_cache = None;
+    let mut upload = true;
+    let mut patch = None;
+    let mut watch = false;
+    let mut gc = false;
+    let mut max_texture_size = None;
+    let mut tiers = Vec::new();
+    let mut options = CookOptions::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--database" => database = Some(value()?),
+            "--manifest" => manifest = Some(value()?),
+            "--derived-cache" => derived_cache = Some(value()?),
+            "--remote-cache" => remote_cache = Some(value()?),
+            "--no-upload" => upload = false,
+            "--patch" => patch = Some(value()?),
+            "--watch" => watch = true,
+            "--gc" => gc = true,
+            "--compression" => options.compression = parse_compression(&value()?)?,
+            "--key" => options.encryption = Some(parse_key(&value()?)?),
+            "--platform" => options.profile = parse_profile(&value()?),
+            "--max-texture-size" => max_texture_size = Some(parse_texture_size(&value()?)?),
+            "--tiers" => tiers = value()?.split(',').filter(|tier| !tier.is_empty()).map(str::to_string).collect(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            },
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    // applied last so they override the platform's preset regardless of argument order
+    if max_texture_size.is_some() {
+        options.profile.max_texture_size = max_texture_size;
+    }
+    options.profile.tiers = tiers;
+    if watch && derived_cache.is_none() {
+        return Err("--watch needs a --derived-cache to cook into".to_string());
+    }
+    if remote_cache.is_some() && derived_cache.is_none() {
+        return Err("--remote-cache needs a --derived-cache to keep downloads in".to_string());
+    }
+    if gc && derived_cache.is_none() {
+        return Err("--gc needs a --derived-cache to collect".to_string());
+    }
+    let remote_cache = remote_cache.map(|url| RemoteCache::new(&url).with_upload(upload));
+    let (content_dir, output) = match <[String; 2]>::try_from(positional) {
+        Ok([content_dir, output]) => (content_dir, Some(output)),
+        Err(positional) if watch && positional.len() == 1 => (positional[0].clone(), None),
+        Err(_) => return Err("expected a content directory and an output path".to_string())
+    };
+    if manifest.is_some() && output.is_none() {
+        return Err("--manifest needs an output pack".to_string());
+    }
+    Ok(Args { content_dir, output, database, manifest, derived_cache, remote_cache, patch, watch, gc, options })
+}
+
+fn failures<'a>(scan: &'a ScanReport, cook: &'a CookReport) -> Vec<serde_json::Value> {
+    scan.failed.iter().chain(&cook.failed)
+        .map(|e| json!({ "path": e.path(), "error": e.to_string() }))
+        .collect()
+}
+
+/// Warnings from both, tagged with where they came from. Checks that need decoded pixels only run
+/// when an asset is cooked, not when it's served from the derived cache.
+fn warnings<'a>(scan: &'a ScanReport, cook: &'a CookReport) -> Vec<serde_json::Value> {
+    let scan = scan.warnings.iter().map(|w| ("scan", w));
+    let cook = cook.warnings.iter().map(|w| ("cook", w));
+    scan.chain(cook)
+        .map(|(stage, w)| json!({ "path": w.path(), "warning": w.to_string(), "stage": stage }))
+        .collect()
+}
+
+fn run(args: &Args) -> Result<bool, AssetRegistryError> {
+    let mut config = RegistryConfig::new(&args.content_dir);
+    if let Some(database) = &args.database {
+        config = config.with_database(database);
+    }
+    let mut registry = config.build()?;
+    registry.derived_cache = args.derived_cache.as_ref().map(|dir| {
+        let cache = DerivedCache::new(dir.as_ref());
+        match &args.remote_cache {
+            Some(remote) => cache.with_remote(remote.clone()),
+            None => cache
+        }
+    });
+    let scan = registry.rescan()?;
+    registry.save_database()?;
+    let cook = match (&args.output, &args.patch) {
+        (Some(output), Some(previous)) => registry.cook_patch(output, previous, &args.options)?,
+        (Some(output), None) => registry.cook(output, &args.options)?,
+        (None, _) => registry.fill_derived_cache(&args.options.profile)?,
+    };
+    if let Some(manifest) = &args.manifest {
+        cook.manifest.write(manifest)?;
+    }
+    let gc = match args.gc {
+        true => Some(registry.collect_derived_garbage(std::slice::from_ref(&args.options.profile), &GcOptions::default())?),
+        false => None
+    };
+    let failed = failures(&scan, &cook);
+    let report = json!({
+        "scan": {
+            "added": scan.added.len(),
+            "modified": scan.modified.len(),
+            "removed": scan.removed.len(),
+            "moved": scan.moved.len(),
+        },
+        "cook": {
+            "output": args.output,
+            "platform": args.options.profile.platform,
+            "entries": cook.entries,
+            "unchanged": cook.unchanged,
+            "removed": cook.removed,
+            "bytes_written": cook.bytes_written,
+        },
+        "gc": gc.map(|gc| json!({ "removed": gc.removed.len(), "bytes_freed": gc.bytes_freed })),
+        "failed": failed,
+        "warnings": warnings(&scan, &cook),
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    if args.watch {
+        registry.watch_and_cook(&args.options.profile, &CancellationToken::new(), Duration::from_millis(250), &mut |scan, cook| {
+            let update = json!({
+                "scan": {
+                    "added": scan.added.len(),
+                    "modified": scan.modified.len(),
+                    "removed": scan.removed.len(),
+                    "moved": scan.moved.len(),
+                },
+                "cooked": cook.entries,
+                "failed": failures(scan, cook),
+                "warnings": warnings(scan, cook),
+            });
+            println!("{}", update);
+        })?;
+    }
+    Ok(failed.is_empty())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+    match run(&args) {
+        Ok(true) => {},
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    }
+}
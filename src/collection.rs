@@ -0,0 +1,47 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::error::AssetRegistryError;
+use crate::archive;
+
+
+pub const COLLECTION_EXTENSION: &str = "collection";
+
+
+/// A named group of assets that are loaded or cooked together, e.g. everything a level needs.
+/// Collections are defined through `AssetRegistry::define_collection`, or by a JSON file named
+/// `<name>.collection` anywhere in the content tree, which is picked up by scans.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AssetCollection {
+    /// Asset paths relative to the content root.
+    #[serde(default)]
+    pub assets: Vec<String>,
+    /// Glob patterns, as accepted by `AssetRegistry::find`.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Every asset carrying one of these tags is included.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The collection file this was read from, relative to the content root. `None` for
+    /// collections defined through the API.
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl AssetCollection {
+    pub fn read(path: &Path) -> Result<Self, AssetRegistryError> {
+        let bytes = archive::read(path).map_err(|e| AssetRegistryError::io(path.display(), e))?;
+        match serde_json::from_slice(&bytes) {
+            Ok(collection) => Ok(collection),
+            Err(source) => Err(AssetRegistryError::Sidecar { path: path.display().to_string(), source })
+        }
+    }
+
+    /// The collection name for a collection file, or `None` if `path` isn't one.
+    pub fn name_for(path: &Path) -> Option<String> {
+        match path.extension() {
+            Some(ext) if ext == COLLECTION_EXTENSION => path.file_stem().map(|s| s.to_string_lossy().to_string()),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Color space conversions, so everything that touches pixels agrees on what they mean. 8-bit
+//! pixels are sRGB encoded unless a texture's `srgb` setting says otherwise, and anything that
+//! filters or blends them does so on linear values.
+
+use image::{ImageBuffer, Rgba, FilterType};
+use serde::{Serialize, Deserialize};
+
+use crate::dither::{DitherMode, encode_rgba8};
+
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    }
+    else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    }
+    else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Applies a display gamma to a normalized value. Gammas above 1 brighten midtones.
+pub fn apply_gamma(value: f32, gamma: f32) -> f32 {
+    value.max(0.0).powf(1.0 / gamma)
+}
+
+/// The red, green and blue primaries RGB values are relative to. Textures are uploaded as
+/// Rec.709, which sRGB shares its primaries with, so other sources are converted on import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorPrimaries {
+    #[default]
+    Rec709,
+    Rec2020,
+}
+
+impl ColorPrimaries {
+    /// Weights of the linear red, green and blue channels in relative luminance.
+    pub fn luma_weights(self) -> [f32; 3] {
+        match self {
+            ColorPrimaries::Rec709 => [0.2126, 0.7152, 0.0722],
+            ColorPrimaries::Rec2020 => [0.2627, 0.6780, 0.0593],
+        }
+    }
+
+    /// Matrix taking linear RGB with these primaries to `target`'s, or `None` if they're the same.
+    pub fn conversion_to(self, target: ColorPrimaries) -> Option<[[f32; 3]; 3]> {
+        match (self, target) {
+            (ColorPrimaries::Rec709, ColorPrimaries::Rec2020) => Some([
+                [0.6274, 0.3293, 0.0433],
+                [0.0691, 0.9195, 0.0114],
+                [0.0164, 0.0880, 0.8956],
+            ]),
+            (ColorPrimaries::Rec2020, ColorPrimaries::Rec709) => Some([
+                [1.6605, -0.5876, -0.0728],
+                [-0.1246, 1.1329, -0.0083],
+                [-0.0182, -0.1006, 1.1187],
+            ]),
+            _ => None
+        }
+    }
+}
+
+/// Tightly packed RGBA8 pixels as normalized linear values. Alpha is always linear.
+pub fn to_linear(pixels: &[u8], srgb: bool) -> Vec<f32> {
+    let mut table = [0.0f32; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        *value = i as f32 / 255.0;
+        if srgb {
+            *value = srgb_to_linear(*value);
+        }
+    }
+    pixels.chunks_exact(4)
+        .flat_map(|p| [table[p[0] as usize], table[p[1] as usize], table[p[2] as usize], p[3] as f32 / 255.0])
+        .collect()
+}
+
+/// The inverse of `to_linear`, clamping values outside 0 to 1.
+pub fn from_linear(values: &[f32], srgb: bool) -> Vec<u8> {
+    let encode = |value: f32| {
+        let value = value.clamp(0.0, 1.0);
+        let value = if srgb { linear_to_srgb(value) } else { value };
+        (value * 255.0).round() as u8
+    };
+    values.chunks_exact(4)
+        .flat_map(|p| [encode(p[0]), encode(p[1]), encode(p[2]), (p[3].clamp(0.0, 1.0) * 255.0).round() as u8])
+        .collect()
+}
+
+/// Converts tightly packed RGBA8 pixels from one set of primaries to another in place.
+pub fn convert_primaries(pixels: &mut [u8], from: ColorPrimaries, to: ColorPrimaries, srgb: bool) {
+    let matrix = match from.conversion_to(to) {
+        Some(matrix) => matrix,
+        None => return
+    };
+    let mut values = to_linear(pixels, srgb);
+    for p in values.chunks_exact_mut(4) {
+        let rgb = [p[0], p[1], p[2]];
+        for (c, row) in matrix.iter().enumerate() {
+            p[c] = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+        }
+    }
+    pixels.copy_from_slice(&from_linear(&values, srgb));
+}
+
+/// Resizes tightly packed RGBA8 pixels, filtering in linear space so sRGB images don't darken, and
+/// dithering the result back to 8 bits. `None` if `pixels` doesn't match `size`.
+pub fn resize_rgba8(pixels: &[u8], size: [u32; 2], new_size: [u32; 2], srgb: bool, dither: DitherMode) -> Option<Vec<u8>> {
+    // 16 bits per channel, so dark sRGB values survive the round trip through linear
+    let linear: Vec<u16> = to_linear(pixels, srgb).into_iter().map(|v| (v * 65535.0).round() as u16).collect();
+    let image = ImageBuffer::<Rgba<u16>, _>::from_raw(size[0], size[1], linear)?;
+    let resized = image::imageops::resize(&image, new_size[0], new_size[1], FilterType::Triangle);
+    let values: Vec<f32> = resized.into_raw().into_iter().map(|v| v as f32 / 65535.0).collect();
+    Some(encode_rgba8(&values, new_size[0], srgb, dither))
+}
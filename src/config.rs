@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use hashbrown::HashSet;
+use vulkano::device::Queue;
+
+use crate::derived::{DerivedCache, RemoteCache};
+use crate::error::AssetRegistryError;
+use crate::registry::{AssetRegistry, ChangeDetection};
+use crate::scan::SymlinkMode;
+use crate::thumbnail::ThumbnailCache;
+
+
+/// Everything an `AssetRegistry` is set up with. Settings are checked when the registry is built,
+/// so a bad one fails there instead of partway through the first scan.
+#[derive(Debug, Clone)]
+pub struct RegistryConfig {
+    pub base_path: String,
+    /// Used to recognize absolute paths passed to lookups. Defaults to the canonical form of
+    /// `base_path`.
+    pub absolute_path: Option<String>,
+    /// `None` for a headless registry.
+    pub queue: Option<Arc<Queue>>,
+    /// See `AssetRegistry::transfer_queues`.
+    pub transfer_queues: Vec<Arc<Queue>>,
+    pub database_path: Option<String>,
+    /// How deep below a mount's root scans import files, where 1 is only files directly in the
+    /// root. `None` for no limit.
+    pub max_depth: Option<usize>,
+    pub symlinks: SymlinkMode,
+    pub ignore_patterns: Vec<String>,
+    /// File extensions to import, lowercase and without the dot. `None` imports everything an
+    /// importer supports.
+    pub extensions: Option<Vec<String>>,
+    pub change_detection: ChangeDetection,
+    /// Where derived data and thumbnails are cached, in `derived` and `thumbnails` below it.
+    pub cache_directory: Option<PathBuf>,
+    /// Shared derived cache behind the local one, see `RemoteCache`. Needs a `cache_directory`.
+    pub remote_cache: Option<RemoteCache>,
+    /// Worker threads for imports. `None` uses rayon's global pool.
+    pub import_threads: Option<usize>,
+}
+
+impl RegistryConfig {
+    pub fn new(base_path: &str) -> Self {
+        Self {
+            base_path: base_path.to_string(),
+            absolute_path: None,
+            queue: None,
+            transfer_queues: Vec::new(),
+            database_path: None,
+            max_depth: None,
+            symlinks: SymlinkMode::default(),
+            ignore_patterns: Vec::new(),
+            extensions: None,
+            change_detection: ChangeDetection::Timestamp,
+            cache_directory: None,
+            remote_cache: None,
+            import_threads: None,
+        }
+    }
+
+    pub fn with_absolute_path(mut self, absolute_path: &str) -> Self {
+        self.absolute_path = Some(absolute_path.to_string());
+        self
+    }
+
+    pub fn with_queue(mut self, queue: Arc<Queue>) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Spreads texture uploads over `queue` too, see `AssetRegistry::transfer_queues`.
+    pub fn with_transfer_queue(mut self, queue: Arc<Queue>) -> Self {
+        self.transfer_queues.push(queue);
+        self
+    }
+
+    /// Backs the registry with a database file, see `AssetRegistry::open`.
+    pub fn with_database(mut self, database_path: &str) -> Self {
+        self.database_path = Some(database_path.to_string());
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn with_symlinks(mut self, symlinks: SymlinkMode) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    pub fn with_ignore_pattern(mut self, pattern: &str) -> Self {
+        self.ignore_patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = Some(extensions.iter().map(|e| e.to_string()).collect());
+        self
+    }
+
+    pub fn with_change_detection(mut self, change_detection: ChangeDetection) -> Self {
+        self.change_detection = change_detection;
+        self
+    }
+
+    pub fn with_cache_directory(mut self, cache_directory: &Path) -> Self {
+        self.cache_directory = Some(cache_directory.to_path_buf());
+        self
+    }
+
+    pub fn with_remote_cache(mut self, remote: RemoteCache) -> Self {
+        self.remote_cache = Some(remote);
+        self
+    }
+
+    pub fn with_import_threads(mut self, threads: usize) -> Self {
+        self.import_threads = Some(threads);
+        self
+    }
+
+    /// Checks the settings and creates the registry, loading its database if it has one.
+    pub fn build(self) -> Result<AssetRegistry, AssetRegistryError> {
+        let invalid = |reason: String| Err(AssetRegistryError::InvalidConfig(reason));
+        if self.max_depth == Some(0) {
+            return invalid("max_depth must be at least 1".to_string());
+        }
+        if self.import_threads == Some(0) {
+            return invalid("import_threads must be at least 1".to_string());
+        }
+        if !self.transfer_queues.is_empty() {
+            let queue = match &self.queue {
+                Some(queue) => queue,
+                None => return invalid("transfer queues need a queue to upload for".to_string())
+            };
+            if self.transfer_queues.iter().any(|transfer| !Arc::ptr_eq(transfer.device(), queue.device())) {
+                return invalid("transfer queues have to be on the same device as the queue".to_string());
+            }
+            if self.transfer_queues.iter().any(|transfer| transfer.is_same(queue)) {
+                return invalid("the queue can't also be a transfer queue".to_string());
+            }
+        }
+        if let Some(remote) = &self.remote_cache {
+            if self.cache_directory.is_none() {
+                return invalid("a remote cache needs a cache_directory to keep its entries in".to_string());
+            }
+            if !remote.url.starts_with("http://") && !remote.url.starts_with("https://") {
+                return invalid(format!("remote cache URL '{}' isn't http:// or https://", remote.url));
+            }
+        }
+        let extensions: Option<HashSet<String>> = match &self.extensions {
+            Some(extensions) => {
+                if let Some(bad) = extensions.iter().find(|e| e.is_empty() || e.contains('.') || e.contains('/')) {
+                    return invalid(format!("'{}' is not a file extension, expected e.g. \"png\"", bad));
+                }
+                Some(extensions.iter().map(|e| e.to_lowercase()).collect())
+            }
+            None => None
+        };
+        let absolute_path = match &self.absolute_path {
+            Some(path) => path.clone(),
+            None => match std::fs::canonicalize(&self.base_path) {
+                Ok(path) => path.to_string_lossy().to_string(),
+                Err(_) => return Err(AssetRegistryError::PathDoesNotExist(self.base_path.clone()))
+            }
+        };
+
+        let mut registry = AssetRegistry::with_queue(&self.base_path, &absolute_path, self.queue)?;
+        registry.transfer_queues = self.transfer_queues;
+        registry.max_depth = self.max_depth;
+        registry.symlinks = self.symlinks;
+        registry.extensions = extensions;
+        registry.change_detection = self.change_detection;
+        for pattern in self.ignore_patterns.iter() {
+            registry.add_ignore_pattern(pattern)?;
+        }
+        if let Some(directory) = &self.cache_directory {
+            let derived_cache = DerivedCache::new(&directory.join("derived"));
+            registry.derived_cache = Some(match self.remote_cache {
+                Some(remote) => derived_cache.with_remote(remote),
+                None => derived_cache
+            });
+            registry.thumbnail_cache = Some(ThumbnailCache::new(&directory.join("thumbnails")));
+        }
+        registry.set_import_threads(self.import_threads)?;
+        match &self.database_path {
+            Some(database_path) => registry.with_database(database_path),
+            None => Ok(registry)
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use hashbrown::HashMap;
+use serde::{Serialize, Deserialize};
+
+use vulkano::format::Format;
+
+use crate::asset::{AssetType, TextureAssetData};
+use crate::bc::{BlockFormat, compress_mips};
+use crate::color::resize_rgba8;
+use crate::dither::{DitherMode, quantize_rgba8};
+use crate::error::ImportError;
+use crate::manifest::BuildManifest;
+use crate::mips::{generate_mips, mip_count};
+use crate::pack::{PackCompression, PackedData, PackKey};
+use crate::texture::{TextureMetadata, TextureSize, TextureOverride, CompressionMode};
+use crate::validate::ImportWarning;
+
+
+/// Version of the processing `CookProfile::apply_texture` does. Bump it whenever its output
+/// changes, e.g. the resize filter, so textures cooked by older versions get cooked again.
+pub const TEXTURE_PROCESSOR_VERSION: u32 = 3;
+
+/// Version of the processor that cooks assets of a type. Derived data is stamped with it.
+pub fn processor_version(asset_type: AssetType) -> u32 {
+    match asset_type {
+        AssetType::Texture => TEXTURE_PROCESSOR_VERSION,
+    }
+}
+
+/// How assets are processed for one target platform, so a single content tree can cook correctly
+/// for each of them. Cooked data is cached separately per profile.
+///
+/// Sidecars can override texture settings for a platform or quality tier by name. Overrides for
+/// the profile's platform apply to everything it cooks, and each tier gets its own variant of the
+/// assets that have overrides for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CookProfile {
+    pub platform: String,
+    /// Quality tiers to cook variants for, selectable at runtime with `PackedRegistry::set_tier`.
+    pub tiers: Vec<String>,
+    /// Replaces every texture's compression mode. `None` keeps the sidecar settings.
+    pub texture_compression: Option<CompressionMode>,
+    /// Textures bigger than this are scaled down to fit, keeping their aspect ratio.
+    pub max_texture_size: Option<TextureSize>,
+}
+
+impl CookProfile {
+    /// A profile that cooks everything as it's set up in the sidecars.
+    pub fn new(platform: &str) -> Self {
+        Self { platform: platform.to_string(), tiers: Vec::new(), texture_compression: None, max_texture_size: None }
+    }
+
+    pub fn pc() -> Self {
+        Self::new("pc")
+    }
+
+    /// Caps textures at 2048 pixels.
+    pub fn mobile() -> Self {
+        Self { max_texture_size: Some(TextureSize::_2048x2048), ..Self::new("mobile") }
+    }
+
+    /// The variants cooked for a texture: the base data, plus each tier it has overrides for.
+    pub fn texture_variants<'a>(&'a self, tex_data: &TextureAssetData) -> Vec<Option<&'a str>> {
+        let tiers = self.tiers.iter().filter(|tier| tex_data.overrides.contains_key(*tier));
+        std::iter::once(None).chain(tiers.map(|tier| Some(tier.as_str()))).collect()
+    }
+
+    /// The sidecar overrides that apply to a variant, in the order they're applied.
+    pub fn texture_overrides<'a>(&self, tex_data: &'a TextureAssetData, tier: Option<&str>) -> Vec<&'a TextureOverride> {
+        std::iter::once(self.platform.as_str()).chain(tier)
+            .filter_map(|name| tex_data.overrides.get(name))
+            .collect()
+    }
+
+    /// Applies the profile and `overrides` to a texture's settings and decoded pixels, then block
+    /// compresses it, see `process_texture` and `compress_texture`.
+    pub fn apply_texture(&self, path: &str, settings: &TextureMetadata, overrides: &[&TextureOverride], pixels: Vec<u8>) -> Result<(TextureMetadata, Vec<u8>), ImportError> {
+        let (settings, pixels) = self.process_texture(path, settings, overrides, pixels)?;
+        compress_texture(path, settings, pixels)
+    }
+
+    /// Applies the profile and `overrides` to a texture's settings and decoded pixels, scaling it
+    /// down to fit. Textures that dither and are block compressed have their colors quantized to
+    /// what the blocks store. Fails if the pixels don't match the settings.
+    pub fn process_texture(&self, path: &str, settings: &TextureMetadata, overrides: &[&TextureOverride], pixels: Vec<u8>) -> Result<(TextureMetadata, Vec<u8>), ImportError> {
+        let mut settings = settings.clone();
+        let compression = overrides.iter().rev()
+            .find_map(|o| o.compression_mode.as_ref())
+            .or(self.texture_compression.as_ref());
+        if let Some(mode) = compression {
+            settings.compression_mode = mode.clone();
+        }
+        let limit = overrides.iter().map(|o| &o.max_texture_size)
+            .chain([&self.max_texture_size, &settings.max_texture_size])
+            .flatten()
+            .map(|size| size.pixels())
+            .min();
+        let divisor: u32 = overrides.iter().filter_map(|o| o.downscale).map(|d| d.max(1)).product();
+        let [width, height] = settings.max_ingame_size;
+        let largest = width.max(height) as f64;
+        let scale = limit.map_or(1.0, |limit| (limit as f64 / largest).min(1.0)) / divisor as f64;
+        let scaled = [((width as f64 * scale) as u32).max(1), ((height as f64 * scale) as u32).max(1)];
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(ImportError::ChangedSize { path: path.to_string() });
+        }
+        let mut pixels = if scaled == [width, height] {
+            pixels
+        }
+        else {
+            match resize_rgba8(&pixels, [width, height], scaled, settings.srgb, settings.dithering) {
+                Some(pixels) => pixels,
+                None => return Err(ImportError::ChangedSize { path: path.to_string() })
+            }
+        };
+        settings.max_ingame_size = scaled;
+        settings.data_size = [scaled[0] * scaled[1] * 4, 0];
+        let block_bits = match settings.compression_mode {
+            CompressionMode::DXT1 => Some([5, 6, 5, 8]),
+            CompressionMode::DXT1Cutout => Some([5, 6, 5, 1]),
+            CompressionMode::DXT5 => Some([5, 6, 5, 8]),
+            CompressionMode::None | CompressionMode::ASTC4x4 => None,
+        };
+        if let (Some(bits), true) = (block_bits, settings.dithering != DitherMode::None) {
+            quantize_rgba8(&mut pixels, scaled[0], bits, settings.dithering);
+        }
+        Ok((settings, pixels))
+    }
+}
+
+impl Default for CookProfile {
+    fn default() -> Self {
+        Self::pc()
+    }
+}
+
+/// Block compresses a processed texture's pixels. Color textures set to DXT1 or DXT5 come out as
+/// BC1 or BC3 blocks with a full mip chain, which can't be generated after upload. ASTC has no
+/// upload path, so those stay uncompressed, as does everything else. Fails if the pixels don't
+/// match the settings.
+pub fn compress_texture(path: &str, mut settings: TextureMetadata, pixels: Vec<u8>) -> Result<(TextureMetadata, Vec<u8>), ImportError> {
+    let scaled = settings.max_ingame_size;
+    let block_format = match settings.compression_mode {
+        CompressionMode::DXT1 => Some(BlockFormat::Bc1),
+        CompressionMode::DXT1Cutout => Some(BlockFormat::Bc1Cutout),
+        CompressionMode::DXT5 => Some(BlockFormat::Bc3),
+        CompressionMode::None | CompressionMode::ASTC4x4 => None,
+    };
+    let is_color = matches!(settings.format, Format::R8G8B8A8Srgb | Format::R8G8B8A8Unorm);
+    let block_format = match block_format {
+        Some(block_format) if is_color => block_format,
+        _ => return Ok((settings, pixels))
+    };
+    let blocks = match generate_mips(&pixels, scaled, settings.srgb, &settings.mip_gen_settings).and_then(|chain| compress_mips(&chain, scaled, block_format)) {
+        Some(blocks) => blocks,
+        None => return Err(ImportError::ChangedSize { path: path.to_string() })
+    };
+    settings.format = match (block_format, settings.srgb) {
+        (BlockFormat::Bc1 | BlockFormat::Bc1Cutout, true) => Format::BC1_RGBASrgbBlock,
+        (BlockFormat::Bc1 | BlockFormat::Bc1Cutout, false) => Format::BC1_RGBAUnormBlock,
+        (BlockFormat::Bc3, true) => Format::BC3SrgbBlock,
+        (BlockFormat::Bc3, false) => Format::BC3UnormBlock,
+    };
+    settings.num_mips = mip_count(scaled);
+    settings.gpu_mips = false;
+    settings.data_size[1] = blocks.len() as u32;
+    Ok((settings, blocks))
+}
+
+
+/// Settings for `AssetRegistry::cook`.
+#[derive(Debug, Clone)]
+pub struct CookOptions {
+    /// Codec for any data worth compressing. Data that's already compressed is stored as-is.
+    pub compression: PackCompression,
+    /// Codecs for specific asset types, used instead of `compression` regardless of the data.
+    pub type_compression: HashMap<AssetType, PackCompression>,
+    /// Encrypts every entry with this key. The same key has to be given to `PackedRegistry`.
+    pub encryption: Option<PackKey>,
+    pub profile: CookProfile,
+}
+
+impl CookOptions {
+    /// LZ4 everywhere, for platforms where load times matter more than size.
+    pub fn fast_load() -> Self {
+        Self { compression: PackCompression::Lz4, ..Self::default() }
+    }
+
+    /// zstd everywhere, for builds that are downloaded.
+    pub fn small() -> Self {
+        Self { compression: PackCompression::Zstd, ..Self::default() }
+    }
+
+    /// The codec an entry is cooked with.
+    pub fn compression_for(&self, data: &PackedData) -> PackCompression {
+        match self.type_compression.get(&data.asset_type()) {
+            Some(compression) => *compression,
+            None if data.is_compressible() => self.compression,
+            None => PackCompression::None,
+        }
+    }
+}
+
+impl Default for CookOptions {
+    fn default() -> Self {
+        Self { compression: PackCompression::Zstd, type_compression: HashMap::new(), encryption: None, profile: CookProfile::default() }
+    }
+}
+
+/// What a cook wrote.
+#[derive(Debug, Default)]
+pub struct CookReport {
+    pub entries: usize,
+    /// For patch packs, assets left out because they match the previous pack.
+    pub unchanged: usize,
+    /// For patch packs, assets in the previous pack that no longer exist.
+    pub removed: usize,
+    /// Size of the pack file.
+    pub bytes_written: u64,
+    /// Assets that couldn't be processed and were left out of the pack.
+    pub failed: Vec<ImportError>,
+    /// Content standards that cooked assets don't meet, for the checks that need their pixels,
+    /// see `validate_pixels`. Reported once per asset, when its base variant is cooked. Assets
+    /// taken from the derived cache were checked when they were first cooked instead.
+    pub warnings: Vec<ImportWarning>,
+    /// Every asset in the build, including those a patch cook left in the previous pack.
+    pub manifest: BuildManifest,
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+use hashbrown::HashMap;
+use serde::{Serialize, Deserialize};
+use tracing::warn;
+
+use crate::asset::{Asset, AssetId, FileTreeNode};
+use crate::arena::Arena;
+use crate::error::AssetRegistryError;
+
+
+/// Bump this whenever the layout of anything stored in the database changes.
+/// Databases written with a different version are discarded and rebuilt by the next rescan.
+pub const DATABASE_VERSION: u32 = 18;
+
+
+// written and read back positionally by bincode, so these two must keep the same field order
+
+#[derive(Serialize)]
+struct RegistryDatabaseRef<'a> {
+    version: u32,
+    base_path: &'a str,
+    file_tree: &'a FileTreeNode,
+    assets: &'a Arena<Asset>,
+    uid_to_path: &'a HashMap<AssetId, Arc<str>>,
+    redirects: &'a HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegistryDatabase {
+    pub version: u32,
+    pub base_path: String,
+    pub file_tree: FileTreeNode,
+    pub assets: Arena<Asset>,
+    pub uid_to_path: HashMap<AssetId, String>,
+    pub redirects: HashMap<String, String>,
+}
+
+impl RegistryDatabase {
+    pub fn read(path: &str) -> Result<Option<Self>, AssetRegistryError> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let reader = BufReader::new(File::open(path).map_err(|e| AssetRegistryError::io(path, e))?);
+        let db: RegistryDatabase = match bincode::deserialize_from(reader) {
+            Ok(db) => db,
+            Err(source) => return Err(AssetRegistryError::Database { path: path.to_string(), source })
+        };
+        if db.version != DATABASE_VERSION {
+            warn!(path, version = db.version, expected = DATABASE_VERSION, "Discarding registry database with outdated version");
+            return Ok(None);
+        }
+        Ok(Some(db))
+    }
+
+    pub fn write(path: &str, base_path: &str, file_tree: &FileTreeNode, assets: &Arena<Asset>, uid_to_path: &HashMap<AssetId, Arc<str>>,
+                 redirects: &HashMap<String, String>) -> Result<(), AssetRegistryError>
+    {
+        let writer = BufWriter::new(File::create(path).map_err(|e| AssetRegistryError::io(path, e))?);
+        let result = bincode::serialize_into(writer, &RegistryDatabaseRef {
+            version: DATABASE_VERSION,
+            base_path,
+            file_tree,
+            assets,
+            uid_to_path,
+            redirects,
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err(source) => Err(AssetRegistryError::Database { path: path.to_string(), source })
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Engine default textures, registered with every registry under the reserved `builtin://` mount
+//! so renderers always have something safe to bind for unset material slots.
+
+use vulkano::format::Format;
+use vulkano::sampler::Filter;
+
+use crate::asset::{AssetData, TextureAssetData};
+use crate::texture::TextureMetadata;
+
+
+/// Reserved mount name. It can't be mounted, and assets can't be registered below it.
+pub const BUILTIN_MOUNT: &str = "builtin";
+
+pub const WHITE: &str = "builtin://textures/white";
+pub const BLACK: &str = "builtin://textures/black";
+pub const GRAY: &str = "builtin://textures/gray";
+/// Tangent space normal map pointing straight out of the surface.
+pub const FLAT_NORMAL: &str = "builtin://textures/flat_normal";
+/// Magenta and black checkerboard, for assets that are missing or failed to load.
+pub const CHECKER: &str = "builtin://textures/checker";
+
+const CHECKER_SIZE: u32 = 8;
+
+
+/// Every default texture with its path.
+pub fn default_textures() -> Vec<(&'static str, AssetData)> {
+    let mut checker = Vec::with_capacity((CHECKER_SIZE * CHECKER_SIZE * 4) as usize);
+    for y in 0..CHECKER_SIZE {
+        for x in 0..CHECKER_SIZE {
+            let on = (x < CHECKER_SIZE / 2) != (y < CHECKER_SIZE / 2);
+            checker.extend_from_slice(if on { &[255, 0, 255, 255] } else { &[0, 0, 0, 255] });
+        }
+    }
+    vec![
+        (WHITE, texture([1, 1], vec![255, 255, 255, 255], true)),
+        (BLACK, texture([1, 1], vec![0, 0, 0, 255], true)),
+        (GRAY, texture([1, 1], vec![128, 128, 128, 255], true)),
+        (FLAT_NORMAL, texture([1, 1], vec![128, 128, 255, 255], false)),
+        (CHECKER, texture([CHECKER_SIZE, CHECKER_SIZE], checker, true)),
+    ]
+}
+
+fn texture(size: [u32; 2], pixels: Vec<u8>, srgb: bool) -> AssetData {
+    let settings = TextureMetadata {
+        source_size: size,
+        max_ingame_size: size,
+        data_size: [size[0] * size[1] * 4, 0],
+        srgb,
+        format: match srgb {
+            true => Format::R8G8B8A8Srgb,
+            false => Format::R8G8B8A8Unorm
+        },
+        // keeps the checker's squares sharp when stretched
+        filter: Filter::Nearest,
+        ..TextureMetadata::default()
+    };
+    AssetData::Texture(TextureAssetData::new(settings, Some(pixels)))
+}
@@ -0,0 +1,161 @@
+use hashbrown::{HashMap, HashSet};
+
+use crate::asset::{Asset, AssetId};
+
+
+/// References between assets, resolved from the dependency paths each asset declares on import.
+/// References are by path, so moving a referenced asset breaks them until the referencing asset
+/// is updated. Rebuilt by the registry whenever the tree changes.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    /// UIDs each asset references, in declaration order.
+    pub forward: HashMap<AssetId, Vec<AssetId>>,
+    /// The reverse of `forward`: UIDs of the assets referencing each asset.
+    pub referencers: HashMap<AssetId, Vec<AssetId>>,
+    /// Declared dependency paths that don't resolve to an asset, per referencing asset.
+    pub missing: HashMap<AssetId, Vec<String>>,
+}
+
+impl DependencyGraph {
+    /// Resolves every asset's declared dependencies against the assets in the tree.
+    pub fn build<'a, I: Iterator<Item = (String, &'a Asset)>>(assets: I) -> Self {
+        let assets: Vec<(String, &Asset)> = assets.collect();
+        let path_to_uid: HashMap<&str, AssetId> = assets.iter().map(|(path, asset)| (path.as_str(), asset.uid)).collect();
+        let mut graph = Self::default();
+        for (_, asset) in assets.iter() {
+            graph.insert(asset, |path| path_to_uid.get(path).copied());
+        }
+        graph
+    }
+
+    /// Brings the graph up to date after the assets in `uids` were added, changed or removed,
+    /// without going over every asset like `build`. `asset` finds an asset in the tree by UID, and
+    /// `resolve` by path. Assets referencing the changed ones, or paths that may now resolve, are
+    /// updated too.
+    pub fn update<'a>(&mut self, uids: &[AssetId], asset: impl Fn(AssetId) -> Option<&'a Asset>, resolve: impl Fn(&str) -> Option<AssetId>) {
+        let mut affected: HashSet<AssetId> = uids.iter().cloned().collect();
+        for uid in uids.iter() {
+            affected.extend(self.referencers_of(*uid).iter().cloned());
+        }
+        for (referencer, paths) in self.missing.iter() {
+            if paths.iter().any(|path| resolve(path).is_some()) {
+                affected.insert(*referencer);
+            }
+        }
+        for uid in affected.iter() {
+            for dep in self.forward.remove(uid).unwrap_or_default() {
+                if let Some(referencers) = self.referencers.get_mut(&dep) {
+                    referencers.retain(|referencer| referencer != uid);
+                    if referencers.is_empty() {
+                        self.referencers.remove(&dep);
+                    }
+                }
+            }
+            self.missing.remove(uid);
+        }
+        for uid in affected {
+            if let Some(asset) = asset(uid) {
+                self.insert(asset, &resolve);
+            }
+        }
+    }
+
+    /// Adds an asset's references, which mustn't be in the graph yet.
+    fn insert(&mut self, asset: &Asset, resolve: impl Fn(&str) -> Option<AssetId>) {
+        if asset.dependencies.is_empty() {
+            return;
+        }
+        let mut resolved = Vec::new();
+        for path in asset.dependencies.iter() {
+            match resolve(path) {
+                Some(uid) => resolved.push(uid),
+                None => self.missing.entry(asset.uid).or_default().push(path.clone())
+            }
+        }
+        for dep in resolved.iter() {
+            self.referencers.entry(*dep).or_default().push(asset.uid);
+        }
+        self.forward.insert(asset.uid, resolved);
+    }
+
+    /// Assets directly referenced by `uid`.
+    pub fn dependencies_of(&self, uid: AssetId) -> &[AssetId] {
+        match self.forward.get(&uid) {
+            Some(deps) => deps,
+            None => &[]
+        }
+    }
+
+    /// Assets directly referencing `uid`.
+    pub fn referencers_of(&self, uid: AssetId) -> &[AssetId] {
+        match self.referencers.get(&uid) {
+            Some(referencers) => referencers,
+            None => &[]
+        }
+    }
+
+    /// Every asset `uid` depends on, directly or indirectly, in breadth-first order. Cycles are
+    /// tolerated, and `uid` itself is never included.
+    pub fn all_dependencies_of(&self, uid: AssetId) -> Vec<AssetId> {
+        closure(&self.forward, &[uid])
+    }
+
+    /// Every asset that depends on any of `uids`, directly or indirectly, in breadth-first order.
+    /// None of `uids` are included.
+    pub fn all_referencers_of(&self, uids: &[AssetId]) -> Vec<AssetId> {
+        closure(&self.referencers, uids)
+    }
+
+    /// Orders `uids` so every asset comes after the assets it depends on. Assets caught in a
+    /// reference cycle keep their relative order and go last.
+    pub fn topological_order(&self, uids: &[AssetId]) -> Vec<AssetId> {
+        let set: HashSet<AssetId> = uids.iter().cloned().collect();
+        let mut remaining: HashMap<AssetId, usize> = uids.iter().map(|uid| {
+            (*uid, self.dependencies_of(*uid).iter().filter(|d| set.contains(*d) && *d != uid).count())
+        }).collect();
+        let mut order: Vec<AssetId> = uids.iter().filter(|uid| remaining[*uid] == 0).cloned().collect();
+        let mut next = 0;
+        while next < order.len() {
+            let uid = order[next];
+            next += 1;
+            if let Some(referencers) = self.referencers.get(&uid) {
+                for referencer in referencers.iter() {
+                    if let Some(count) = remaining.get_mut(referencer) {
+                        if *count > 0 {
+                            *count -= 1;
+                            if *count == 0 {
+                                order.push(*referencer);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if order.len() < uids.len() {
+            let ordered: HashSet<AssetId> = order.iter().cloned().collect();
+            order.extend(uids.iter().filter(|uid| !ordered.contains(*uid)));
+        }
+        order
+    }
+}
+
+/// Breadth-first walk of `edges` from `start`, excluding `start` itself.
+fn closure(edges: &HashMap<AssetId, Vec<AssetId>>, start: &[AssetId]) -> Vec<AssetId> {
+    let mut visited: HashSet<AssetId> = start.iter().cloned().collect();
+    let mut result = Vec::new();
+    let mut queue: Vec<AssetId> = start.to_vec();
+    let mut next = 0;
+    while next < queue.len() {
+        let current = queue[next];
+        next += 1;
+        if let Some(targets) = edges.get(&current) {
+            for target in targets.iter() {
+                if visited.insert(*target) {
+                    result.push(*target);
+                    queue.push(*target);
+                }
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,288 @@
+//! On-disk cache of cooked asset data, keyed by everything that affects the result, so an asset
+//! is only ever processed once per version of its source and settings.
+//!
+//! A cache can also be backed by an HTTP server shared by a team, see `RemoteCache`, so only one
+//! machine ever cooks each version of an asset.
+
+use std::fmt::Display;
+use std::fs;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use hashbrown::HashSet;
+use serde::{Serialize, Deserialize};
+use tracing::warn;
+
+use crate::asset::{Asset, AssetData};
+use crate::cook::{CookProfile, processor_version};
+use crate::error::AssetRegistryError;
+use crate::gc::{CacheFile, GcOptions, GcReport, collect_garbage, read_dir};
+use crate::pack::{PackCompression, PackedData};
+
+
+/// Bump this whenever cooked data or the entry layout changes. Entries from other versions are
+/// ignored and cooked again.
+pub const DERIVED_VERSION: u32 = 8;
+
+
+/// Key of an asset's cooked data in a `DerivedCache`. Changes whenever the source, the import
+/// settings, the cook profile or the asset type's processor version do. `tier` selects a quality
+/// tier variant.
+pub fn derived_key(asset: &Asset, profile: &CookProfile, tier: Option<&str>) -> u64 {
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    hasher.write_u32(DERIVED_VERSION);
+    hasher.write_u32(processor_version(asset.data.asset_type()));
+    hasher.write_u64(asset.content_hash);
+    // serializing plain settings structs can't fail
+    hasher.write(&bincode::serialize(profile).unwrap());
+    hasher.write(&bincode::serialize(&tier).unwrap());
+    match &asset.data {
+        AssetData::Texture(tex_data) => {
+            hasher.write_u8(0);
+            hasher.write(&bincode::serialize(&tex_data.settings).unwrap());
+            hasher.write(&bincode::serialize(&profile.texture_overrides(tex_data, tier)).unwrap());
+        }
+    }
+    hasher.finish()
+}
+
+#[derive(Serialize, Deserialize)]
+struct DerivedEntry {
+    version: u32,
+    /// See `cook::processor_version`.
+    processor_version: u32,
+    data: PackedData,
+    uncompressed_size: u64,
+    /// LZ4 compressed, which is cheap enough that reading from the cache stays fast.
+    bytes: Vec<u8>,
+}
+
+/// Entries bigger than this are never downloaded, in case a misconfigured server returns
+/// something else entirely.
+const MAX_REMOTE_ENTRY_SIZE: u64 = 1 << 30;
+
+/// Most LZ4 can expand data by. Entries claiming more are corrupt, and are rejected before their
+/// decompressed size is allocated.
+const MAX_LZ4_RATIO: u64 = 255;
+
+
+fn encode_entry(data: &PackedData, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let entry = DerivedEntry {
+        version: DERIVED_VERSION,
+        processor_version: processor_version(data.asset_type()),
+        data: data.clone(),
+        uncompressed_size: bytes.len() as u64,
+        bytes: PackCompression::Lz4.compress(bytes)?,
+    };
+    // serializing plain data can't fail
+    Ok(bincode::serialize(&entry).unwrap())
+}
+
+/// Unreadable entries are treated as missing, so they just get cooked again.
+fn decode_entry(encoded: &[u8], origin: &dyn Display) -> Option<(PackedData, Vec<u8>)> {
+    let entry: DerivedEntry = match bincode::deserialize(encoded) {
+        Ok(entry) => entry,
+        Err(e) => {
+            warn!(%origin, error = %e, "Ignoring corrupt derived data");
+            return None;
+        }
+    };
+    if entry.version != DERIVED_VERSION {
+        return None;
+    }
+    // keys include the processor version, so this only catches entries stored under the wrong key
+    if entry.processor_version != processor_version(entry.data.asset_type()) {
+        warn!(%origin, version = entry.processor_version, "Ignoring derived data from another processor version");
+        return None;
+    }
+    if entry.uncompressed_size > (entry.bytes.len() as u64).saturating_mul(MAX_LZ4_RATIO) {
+        warn!(%origin, size = entry.uncompressed_size, "Ignoring corrupt derived data");
+        return None;
+    }
+    match PackCompression::Lz4.decompress(&entry.bytes, entry.uncompressed_size) {
+        Ok(bytes) => Some((entry.data, bytes)),
+        Err(e) => {
+            warn!(%origin, error = %e, "Ignoring corrupt derived data");
+            None
+        }
+    }
+}
+
+
+/// A derived cache shared over HTTP. Entries are downloaded with `GET <url>/<key>` and uploaded
+/// with `PUT <url>/<key>`, in the same format as local entries, so any server that can store
+/// files works. After a connection fails the server is skipped for the rest of the session,
+/// rather than timing out on every asset.
+#[derive(Debug, Clone)]
+pub struct RemoteCache {
+    pub url: String,
+    /// Whether cooked entries are uploaded. Usually only build machines upload and everyone else
+    /// just downloads.
+    pub upload: bool,
+    agent: ureq::Agent,
+    available: Arc<AtomicBool>,
+}
+
+impl RemoteCache {
+    pub fn new(url: &str) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(5))
+            .timeout(Duration::from_secs(60))
+            .build();
+        Self { url: url.trim_end_matches('/').to_string(), upload: true, agent, available: Arc::new(AtomicBool::new(true)) }
+    }
+
+    pub fn with_upload(mut self, upload: bool) -> Self {
+        self.upload = upload;
+        self
+    }
+
+    pub fn url_for(&self, key: u64) -> String {
+        format!("{}/{:016x}", self.url, key)
+    }
+
+    /// Downloads an encoded entry. Missing entries and failures both return `None`.
+    pub fn get(&self, key: u64) -> Option<Vec<u8>> {
+        if !self.available.load(Ordering::Relaxed) {
+            return None;
+        }
+        let url = self.url_for(key);
+        let response = match self.agent.get(&url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return None,
+            Err(e) => {
+                self.failed(&url, e);
+                return None;
+            }
+        };
+        let mut encoded = Vec::new();
+        match response.into_reader().take(MAX_REMOTE_ENTRY_SIZE).read_to_end(&mut encoded) {
+            Ok(_) => Some(encoded),
+            Err(e) => {
+                warn!(url = url.as_str(), error = %e, "Failed to download derived data");
+                None
+            }
+        }
+    }
+
+    /// Uploads an encoded entry, if uploading is enabled. Failures are only logged, since the
+    /// entry is still in the local cache.
+    pub fn put(&self, key: u64, encoded: &[u8]) {
+        if !self.upload || !self.available.load(Ordering::Relaxed) {
+            return;
+        }
+        let url = self.url_for(key);
+        if let Err(e) = self.agent.put(&url).set("Content-Type", "application/octet-stream").send_bytes(encoded) {
+            self.failed(&url, e);
+        }
+    }
+
+    fn failed(&self, url: &str, error: ureq::Error) {
+        match error {
+            ureq::Error::Status(status, _) => warn!(url, status, "Remote derived cache request failed"),
+            ureq::Error::Transport(e) => {
+                warn!(url, error = %e, "Remote derived cache is unreachable, skipping it from now on");
+                self.available.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+
+/// A directory of cooked data, one file per key, optionally backed by a `RemoteCache`.
+#[derive(Debug, Clone)]
+pub struct DerivedCache {
+    pub root: PathBuf,
+    pub remote: Option<RemoteCache>,
+}
+
+impl DerivedCache {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf(), remote: None }
+    }
+
+    /// Falls back to `remote` for entries missing locally, and uploads new entries to it.
+    pub fn with_remote(mut self, remote: RemoteCache) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Entries are spread over subdirectories by their first byte, to keep directories small.
+    pub fn path_for(&self, key: u64) -> PathBuf {
+        let name = format!("{:016x}", key);
+        self.root.join(&name[..2]).join(name)
+    }
+
+    /// Whether the entry is in the local cache.
+    pub fn contains(&self, key: u64) -> bool {
+        self.path_for(key).exists()
+    }
+
+    /// Reads an entry, downloading it from the remote cache if it's only there.
+    pub fn get(&self, key: u64) -> Option<(PackedData, Vec<u8>)> {
+        let path = self.path_for(key);
+        if let Ok(file) = fs::read(&path) {
+            if let Some(entry) = decode_entry(&file, &path.display()) {
+                return Some(entry);
+            }
+        }
+        let remote = self.remote.as_ref()?;
+        let encoded = remote.get(key)?;
+        let entry = decode_entry(&encoded, &remote.url_for(key))?;
+        if let Err(e) = self.write_local(key, &encoded) {
+            warn!(error = %e, "Failed to store downloaded derived data");
+        }
+        Some(entry)
+    }
+
+    /// Stores an entry locally, and uploads it if the remote cache takes uploads.
+    pub fn put(&self, key: u64, data: &PackedData, bytes: &[u8]) -> Result<(), AssetRegistryError> {
+        let encoded = self.put_local(key, data, bytes)?;
+        if let Some(remote) = &self.remote {
+            remote.put(key, &encoded);
+        }
+        Ok(())
+    }
+
+    /// Stores an entry locally only, returning it encoded, for uploading with `RemoteCache::put`.
+    pub fn put_local(&self, key: u64, data: &PackedData, bytes: &[u8]) -> Result<Vec<u8>, AssetRegistryError> {
+        let encoded = encode_entry(data, bytes).map_err(|e| AssetRegistryError::io(self.path_for(key).display(), e))?;
+        self.write_local(key, &encoded)?;
+        Ok(encoded)
+    }
+
+    /// Deletes every local entry whose key isn't in `live`, and any past the limits in `options`.
+    /// The remote cache is left alone, since other machines may still need its entries.
+    pub fn collect_garbage(&self, live: &HashSet<u64>, options: &GcOptions) -> Result<GcReport, AssetRegistryError> {
+        let mut files = Vec::new();
+        let directories = read_dir(&self.root)?;
+        for directory in directories.iter().filter(|path| path.is_dir()) {
+            files.extend(read_dir(directory)?.into_iter().filter_map(|path| {
+                let key = path.file_name()?.to_str().and_then(|name| u64::from_str_radix(name, 16).ok());
+                CacheFile::new(path, key)
+            }));
+        }
+        let report = collect_garbage(files, live, options)?;
+        if !options.dry_run {
+            for directory in directories {
+                // only succeeds for directories left empty
+                let _ = fs::remove_dir(directory);
+            }
+        }
+        Ok(report)
+    }
+
+    fn write_local(&self, key: u64, encoded: &[u8]) -> Result<(), AssetRegistryError> {
+        let path = self.path_for(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| AssetRegistryError::io(parent.display(), e))?;
+        }
+        // written beside the entry and renamed into place, so readers never see half an entry
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, encoded).map_err(|e| AssetRegistryError::io(temp_path.display(), e))?;
+        fs::rename(&temp_path, &path).map_err(|e| AssetRegistryError::io(path.display(), e))
+    }
+}
@@ -0,0 +1,118 @@
+//! Descriptor sets binding a list of textures, so renderers don't have to assemble one by hand for
+//! every material.
+//!
+//! Each binding of the set has to be a single combined image sampler, and gets the texture at the
+//! same position in the list, with a sampler made from its settings.
+
+use std::sync::Arc;
+use vulkano::buffer::BufferAccess;
+use vulkano::descriptor::descriptor::{DescriptorDesc, DescriptorDescTy};
+use vulkano::descriptor::descriptor_set::{DescriptorPool, DescriptorPoolAlloc, DescriptorSet, DescriptorSetDesc, DescriptorWrite};
+use vulkano::descriptor::descriptor_set::{StdDescriptorPoolAlloc, UnsafeDescriptorSet};
+use vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract;
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::image::ImageViewAccess;
+use vulkano::sampler::Sampler;
+
+use crate::asset::AssetId;
+use crate::error::UploadError;
+use crate::texture::Texture;
+
+
+/// Pipeline layout a texture set is built for.
+pub type Layout = Arc<dyn PipelineLayoutAbstract + Send + Sync>;
+
+/// What texture sets are cached by: the layout's address, the set index and the textures' UIDs.
+pub(crate) type TextureSetKey = (usize, usize, Vec<AssetId>);
+
+
+/// A descriptor set binding textures in order, see the module docs. Usually made with
+/// `AssetRegistry::texture_set`, which caches them.
+pub struct TextureSet {
+    inner: StdDescriptorPoolAlloc,
+    layout: Layout,
+    set_id: usize,
+    /// Kept alive for as long as the set is.
+    textures: Vec<(Texture, Arc<Sampler>)>,
+}
+
+impl TextureSet {
+    /// Builds set `set_id` of `layout`, binding each texture with its sampler.
+    pub fn new(layout: Layout, set_id: usize, textures: Vec<(Texture, Arc<Sampler>)>) -> Result<Self, UploadError> {
+        let error = |reason: String| UploadError::DescriptorSet { set_id, reason };
+        let bindings = layout.num_bindings_in_set(set_id).unwrap_or(0);
+        if bindings != textures.len() {
+            return Err(error(format!("the layout has {} bindings but {} textures were given", bindings, textures.len())));
+        }
+        let mut writes = Vec::with_capacity(textures.len());
+        for (binding, (texture, sampler)) in textures.iter().enumerate() {
+            match layout.descriptor(set_id, binding).map(|desc| desc.ty) {
+                Some(DescriptorDescTy::CombinedImageSampler(_)) => {},
+                _ => return Err(error(format!("binding {} isn't a combined image sampler", binding)))
+            }
+            let view = texture.view();
+            if !view.can_be_sampled(sampler) {
+                return Err(error(format!("binding {} can't be sampled with its sampler", binding)));
+            }
+            writes.push(DescriptorWrite::combined_image_sampler(binding as u32, 0, sampler, &view));
+        }
+        let set_layout = match layout.descriptor_set_layout(set_id) {
+            Some(set_layout) => set_layout.clone(),
+            None => return Err(error("the layout has no such set".to_string()))
+        };
+        let mut pool = Device::standard_descriptor_pool(layout.device());
+        let mut inner = pool.alloc(&set_layout).map_err(|e| error(e.to_string()))?;
+        unsafe {
+            inner.inner_mut().write(layout.device(), writes.into_iter());
+        }
+        Ok(Self { inner, layout, set_id, textures })
+    }
+
+    pub fn textures(&self) -> impl Iterator<Item = &Texture> {
+        self.textures.iter().map(|(texture, _)| texture)
+    }
+}
+
+unsafe impl DescriptorSet for TextureSet {
+    fn inner(&self) -> &UnsafeDescriptorSet {
+        self.inner.inner()
+    }
+
+    fn num_buffers(&self) -> usize {
+        0
+    }
+
+    fn buffer(&self, _index: usize) -> Option<(&dyn BufferAccess, u32)> {
+        None
+    }
+
+    fn num_images(&self) -> usize {
+        self.textures.len()
+    }
+
+    fn image(&self, index: usize) -> Option<(&dyn ImageViewAccess, u32)> {
+        self.textures.get(index).map(|(texture, _)| (texture.view(), index as u32))
+    }
+}
+
+unsafe impl DescriptorSetDesc for TextureSet {
+    fn num_bindings(&self) -> usize {
+        self.layout.num_bindings_in_set(self.set_id).unwrap_or(0)
+    }
+
+    fn descriptor(&self, binding: usize) -> Option<DescriptorDesc> {
+        self.layout.descriptor(self.set_id, binding)
+    }
+}
+
+unsafe impl DeviceOwned for TextureSet {
+    fn device(&self) -> &Arc<Device> {
+        self.layout.device()
+    }
+}
+
+impl std::fmt::Debug for TextureSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureSet").field("set_id", &self.set_id).field("textures", &self.textures.len()).finish()
+    }
+}
@@ -0,0 +1,71 @@
+//! Dithering for when pixels lose precision, e.g. filtered values going back to 8 bits or colors
+//! quantized for block compression, so smooth gradients don't band.
+
+use serde::{Serialize, Deserialize};
+
+use crate::color::linear_to_srgb;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DitherMode {
+    #[default]
+    None,
+    /// An 8x8 Bayer matrix. Cheap and stable, but the pattern can be visible.
+    Ordered,
+    /// Blue noise, approximated with interleaved gradient noise so no noise texture is needed.
+    /// Less visible than ordered dithering on smooth gradients like skies.
+    BlueNoise,
+}
+
+impl DitherMode {
+    /// Offset added before rounding the pixel at `x`, `y`, in steps of the target precision.
+    pub fn threshold(self, x: u32, y: u32) -> f32 {
+        match self {
+            DitherMode::None => 0.0,
+            DitherMode::Ordered => {
+                let (x, y) = (x & 7, y & 7);
+                let xy = x ^ y;
+                let index = ((xy & 1) << 5) | ((y & 1) << 4) | ((xy & 2) << 2) | ((y & 2) << 1) | ((xy & 4) >> 1) | ((y & 4) >> 2);
+                (index as f32 + 0.5) / 64.0 - 0.5
+            }
+            DitherMode::BlueNoise => {
+                let noise = (0.067_110_56 * x as f32 + 0.005_837_15 * y as f32).fract();
+                (52.982_918 * noise).fract() - 0.5
+            }
+        }
+    }
+}
+
+/// Encodes normalized linear RGBA values, `width` pixels per row, as RGBA8 with dithering. Alpha
+/// is always linear.
+pub fn encode_rgba8(values: &[f32], width: u32, srgb: bool, mode: DitherMode) -> Vec<u8> {
+    let width = width.max(1) as usize;
+    let mut pixels = Vec::with_capacity(values.len());
+    for (i, p) in values.chunks_exact(4).enumerate() {
+        let threshold = mode.threshold((i % width) as u32, (i / width) as u32);
+        for (c, value) in p.iter().enumerate() {
+            let value = value.clamp(0.0, 1.0);
+            let value = if srgb && c < 3 { linear_to_srgb(value) } else { value };
+            pixels.push((value * 255.0 + threshold).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+    pixels
+}
+
+/// Reduces RGBA8 pixels, `width` per row, to `bits` of precision per channel in place, e.g.
+/// `[5, 6, 5, 8]` for the colors block compression stores. Values stay 8-bit, with the low bits
+/// filled so white stays white.
+pub fn quantize_rgba8(pixels: &mut [u8], width: u32, bits: [u8; 4], mode: DitherMode) {
+    let width = width.max(1) as usize;
+    for (i, p) in pixels.chunks_exact_mut(4).enumerate() {
+        let threshold = mode.threshold((i % width) as u32, (i / width) as u32);
+        for (value, bits) in p.iter_mut().zip(bits.iter()) {
+            if *bits >= 8 {
+                continue;
+            }
+            let levels = ((1u32 << bits) - 1) as f32;
+            let level = (*value as f32 / 255.0 * levels + threshold).round().clamp(0.0, levels);
+            *value = (level * 255.0 / levels).round() as u8;
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::fmt::{Display, Formatter, Error};
+use image::ColorType;
+use vulkano::format::Format;
+
+
+// Registry errors /////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum AssetRegistryError {
+    PathDoesNotExist(String),
+    UnknownCollection(String),
+    InvalidMountName(String),
+    UnknownMount(String),
+    InvalidPattern { pattern: String, source: globset::Error },
+    /// An asset passed to `register_asset` can't be added.
+    InvalidAsset { path: String, reason: String },
+    InvalidAlias { alias: String, reason: String },
+    /// A `RegistryConfig` setting was rejected by `build`.
+    InvalidConfig(String),
+    /// Reading or writing a file other than an asset source failed.
+    Io { path: String, source: std::io::Error },
+    Database { path: String, source: bincode::Error },
+    Sidecar { path: String, source: serde_json::Error },
+    Pack { path: String, source: PackError },
+    Manifest { path: String, source: serde_json::Error },
+    Scan(ScanError),
+    Import(ImportError),
+    Upload(UploadError),
+    Other(Error)
+}
+
+impl AssetRegistryError {
+    pub(crate) fn io(path: impl Display, source: std::io::Error) -> Self {
+        AssetRegistryError::Io { path: path.to_string(), source }
+    }
+}
+
+impl Display for AssetRegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            AssetRegistryError::PathDoesNotExist(path) => {
+                write!(f, "Path does not exist: '{}'", path)?;
+            },
+            AssetRegistryError::UnknownCollection(name) => {
+                write!(f, "No collection named '{}'", name)?;
+            },
+            AssetRegistryError::InvalidMountName(name) => {
+                write!(f, "Invalid mount name '{}', expected letters, digits, '-' or '_'", name)?;
+            },
+            AssetRegistryError::UnknownMount(name) => {
+                write!(f, "Nothing is mounted as '{}'", name)?;
+            },
+            AssetRegistryError::InvalidPattern { pattern, source } => {
+                write!(f, "Invalid pattern '{}': {}", pattern, source)?;
+            },
+            AssetRegistryError::InvalidAsset { path, reason } => {
+                write!(f, "Can't register asset '{}': {}", path, reason)?;
+            },
+            AssetRegistryError::InvalidAlias { alias, reason } => {
+                write!(f, "Can't alias '{}': {}", alias, reason)?;
+            },
+            AssetRegistryError::InvalidConfig(reason) => {
+                write!(f, "Invalid registry config: {}", reason)?;
+            },
+            AssetRegistryError::Io { path, source } => {
+                write!(f, "I/O error on '{}': {}", path, source)?;
+            },
+            AssetRegistryError::Database { path, source } => {
+                write!(f, "Registry database error in '{}': {}", path, source)?;
+            },
+            AssetRegistryError::Sidecar { path, source } => {
+                write!(f, "Sidecar error in '{}': {}", path, source)?;
+            },
+            AssetRegistryError::Pack { path, source } => {
+                write!(f, "Pack error in '{}': {}", path, source)?;
+            },
+            AssetRegistryError::Manifest { path, source } => {
+                write!(f, "Manifest error in '{}': {}", path, source)?;
+            },
+            AssetRegistryError::Scan(e) => {
+                write!(f, "{}", e)?;
+            },
+            AssetRegistryError::Import(e) => {
+                write!(f, "{}", e)?;
+            },
+            AssetRegistryError::Upload(e) => {
+                write!(f, "{}", e)?;
+            },
+            AssetRegistryError::Other(e) => {
+                write!(f, "{}", e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssetRegistryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetRegistryError::PathDoesNotExist(_) => None,
+            AssetRegistryError::UnknownCollection(_) => None,
+            AssetRegistryError::InvalidMountName(_) => None,
+            AssetRegistryError::UnknownMount(_) => None,
+            AssetRegistryError::InvalidPattern { source, .. } => Some(source),
+            AssetRegistryError::InvalidAsset { .. } => None,
+            AssetRegistryError::InvalidAlias { .. } => None,
+            AssetRegistryError::InvalidConfig(_) => None,
+            AssetRegistryError::Io { source, .. } => Some(source),
+            AssetRegistryError::Database { source, .. } => Some(source),
+            AssetRegistryError::Sidecar { source, .. } => Some(source),
+            AssetRegistryError::Pack { source, .. } => Some(source),
+            AssetRegistryError::Manifest { source, .. } => Some(source),
+            AssetRegistryError::Scan(e) => Some(e),
+            AssetRegistryError::Import(e) => Some(e),
+            AssetRegistryError::Upload(e) => Some(e),
+            AssetRegistryError::Other(e) => Some(e),
+        }
+    }
+}
+
+impl From<Error> for AssetRegistryError {
+    fn from(e: Error) -> Self {
+        AssetRegistryError::Other(e)
+    }
+}
+impl From<ScanError> for AssetRegistryError {
+    fn from(e: ScanError) -> Self {
+        AssetRegistryError::Scan(e)
+    }
+}
+impl From<ImportError> for AssetRegistryError {
+    fn from(e: ImportError) -> Self {
+        AssetRegistryError::Import(e)
+    }
+}
+impl From<UploadError> for AssetRegistryError {
+    fn from(e: UploadError) -> Self {
+        AssetRegistryError::Upload(e)
+    }
+}
+
+
+// Pack errors /////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum PackError {
+    Io(std::io::Error),
+    /// The file isn't a pack.
+    BadMagic,
+    /// The pack was written by a different version of the cooker.
+    UnsupportedVersion(u32),
+    Index(bincode::Error),
+    /// An entry extends past the end of the pack.
+    Truncated,
+    /// An entry is encrypted and no key was given.
+    MissingKey,
+    /// An entry failed to encrypt or decrypt, usually because the key is wrong.
+    Decrypt,
+    /// A streamed pack couldn't be downloaded.
+    Http { url: String, reason: String },
+    /// Downloaded data doesn't match the hash in the pack's index.
+    Corrupt,
+}
+
+impl Display for PackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            PackError::Io(e) => write!(f, "{}", e),
+            PackError::BadMagic => write!(f, "Not a pack file"),
+            PackError::UnsupportedVersion(version) => write!(f, "Unsupported pack version {}", version),
+            PackError::Index(e) => write!(f, "Corrupt pack index: {}", e),
+            PackError::Truncated => write!(f, "Pack is truncated"),
+            PackError::MissingKey => write!(f, "Pack is encrypted and no key was given"),
+            PackError::Decrypt => write!(f, "Failed to decrypt pack entry, the key may be wrong"),
+            PackError::Http { url, reason } => write!(f, "Failed to download '{}': {}", url, reason),
+            PackError::Corrupt => write!(f, "Pack entry doesn't match its hash"),
+        }
+    }
+}
+
+impl std::error::Error for PackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackError::Io(e) => Some(e),
+            PackError::Index(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for PackError {
+    fn from(e: std::io::Error) -> Self {
+        PackError::Io(e)
+    }
+}
+
+
+// Scan errors /////////////////////////////////////////////////////////////////////////////////////
+
+/// Failures of the scanning machinery itself, as opposed to individual files.
+#[derive(Debug)]
+pub enum ScanError {
+    WalkDir(walkdir::Error),
+    Watch(notify::Error),
+    ThreadPool(rayon::ThreadPoolBuildError),
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            ScanError::WalkDir(e) => write!(f, "Failed to walk content directory: {}", e),
+            ScanError::Watch(e) => write!(f, "File watcher error: {}", e),
+            ScanError::ThreadPool(e) => write!(f, "Failed to create import thread pool: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScanError::WalkDir(e) => Some(e),
+            ScanError::Watch(e) => Some(e),
+            ScanError::ThreadPool(e) => Some(e),
+        }
+    }
+}
+
+impl From<walkdir::Error> for ScanError {
+    fn from(e: walkdir::Error) -> Self {
+        ScanError::WalkDir(e)
+    }
+}
+impl From<notify::Error> for ScanError {
+    fn from(e: notify::Error) -> Self {
+        ScanError::Watch(e)
+    }
+}
+impl From<rayon::ThreadPoolBuildError> for ScanError {
+    fn from(e: rayon::ThreadPoolBuildError) -> Self {
+        ScanError::ThreadPool(e)
+    }
+}
+
+
+// Import errors ///////////////////////////////////////////////////////////////////////////////////
+
+/// A file that couldn't be imported. Any previously imported version of it is left untouched.
+/// Paths are relative to the content root.
+#[derive(Debug)]
+pub enum ImportError {
+    Io { path: String, source: std::io::Error },
+    Decode { path: String, source: image::ImageError },
+    UnsupportedColorType { path: String, color_type: ColorType },
+    UnsupportedFileType { path: String },
+    /// The buffer given to `decode_texture_into` doesn't fit the decoded image.
+    SizeMismatch { path: String, expected: usize, found: usize },
+    /// The decoded source doesn't match its settings, because it changed size since it was imported.
+    ChangedSize { path: String },
+}
+
+impl ImportError {
+    /// The file that failed to import.
+    pub fn path(&self) -> &str {
+        match self {
+            ImportError::Io { path, .. } => path,
+            ImportError::Decode { path, .. } => path,
+            ImportError::UnsupportedColorType { path, .. } => path,
+            ImportError::UnsupportedFileType { path } => path,
+            ImportError::SizeMismatch { path, .. } => path,
+            ImportError::ChangedSize { path } => path,
+        }
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Failed to import '{}': ", self.path())?;
+        match self {
+            ImportError::Io { source, .. } => write!(f, "{}", source),
+            ImportError::Decode { source, .. } => write!(f, "{}", source),
+            ImportError::UnsupportedColorType { color_type, .. } => write!(f, "Unsupported color type: {:?}", color_type),
+            ImportError::UnsupportedFileType { .. } => write!(f, "Unsupported file type"),
+            ImportError::SizeMismatch { expected, found, .. } => write!(f, "Decoded image is {} bytes but the buffer is {}", expected, found),
+            ImportError::ChangedSize { .. } => write!(f, "Changed size since it was imported, rescan first"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Io { source, .. } => Some(source),
+            ImportError::Decode { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
+
+
+// Upload errors ///////////////////////////////////////////////////////////////////////////////////
+
+/// A texture that couldn't be made available on the GPU.
+#[derive(Debug)]
+pub enum UploadError {
+    /// No asset at this path.
+    NotFound(String),
+    /// The source file couldn't be decoded.
+    Decode(ImportError),
+    UnsupportedFormat { path: String, format: Format },
+    ImageCreation { path: String, source: vulkano::image::ImageCreationError },
+    /// A background load ended without producing a result.
+    Cancelled(String),
+    /// Reading cooked data from a pack failed.
+    Pack { path: String, source: PackError },
+    /// The registry was created without a GPU.
+    Headless(String),
+    /// The source decoded to a different size than it was imported at, so it changed since the
+    /// last scan.
+    SizeMismatch(String),
+    /// The upload couldn't be submitted to the GPU.
+    Submit { path: String, source: vulkano::sync::FlushError },
+    /// Recording the upload's commands failed.
+    Commands { path: String, reason: String },
+    Sampler { path: String, source: vulkano::sampler::SamplerCreationError },
+    /// The textures don't fit the descriptor set layout, or the set couldn't be allocated.
+    DescriptorSet { set_id: usize, reason: String },
+    /// There's no room left in a `DynamicAtlas` for the texture.
+    AtlasFull(String),
+}
+
+impl Display for UploadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            UploadError::NotFound(path) => write!(f, "No texture asset at '{}'", path),
+            UploadError::Decode(e) => write!(f, "{}", e),
+            UploadError::UnsupportedFormat { path, format } => write!(f, "Failed to upload '{}': unsupported format {:?}", path, format),
+            UploadError::ImageCreation { path, source } => write!(f, "Failed to upload '{}': {}", path, source),
+            UploadError::Cancelled(path) => write!(f, "Loading '{}' was cancelled", path),
+            UploadError::Pack { path, source } => write!(f, "Failed to read from pack '{}': {}", path, source),
+            UploadError::Headless(path) => write!(f, "Can't upload '{}' from a headless registry", path),
+            UploadError::SizeMismatch(path) => write!(f, "'{}' changed size since it was imported, rescan first", path),
+            UploadError::Submit { path, source } => write!(f, "Failed to submit upload of '{}': {}", path, source),
+            UploadError::Commands { path, reason } => write!(f, "Failed to record upload of '{}': {}", path, reason),
+            UploadError::Sampler { path, source } => write!(f, "Failed to create a sampler for '{}': {}", path, source),
+            UploadError::DescriptorSet { set_id, reason } => write!(f, "Failed to build descriptor set {}: {}", set_id, reason),
+            UploadError::AtlasFull(path) => write!(f, "No room left in the atlas for '{}'", path),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UploadError::Decode(e) => Some(e),
+            UploadError::ImageCreation { source, .. } => Some(source),
+            UploadError::Pack { source, .. } => Some(source),
+            UploadError::Submit { source, .. } => Some(source),
+            UploadError::Sampler { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
+
+impl From<ImportError> for UploadError {
+    fn from(e: ImportError) -> Self {
+        UploadError::Decode(e)
+    }
+}
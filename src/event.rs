@@ -0,0 +1,77 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::asset::AssetId;
+use crate::scan::ScanReport;
+
+
+/// A change to an asset in the registry. Paths are relative to the content root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetEvent {
+    Added { uid: AssetId, path: String },
+    Modified { uid: AssetId, path: String },
+    Removed { uid: AssetId, path: String },
+    Moved { uid: AssetId, from: String, to: String },
+    /// The asset was forcibly reprocessed, even though its source didn't change.
+    Reimported { uid: AssetId, path: String },
+    /// The asset's cached GPU texture was replaced with one uploaded from its new data, see
+    /// `AssetRegistry::hot_swap_textures`. Holders of the old texture should fetch it again.
+    Swapped { uid: AssetId, path: String },
+}
+
+impl AssetEvent {
+    pub fn uid(&self) -> AssetId {
+        match self {
+            AssetEvent::Added { uid, .. } => *uid,
+            AssetEvent::Modified { uid, .. } => *uid,
+            AssetEvent::Removed { uid, .. } => *uid,
+            AssetEvent::Moved { uid, .. } => *uid,
+            AssetEvent::Reimported { uid, .. } => *uid,
+            AssetEvent::Swapped { uid, .. } => *uid,
+        }
+    }
+}
+
+
+/// Fans asset events out to every subscriber. Subscribers that have dropped their receiver are
+/// forgotten the next time an event is sent.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Vec<Sender<AssetEvent>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self) -> Receiver<AssetEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.is_empty()
+    }
+
+    pub fn send(&mut self, event: AssetEvent) {
+        self.subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+
+    pub fn send_report(&mut self, report: &ScanReport) {
+        if !self.has_subscribers() {
+            return;
+        }
+        for e in report.added.iter() {
+            self.send(AssetEvent::Added { uid: e.uid, path: e.path.clone() });
+        }
+        for e in report.modified.iter() {
+            self.send(AssetEvent::Modified { uid: e.uid, path: e.path.clone() });
+        }
+        for e in report.moved.iter() {
+            self.send(AssetEvent::Moved { uid: e.uid, from: e.from.clone(), to: e.to.clone() });
+        }
+        for e in report.removed.iter() {
+            self.send(AssetEvent::Removed { uid: e.uid, path: e.path.clone() });
+        }
+        for e in report.reimported.iter() {
+            self.send(AssetEvent::Reimported { uid: e.uid, path: e.path.clone() });
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! JSON dump of the registry for tools that don't link against the crate, like build dashboards
+//! and asset audits.
+
+use std::fs::File;
+use std::io::BufWriter;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::asset::{Asset, AssetData, AssetType};
+use crate::error::AssetRegistryError;
+
+
+#[derive(Serialize)]
+struct ExportedAsset<'a> {
+    path: &'a str,
+    /// Hex, like `AssetId`'s `Display`, since JSON numbers can't hold every u64.
+    uid: String,
+    asset_type: AssetType,
+    timestamp: DateTime<Local>,
+    /// Bytes of the source file.
+    source_size: u64,
+    /// Bytes of the processed data, before any cook-time scaling.
+    data_size: u64,
+    content_hash: String,
+    tags: &'a [String],
+    dependencies: &'a [String],
+    data: &'a AssetData,
+}
+
+#[derive(Serialize)]
+struct ExportedRegistry<'a> {
+    base_path: &'a str,
+    assets: Vec<ExportedAsset<'a>>,
+}
+
+/// Writes every asset in `assets` to `path` as JSON, ordered by path.
+pub fn write_json(path: &str, base_path: &str, assets: &[(String, &Asset)]) -> Result<(), AssetRegistryError> {
+    let mut exported: Vec<ExportedAsset> = assets.iter().map(|(asset_path, asset)| ExportedAsset {
+        path: asset_path,
+        uid: asset.uid.to_string(),
+        asset_type: asset.data.asset_type(),
+        timestamp: asset.timestamp,
+        source_size: asset.source_size,
+        data_size: match &asset.data {
+            AssetData::Texture(tex_data) => tex_data.settings.data_size[0] as u64,
+        },
+        content_hash: format!("{:016x}", asset.content_hash),
+        tags: &asset.tags,
+        dependencies: &asset.dependencies,
+        data: &asset.data,
+    }).collect();
+    exported.sort_by(|a, b| a.path.cmp(b.path));
+    let writer = BufWriter::new(File::create(path).map_err(|e| AssetRegistryError::io(path, e))?);
+    let registry = ExportedRegistry { base_path, assets: exported };
+    serde_json::to_writer_pretty(writer, &registry).map_err(|source| AssetRegistryError::Manifest { path: path.to_string(), source })
+}
@@ -0,0 +1,101 @@
+//! Garbage collection for the on-disk caches, which otherwise grow without bound over a project's
+//! life as sources and settings change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use hashbrown::HashSet;
+
+use crate::error::AssetRegistryError;
+
+
+/// What a GC pass deletes besides dead entries, which are always deleted.
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions {
+    /// Only reports what would be deleted.
+    pub dry_run: bool,
+    /// Also deletes live entries that were written longer ago than this.
+    pub max_age: Option<Duration>,
+    /// Also deletes the oldest live entries until each cache is at most this many bytes.
+    pub max_size: Option<u64>,
+}
+
+impl GcOptions {
+    pub fn dry_run() -> Self {
+        Self { dry_run: true, ..Self::default() }
+    }
+}
+
+/// What a GC pass deleted, or would have for a dry run.
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    pub removed: Vec<PathBuf>,
+    pub bytes_freed: u64,
+    pub kept: usize,
+    pub bytes_kept: u64,
+}
+
+impl GcReport {
+    pub fn merge(&mut self, other: GcReport) {
+        self.removed.extend(other.removed);
+        self.bytes_freed += other.bytes_freed;
+        self.kept += other.kept;
+        self.bytes_kept += other.bytes_kept;
+    }
+}
+
+
+/// A file in a cache, with the key it's named by.
+pub(crate) struct CacheFile {
+    pub path: PathBuf,
+    pub key: u64,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl CacheFile {
+    /// Reads a cache file's metadata. `None` if `key` can't tell what it's the key of, so files the
+    /// cache didn't write are left alone.
+    pub fn new(path: PathBuf, key: Option<u64>) -> Option<Self> {
+        let metadata = fs::metadata(&path).ok()?;
+        Some(Self { key: key?, size: metadata.len(), modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), path })
+    }
+}
+
+/// Deletes the files whose keys aren't in `live`, then live files past the age and size limits,
+/// oldest first.
+pub(crate) fn collect_garbage(mut files: Vec<CacheFile>, live: &HashSet<u64>, options: &GcOptions) -> Result<GcReport, AssetRegistryError> {
+    let now = SystemTime::now();
+    files.sort_by_key(|file| file.modified);
+    // dead files go regardless, so only live ones count towards the size limit
+    let mut live_size: u64 = files.iter().filter(|file| live.contains(&file.key)).map(|file| file.size).sum();
+    let mut report = GcReport::default();
+    for file in files {
+        let is_live = live.contains(&file.key);
+        let expired = options.max_age.is_some_and(|max_age| now.duration_since(file.modified).is_ok_and(|age| age > max_age));
+        let oversized = options.max_size.is_some_and(|max_size| live_size > max_size);
+        if is_live && !expired && !oversized {
+            report.kept += 1;
+            report.bytes_kept += file.size;
+            continue;
+        }
+        if !options.dry_run {
+            fs::remove_file(&file.path).map_err(|e| AssetRegistryError::io(file.path.display(), e))?;
+        }
+        if is_live {
+            live_size -= file.size;
+        }
+        report.bytes_freed += file.size;
+        report.removed.push(file.path);
+    }
+    Ok(report)
+}
+
+/// Lists a directory, treating a missing one as empty.
+pub(crate) fn read_dir(directory: &Path) -> Result<Vec<PathBuf>, AssetRegistryError> {
+    match fs::read_dir(directory) {
+        Ok(entries) => Ok(entries.flatten().map(|entry| entry.path()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(AssetRegistryError::io(directory.display(), e))
+    }
+}
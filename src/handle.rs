@@ -0,0 +1,114 @@
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use crate::asset::{AssetId, AssetData, AssetType, TextureAssetData};
+use crate::arena::SlotId;
+use crate::error::UploadError;
+use crate::registry::AssetRegistry;
+use crate::texture::Texture;
+
+
+/// Implemented by each kind of processed asset data, so registry lookups can be typed.
+pub trait AssetKind: Sized {
+    const ASSET_TYPE: AssetType;
+    /// What loading an asset of this kind produces, e.g. the GPU texture for a texture asset.
+    type Loaded: Send + 'static;
+
+    fn from_data(data: &AssetData) -> Option<&Self>;
+
+    /// Loads the asset at `path`, blocking until it's ready to use.
+    fn load(registry: &AssetRegistry, path: &str) -> Result<Self::Loaded, UploadError>;
+}
+
+impl AssetKind for TextureAssetData {
+    const ASSET_TYPE: AssetType = AssetType::Texture;
+    type Loaded = Texture;
+
+    fn from_data(data: &AssetData) -> Option<&Self> {
+        match data {
+            AssetData::Texture(tex) => Some(tex),
+        }
+    }
+
+    fn load(registry: &AssetRegistry, path: &str) -> Result<Texture, UploadError> {
+        registry.get_texture(path)
+    }
+}
+
+
+/// A typed reference to an asset, returned by `AssetRegistry::get`. Resolving a handle is a direct
+/// slot lookup. Handles stay valid when the asset is moved or reprocessed, and stop resolving once
+/// it's removed.
+pub struct Handle<T> {
+    pub uid: AssetId,
+    pub slot: SlotId,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn new(uid: AssetId, slot: SlotId) -> Self {
+        Self { uid, slot, _marker: PhantomData }
+    }
+}
+
+// implemented by hand so they don't require T to implement them
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.uid == other.uid && self.slot == other.slot
+    }
+}
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.uid.hash(state);
+        self.slot.hash(state);
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.uid)
+    }
+}
+
+
+/// A handle that remembers which version of the asset it was taken from, so holders can tell when
+/// the asset was reprocessed or removed and their derived resources (GPU textures, etc.) are stale.
+/// Created with `AssetRegistry::downgrade` and checked with `AssetRegistry::check`.
+pub struct WeakHandle<T> {
+    pub handle: Handle<T>,
+    pub version: u64,
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for WeakHandle<T> {}
+
+impl<T> Debug for WeakHandle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WeakHandle<{}>({} v{})", std::any::type_name::<T>(), self.handle.uid, self.version)
+    }
+}
+
+/// What happened to the asset behind a `WeakHandle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleState {
+    /// Unchanged since the handle was taken.
+    Current,
+    /// Reprocessed since the handle was taken, so anything derived from it should be refreshed.
+    Changed,
+    Removed,
+}
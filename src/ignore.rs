@@ -0,0 +1,138 @@
+//! gitignore-style ignore files, so work files and exported junk in the content tree are never
+//! imported.
+//!
+//! A `.pipedreamignore` file applies to the directory it's in and everything below it. Each line
+//! is a glob pattern, and blank lines and lines starting with `#` are skipped. As with gitignore:
+//!
+//! - patterns without a `/` match files and directories at any depth, e.g. `*.psd`
+//! - patterns containing a `/` are relative to the ignore file's directory, e.g. `/export/*.png`
+//! - a trailing `/` only matches directories, e.g. `_wip/`
+//! - a leading `!` re-includes something an earlier pattern ignored, unless a directory above it
+//!   is ignored
+//!
+//! Later patterns override earlier ones, and ignore files further down the tree override those
+//! above them.
+
+use std::path::Path;
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::archive;
+use crate::error::AssetRegistryError;
+use crate::mount::{MOUNT_SEPARATOR, is_within};
+use crate::path::AssetPath;
+
+
+pub const IGNORE_FILE_NAME: &str = ".pipedreamignore";
+
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    matcher: GlobMatcher,
+    negated: bool,
+    directory_only: bool,
+}
+
+/// The patterns from one ignore file, or given programmatically, for one directory.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// Virtual path of the directory the patterns are relative to.
+    pub directory: String,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    pub fn new(directory: &str) -> Self {
+        Self { directory: directory.to_string(), rules: Vec::new() }
+    }
+
+    /// Reads the ignore file for `directory`, if it has one.
+    pub fn read(source_path: &Path, directory: &str) -> Result<Option<Self>, AssetRegistryError> {
+        if !archive::exists(source_path) {
+            return Ok(None);
+        }
+        let bytes = archive::read(source_path).map_err(|e| AssetRegistryError::io(source_path.display(), e))?;
+        let mut rules = Self::new(directory);
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                rules.add(line)?;
+            }
+        }
+        Ok(Some(rules))
+    }
+
+    /// Adds a pattern, which takes precedence over the ones before it.
+    pub fn add(&mut self, pattern: &str) -> Result<(), AssetRegistryError> {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern)
+        };
+        let (directory_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern)
+        };
+        let glob = match pattern.contains('/') {
+            true => pattern.trim_start_matches('/').to_string(),
+            false => format!("**/{}", pattern)
+        };
+        let matcher = match GlobBuilder::new(&glob).literal_separator(true).build() {
+            Ok(glob) => glob.compile_matcher(),
+            Err(source) => return Err(AssetRegistryError::InvalidPattern { pattern: pattern.to_string(), source })
+        };
+        self.rules.push(IgnoreRule { matcher, negated, directory_only });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `Some(true)` if the last pattern matching `path` ignores it, `Some(false)` if it
+    /// re-includes it, and `None` if no pattern matches.
+    fn check(&self, path: &str, is_directory: bool) -> Option<bool> {
+        if !is_within(path, &self.directory) || path == self.directory {
+            return None;
+        }
+        let relative = match self.directory.is_empty() || self.directory.ends_with(MOUNT_SEPARATOR) {
+            true => &path[self.directory.len()..],
+            false => &path[self.directory.len() + 1..]
+        };
+        self.rules.iter().rev()
+            .find(|rule| (is_directory || !rule.directory_only) && rule.matcher.is_match(relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Every set of ignore rules that applies to a scan, from the least to the most specific.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreSet {
+    pub rules: Vec<IgnoreRules>,
+}
+
+impl IgnoreSet {
+    pub fn add(&mut self, rules: IgnoreRules) {
+        if !rules.is_empty() {
+            self.rules.push(rules);
+        }
+    }
+
+    /// Whether a file is ignored, either itself or because a directory above it is.
+    pub fn is_ignored(&self, path: &str) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let path = AssetPath::new(path);
+        let mut directories = Vec::new();
+        let mut parent = path.parent();
+        while let Some(directory) = parent {
+            parent = directory.parent();
+            directories.push(directory);
+        }
+        // an ignored directory can't have anything below it re-included
+        directories.iter().rev().any(|directory| self.check(directory, true)) || self.check(&path, false)
+    }
+
+    fn check(&self, path: &str, is_directory: bool) -> bool {
+        self.rules.iter().rev().find_map(|rules| rules.check(path, is_directory)).unwrap_or(false)
+    }
+}
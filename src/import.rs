@@ -0,0 +1,229 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local};
+use std::io::Read;
+use image::{ImageDecoder, ColorType};
+use vulkano::format::Format;
+use tracing::{warn, debug_span};
+
+use crate::error::ImportError;
+use crate::asset::{Asset, AssetData, TextureAssetData, uid_from_path, content_hash};
+use crate::sidecar::Sidecar;
+use crate::source::SourceBytes;
+use crate::archive;
+use crate::color::{self, ColorPrimaries};
+use crate::texture::{TextureMetadata, TexturePreset, ChannelMask};
+use crate::validate::{ImportWarning, validate_texture};
+
+
+/// The result of processing one source file, ready to be merged into the registry. Producing one
+/// only reads from disk, so many can be prepared in parallel.
+#[derive(Debug)]
+pub struct PreparedImport {
+    pub sidecar: Sidecar,
+    /// `Ok(None)` for unsupported files.
+    pub result: Result<Option<Asset>, ImportError>,
+    /// Content standards the asset doesn't meet.
+    pub warnings: Vec<ImportWarning>,
+    /// How long processing took.
+    pub duration: Duration,
+}
+
+pub fn file_timestamp(path: &Path) -> std::io::Result<DateTime<Local>> {
+    archive::modified(path)
+}
+
+pub fn prepare_import(source_path: &Path, relative_path: &str) -> PreparedImport {
+    prepare_import_with(source_path, relative_path, read_sidecar(source_path, relative_path))
+}
+
+/// `prepare_import` with a sidecar that was already read.
+pub fn prepare_import_with(source_path: &Path, relative_path: &str, sidecar: Sidecar) -> PreparedImport {
+    let _span = debug_span!("import", path = relative_path).entered();
+    let start = Instant::now();
+    let result = process_file(source_path, relative_path, &sidecar);
+    let warnings = match &result {
+        Ok(Some(asset)) => validate(relative_path, asset),
+        _ => Vec::new()
+    };
+    PreparedImport { sidecar, result, warnings, duration: start.elapsed() }
+}
+
+/// A source file's sidecar, or the defaults if it has none or it can't be read.
+pub fn read_sidecar(source_path: &Path, relative_path: &str) -> Sidecar {
+    match Sidecar::read(source_path) {
+        Ok(sidecar) => sidecar.unwrap_or_default(),
+        Err(e) => {
+            warn!(path = relative_path, error = %e, "Ignoring unreadable sidecar");
+            Sidecar::default()
+        }
+    }
+}
+
+/// Checks an imported asset against the content standards in `validate`. Nothing is decoded for
+/// this, checks that need pixels are done when they're cooked.
+fn validate(relative_path: &str, asset: &Asset) -> Vec<ImportWarning> {
+    match &asset.data {
+        AssetData::Texture(tex_data) => validate_texture(relative_path, &tex_data.settings)
+    }
+}
+
+pub fn process_file(source_path: &Path, relative_path: &str, sidecar: &Sidecar) -> Result<Option<Asset>, ImportError> {
+    let filename = match source_path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Ok(None)
+    };
+    if let Some(ext) = source_path.extension() {
+        let ext = ext.to_string_lossy().to_lowercase();
+        let ext = ext.as_str();
+        if ["png", "jpg", "tga", "dds"].contains(&ext) {
+            let preset = match (sidecar.preset, &sidecar.texture) {
+                (Some(preset), _) => Some(preset),
+                (None, None) => TexturePreset::from_filename(&filename),
+                (None, Some(_)) => None
+            };
+            return process_texture(source_path, &filename, relative_path, ext, sidecar.texture.as_ref(), preset);
+        }
+    }
+    Ok(None)
+}
+
+pub fn process_texture(source_path: &Path, filename: &str, relative_path: &str, ext: &str, settings: Option<&TextureMetadata>, preset: Option<TexturePreset>) -> Result<Option<Asset>, ImportError> {
+    match ext {
+        "png" => {
+            let source = SourceBytes::read(source_path).map_err(|e| io_error(relative_path, e))?;
+            let source_hash = content_hash(&source);
+            let reader = image::png::PNGDecoder::new(std::io::Cursor::new(&source[..])).map_err(|e| decode_error(relative_path, e))?;
+            let dimensions = [reader.dimensions().0 as u32, reader.dimensions().1 as u32];
+            let timestamp = file_timestamp(source_path).map_err(|e| io_error(relative_path, e))?;
+
+            let colortype = reader.colortype();
+            let id = uid_from_path(relative_path);
+
+            // import settings come from the sidecar, the info block always describes the source
+            let mut texture_data = settings.cloned().unwrap_or_default();
+            if let Some(preset) = preset {
+                preset.apply(&mut texture_data);
+            }
+            let rgba = match texture_data.srgb {
+                true => Format::R8G8B8A8Srgb,
+                false => Format::R8G8B8A8Unorm
+            };
+            let (format, has_channels) = match colortype {
+                ColorType::RGB(8) => (rgba, ChannelMask::RED | ChannelMask::GREEN | ChannelMask::BLUE),
+                ColorType::RGBA(8) => (rgba, ChannelMask::all()),
+                ColorType::Gray(8) => (Format::R8Unorm, ChannelMask::RED),
+                color_type => return Err(ImportError::UnsupportedColorType { path: relative_path.to_string(), color_type })
+            };
+            texture_data.source_size = dimensions;
+            texture_data.max_ingame_size = dimensions;
+            texture_data.data_size = [dimensions[0] * dimensions[1] * 4, 0];
+            texture_data.has_channels = has_channels;
+            texture_data.format = format;
+            // grayscale sources are masks and other data, stored as they are
+            if format == Format::R8Unorm {
+                texture_data.srgb = false;
+            }
+            texture_data.num_mips = 0;
+            texture_data.include_channels &= has_channels;
+
+            // pixels are decoded later, when the texture is actually requested
+            Ok(Some(Asset::new(filename, timestamp, id, source_hash, source.len() as u64, None, AssetData::Texture(
+                TextureAssetData::new(texture_data, None))
+            )))
+        },
+        _ => Ok(None)
+    }
+}
+
+/// Decodes a texture's source file into tightly packed Rec.709 RGBA8 pixels, with its adjustments
+/// applied.
+pub fn decode_texture(source_path: &Path, relative_path: &str, settings: &TextureMetadata) -> Result<Vec<u8>, ImportError> {
+    let _span = debug_span!("decode", path = relative_path).entered();
+    let mut pixels = decode_source(source_path, relative_path)?;
+    color::convert_primaries(&mut pixels, settings.primaries, ColorPrimaries::Rec709, settings.srgb);
+    settings.adjustments.apply(&mut pixels);
+    Ok(pixels)
+}
+
+/// Like `decode_texture`, but decodes straight into `pixels`, e.g. a mapped staging buffer, which
+/// has to be exactly the source's size in RGBA8.
+pub fn decode_texture_into(source_path: &Path, relative_path: &str, settings: &TextureMetadata, pixels: &mut [u8]) -> Result<(), ImportError> {
+    let _span = debug_span!("decode", path = relative_path).entered();
+    decode_source_with(source_path, relative_path, |len| match len == pixels.len() {
+        true => Ok(pixels),
+        false => Err(ImportError::SizeMismatch { path: relative_path.to_string(), expected: len, found: pixels.len() })
+    })?;
+    color::convert_primaries(pixels, settings.primaries, ColorPrimaries::Rec709, settings.srgb);
+    settings.adjustments.apply(pixels);
+    Ok(())
+}
+
+fn decode_source(source_path: &Path, relative_path: &str) -> Result<Vec<u8>, ImportError> {
+    let mut pixels = Vec::new();
+    let destination = &mut pixels;
+    decode_source_with(source_path, relative_path, move |len| {
+        destination.resize(len, 0);
+        Ok(&mut destination[..])
+    })?;
+    Ok(pixels)
+}
+
+/// Decodes a source file into RGBA8, asking `destination` for a buffer of the given length once
+/// the size is known. Rows are decoded and expanded one at a time, so besides the destination only
+/// a single row is held in memory, rather than the whole image twice over. Interlaced PNGs are the
+/// exception, the decoder has to buffer those whole.
+fn decode_source_with<'a>(source_path: &Path, relative_path: &str,
+    destination: impl FnOnce(usize) -> Result<&'a mut [u8], ImportError>) -> Result<(), ImportError>
+{
+    let ext = source_path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "png" => {
+            let source = SourceBytes::read(source_path).map_err(|e| io_error(relative_path, e))?;
+            let decoder = image::png::PNGDecoder::new(std::io::Cursor::new(&source[..])).map_err(|e| decode_error(relative_path, e))?;
+            let colortype = decoder.colortype();
+            let channels = match colortype {
+                ColorType::RGB(8) => 3,
+                ColorType::RGBA(8) => 4,
+                // expanded so everything downstream only deals with RGBA8
+                ColorType::Gray(8) => 1,
+                color_type => return Err(ImportError::UnsupportedColorType { path: relative_path.to_string(), color_type })
+            };
+            let (width, height) = decoder.dimensions();
+            let mut row = vec![0u8; decoder.scanline_bytes() as usize];
+            let pixels = destination(width as usize * height as usize * 4)?;
+            let mut reader = decoder.into_reader().map_err(|e| decode_error(relative_path, e))?;
+            for out in pixels.chunks_exact_mut(width as usize * 4) {
+                reader.read_exact(&mut row).map_err(|e| io_error(relative_path, e))?;
+                expand_row(&row, channels, out);
+            }
+            Ok(())
+        },
+        _ => Err(ImportError::UnsupportedFileType { path: relative_path.to_string() })
+    }
+}
+
+fn expand_row(row: &[u8], channels: usize, out: &mut [u8]) {
+    match channels {
+        4 => out.copy_from_slice(&row[..out.len()]),
+        3 => {
+            for (rgba, rgb) in out.chunks_exact_mut(4).zip(row.chunks_exact(3)) {
+                rgba[..3].copy_from_slice(rgb);
+                rgba[3] = 255;
+            }
+        },
+        _ => {
+            for (rgba, &v) in out.chunks_exact_mut(4).zip(row) {
+                rgba.copy_from_slice(&[v, v, v, 255]);
+            }
+        }
+    }
+}
+
+fn io_error(relative_path: &str, source: std::io::Error) -> ImportError {
+    ImportError::Io { path: relative_path.to_string(), source }
+}
+
+fn decode_error(relative_path: &str, source: image::ImageError) -> ImportError {
+    ImportError::Decode { path: relative_path.to_string(), source }
+}
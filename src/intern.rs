@@ -0,0 +1,35 @@
+use std::sync::Arc;
+use hashbrown::HashSet;
+
+
+/// Shared storage for path strings, so a path held by several maps is only allocated once and
+/// cloning it is just a reference count bump.
+#[derive(Debug, Default)]
+pub struct PathInterner {
+    paths: HashSet<Arc<str>>,
+}
+
+impl PathInterner {
+    /// The shared copy of `path`, added if it isn't interned yet.
+    pub fn intern(&mut self, path: &str) -> Arc<str> {
+        if let Some(existing) = self.paths.get(path) {
+            return existing.clone();
+        }
+        let path: Arc<str> = Arc::from(path);
+        self.paths.insert(path.clone());
+        path
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Forgets paths that nothing outside the interner holds anymore.
+    pub fn collect_unused(&mut self) {
+        self.paths.retain(|path| Arc::strong_count(path) > 1);
+    }
+}
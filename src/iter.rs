@@ -0,0 +1,119 @@
+use crate::asset::{Asset, FileTreeNode};
+use crate::arena::Arena;
+use crate::mount::join_path;
+
+
+/// Pushes the children of a directory so they're popped in name order.
+fn push_children<'a>(stack: &mut Vec<(String, &'a FileTreeNode)>, prefix: &str, node: &'a FileTreeNode) {
+    if let FileTreeNode::Directory(map) = node {
+        let mut children: Vec<(&String, &FileTreeNode)> = map.iter().collect();
+        children.sort_by(|a, b| b.0.cmp(a.0));
+        stack.extend(children.into_iter().map(|(name, child)| (join_path(prefix, name), child)));
+    }
+}
+
+
+// Iterators ///////////////////////////////////////////////////////////////////////////////////////
+
+/// Depth-first iterator over every asset below a node, in name order, yielding each asset with its
+/// path relative to the content root.
+pub struct AssetIter<'a> {
+    assets: &'a Arena<Asset>,
+    stack: Vec<(String, &'a FileTreeNode)>,
+}
+
+impl<'a> AssetIter<'a> {
+    /// Iterates the assets below `node`, which lives at `path` relative to the content root.
+    pub fn new(assets: &'a Arena<Asset>, node: &'a FileTreeNode, path: &str) -> Self {
+        Self { assets, stack: vec![(path.to_string(), node)] }
+    }
+}
+
+impl<'a> Iterator for AssetIter<'a> {
+    type Item = (String, &'a Asset);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, node)) = self.stack.pop() {
+            match node {
+                FileTreeNode::File(slot) => {
+                    if let Some(asset) = self.assets.get(*slot) {
+                        return Some((path, asset));
+                    }
+                },
+                FileTreeNode::Directory(_) => push_children(&mut self.stack, &path, node),
+            }
+        }
+        None
+    }
+}
+
+/// Depth-first iterator over every directory below a node, including the node itself, in name order.
+pub struct DirectoryIter<'a> {
+    stack: Vec<(String, &'a FileTreeNode)>,
+}
+
+impl<'a> DirectoryIter<'a> {
+    pub fn new(node: &'a FileTreeNode, path: &str) -> Self {
+        Self { stack: vec![(path.to_string(), node)] }
+    }
+}
+
+impl<'a> Iterator for DirectoryIter<'a> {
+    type Item = (String, &'a FileTreeNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, node)) = self.stack.pop() {
+            if let FileTreeNode::Directory(_) = node {
+                push_children(&mut self.stack, &path, node);
+                return Some((path, node));
+            }
+        }
+        None
+    }
+}
+
+
+// Visitor /////////////////////////////////////////////////////////////////////////////////////////
+
+/// What a `TreeVisitor` wants to do after visiting a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitAction {
+    Continue,
+    /// Don't descend into this directory. Same as `Continue` when returned for an asset.
+    SkipDirectory,
+    /// End the walk immediately.
+    Stop,
+}
+
+/// Walks the file tree depth-first in name order. Paths are relative to the content root.
+pub trait TreeVisitor {
+    fn visit_directory(&mut self, _path: &str) -> VisitAction {
+        VisitAction::Continue
+    }
+
+    fn visit_asset(&mut self, _path: &str, _asset: &Asset) -> VisitAction {
+        VisitAction::Continue
+    }
+}
+
+/// Runs `visitor` over `node` and everything below it. Returns false if the visitor stopped the walk.
+pub fn walk(assets: &Arena<Asset>, node: &FileTreeNode, path: &str, visitor: &mut dyn TreeVisitor) -> bool {
+    match node {
+        FileTreeNode::File(slot) => {
+            match assets.get(*slot) {
+                Some(asset) => visitor.visit_asset(path, asset) != VisitAction::Stop,
+                None => true
+            }
+        },
+        FileTreeNode::Directory(map) => {
+            match visitor.visit_directory(path) {
+                VisitAction::Continue => {},
+                VisitAction::SkipDirectory => return true,
+                VisitAction::Stop => return false,
+            }
+            let mut children: Vec<(&String, &FileTreeNode)> = map.iter().collect();
+            children.sort_by(|a, b| a.0.cmp(b.0));
+            children.into_iter().all(|(name, child)| walk(assets, child, &join_path(path, name), visitor))
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Import work as a graph of jobs, run by a scheduler with a fixed number of worker threads.
+//!
+//! Each job belongs to a stage of the import pipeline and can depend on other jobs, e.g. an atlas
+//! on its sprites or a material on its textures, and only starts once those have finished. Results
+//! are handed back on the thread that called `run` as jobs finish, so they can be merged into state
+//! the workers don't have access to.
+//!
+//! Cooking runs each asset as a chain of jobs, one per stage, see `AssetRegistry::cook`. Scans use
+//! a single `Decode` job per asset: importing only reads a source's header and hashes it, and the
+//! pixel work the later stages stand for isn't done until the asset is cooked or loaded. No import
+//! reads another's result either, so the sidecar dependencies between scan jobs only decide which
+//! asset is imported first.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use parking_lot::Mutex;
+use tracing::debug_span;
+
+
+/// Where a job sits in the import pipeline. Ready jobs of later stages are started first, so work
+/// already in flight is finished before more is started and memory use stays bounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobStage {
+    Decode,
+    Process,
+    Compress,
+    CacheWrite,
+    Upload,
+}
+
+impl JobStage {
+    fn rank(self) -> u8 {
+        match self {
+            JobStage::Decode => 0,
+            JobStage::Process => 1,
+            JobStage::Compress => 2,
+            JobStage::CacheWrite => 3,
+            JobStage::Upload => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JobId(pub usize);
+
+struct Job<'a, T> {
+    stage: JobStage,
+    dependencies: Vec<JobId>,
+    work: Box<dyn FnOnce() -> T + Send + 'a>,
+}
+
+/// Jobs producing a `T` each, and what they wait for, see the module docs.
+pub struct JobGraph<'a, T> {
+    jobs: Vec<Job<'a, T>>,
+}
+
+impl<'a, T: Send> JobGraph<'a, T> {
+    pub fn new() -> Self {
+        Self { jobs: Vec::new() }
+    }
+
+    pub fn add<F: FnOnce() -> T + Send + 'a>(&mut self, stage: JobStage, work: F) -> JobId {
+        self.jobs.push(Job { stage, dependencies: Vec::new(), work: Box::new(work) });
+        JobId(self.jobs.len() - 1)
+    }
+
+    /// Makes `job` wait for `on` to finish. If the jobs left all wait on each other, one caught in
+    /// a cycle starts anyway, so its dependents can go on.
+    pub fn depend(&mut self, job: JobId, on: JobId) {
+        if job != on && !self.jobs[job.0].dependencies.contains(&on) {
+            self.jobs[job.0].dependencies.push(on);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Runs every job on `threads` workers, calling `on_finished` with each result as it comes in.
+    /// Returning `false` from it stops any more jobs from starting, the ones already running are
+    /// still finished and handed over. Returns the jobs that never started.
+    pub fn run<F: FnMut(JobId, T) -> bool>(self, threads: usize, mut on_finished: F) -> Vec<JobId> {
+        let threads = threads.max(1);
+        let mut waiting: Vec<usize> = self.jobs.iter().map(|job| job.dependencies.len()).collect();
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); self.jobs.len()];
+        for (index, job) in self.jobs.iter().enumerate() {
+            for dependency in job.dependencies.iter() {
+                dependents[dependency.0].push(index);
+            }
+        }
+        let dependencies: Vec<Vec<JobId>> = self.jobs.iter().map(|job| job.dependencies.clone()).collect();
+        let stages: Vec<JobStage> = self.jobs.iter().map(|job| job.stage).collect();
+        let mut work: Vec<Option<Box<dyn FnOnce() -> T + Send + 'a>>> = self.jobs.into_iter().map(|job| Some(job.work)).collect();
+        // later stages first, then in the order they were added
+        let mut ready: BinaryHeap<(u8, Reverse<usize>)> = (0..work.len())
+            .filter(|index| waiting[*index] == 0)
+            .map(|index| (stages[index].rank(), Reverse(index)))
+            .collect();
+
+        let (job_sender, job_receiver) = mpsc::channel::<(usize, Box<dyn FnOnce() -> T + Send + 'a>)>();
+        let (done_sender, done_receiver) = mpsc::channel::<(usize, std::thread::Result<T>)>();
+        let job_receiver = Mutex::new(job_receiver);
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                let job_receiver = &job_receiver;
+                let done_sender = done_sender.clone();
+                let stages = &stages;
+                scope.spawn(move || loop {
+                    let next = job_receiver.lock().recv();
+                    match next {
+                        Ok((index, work)) => {
+                            let span = debug_span!("job", stage = ?stages[index]);
+                            let result = catch_unwind(AssertUnwindSafe(|| span.in_scope(work)));
+                            if done_sender.send((index, result)).is_err() {
+                                break;
+                            }
+                        },
+                        Err(_) => break
+                    }
+                });
+            }
+            drop(done_sender);
+
+            let mut running = 0;
+            let mut stopped = false;
+            loop {
+                while !stopped && running < threads {
+                    let index = match ready.pop() {
+                        Some((_, Reverse(index))) => index,
+                        // nothing ready and nothing running means the rest wait on each other
+                        None if running == 0 => match (0..work.len()).find(|index| work[*index].is_some()) {
+                            Some(index) => in_cycle(index, &dependencies, &work),
+                            None => break
+                        },
+                        None => break
+                    };
+                    if let Some(job) = work[index].take() {
+                        job_sender.send((index, job)).expect("job workers stopped early");
+                        running += 1;
+                    }
+                }
+                if running == 0 {
+                    break;
+                }
+                let (index, result) = done_receiver.recv().expect("job workers stopped early");
+                running -= 1;
+                // the other workers stop once the job sender is dropped on the way out
+                let result = match result {
+                    Ok(result) => result,
+                    Err(panic) => resume_unwind(panic)
+                };
+                if !on_finished(JobId(index), result) {
+                    stopped = true;
+                }
+                for dependent in dependents[index].iter() {
+                    waiting[*dependent] -= 1;
+                    if waiting[*dependent] == 0 && work[*dependent].is_some() {
+                        ready.push((stages[*dependent].rank(), Reverse(*dependent)));
+                    }
+                }
+            }
+            drop(job_sender);
+        });
+        work.iter().enumerate().filter(|(_, job)| job.is_some()).map(|(index, _)| JobId(index)).collect()
+    }
+}
+
+/// A job on a cycle of jobs that haven't started, found by following dependencies from `start`,
+/// which has to be stuck waiting.
+fn in_cycle<W>(start: usize, dependencies: &[Vec<JobId>], work: &[Option<W>]) -> usize {
+    let mut visited = vec![false; work.len()];
+    let mut index = start;
+    while !visited[index] {
+        visited[index] = true;
+        match dependencies[index].iter().find(|dependency| work[dependency.0].is_some()) {
+            Some(dependency) => index = dependency.0,
+            None => return index
+        }
+    }
+    index
+}
+
+impl<'a, T: Send> Default for JobGraph<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> std::fmt::Debug for JobGraph<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobGraph").field("jobs", &self.jobs.len()).finish()
+    }
+}
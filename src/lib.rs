@@ -3,5 +3,82 @@
 pub mod asset;
 pub mod texture;
 pub mod registry;
+pub mod scan;
+pub mod event;
+pub mod database;
+pub mod sidecar;
+pub mod import;
+pub mod source;
+pub mod watch;
+pub mod error;
+pub mod metrics;
+pub mod memory;
+pub mod stats;
+pub mod iter;
+pub mod search;
+pub mod collection;
+pub mod dependency;
+pub mod handle;
+pub mod arena;
+pub mod load;
+pub mod loader;
+pub mod jobs;
+pub mod mount;
+pub mod archive;
+pub mod pack;
+pub mod cook;
+pub mod packed;
+pub mod residency;
+pub mod mips;
+pub mod descriptor;
+pub mod atlas;
+pub mod stream;
+pub mod derived;
+pub mod gc;
+pub mod manifest;
+pub mod export;
+pub mod thumbnail;
+pub mod color;
+pub mod dither;
+pub mod bc;
+pub mod validate;
+pub mod path;
+pub mod intern;
+pub mod ignore;
+pub mod config;
+pub mod defaults;
+pub mod snapshot;
+pub mod server;
+mod serde_shims;
 
-pub use self::registry::{AssetRegistry, AssetRegistryError};
+pub use self::registry::{AssetRegistry, ChangeDetection};
+pub use self::config::RegistryConfig;
+pub use self::snapshot::{RegistrySnapshot, AssetInfo, SnapshotDiff};
+pub use self::server::AssetServer;
+pub use self::asset::{AssetId, DirEntryInfo};
+pub use self::path::AssetPath;
+pub use self::error::{AssetRegistryError, ScanError, ImportError, UploadError, PackError};
+pub use self::scan::{ScanReport, ReimportOptions, ScanOptions, ScanProgress, CancellationToken, SymlinkMode};
+pub use self::event::AssetEvent;
+pub use self::metrics::{RegistryMetrics, AssetTimings};
+pub use self::memory::MemoryReport;
+pub use self::stats::{DirectoryStats, DuplicateGroup};
+pub use self::iter::{TreeVisitor, VisitAction};
+pub use self::search::SearchMatch;
+pub use self::collection::AssetCollection;
+pub use self::dependency::DependencyGraph;
+pub use self::handle::{Handle, WeakHandle, HandleState, AssetKind};
+pub use self::load::{LoadFuture, BatchReport};
+pub use self::loader::LoadPriority;
+pub use self::mount::{Mount, Overlay};
+pub use self::cook::{CookOptions, CookProfile, CookReport};
+pub use self::pack::PackKey;
+pub use self::packed::PackedRegistry;
+pub use self::residency::ResidencyManager;
+pub use self::descriptor::TextureSet;
+pub use self::atlas::{DynamicAtlas, UvRect};
+pub use self::stream::RemotePack;
+pub use self::derived::{DerivedCache, RemoteCache};
+pub use self::thumbnail::ThumbnailCache;
+pub use self::gc::{GcOptions, GcReport};
+pub use self::manifest::{BuildManifest, VerifyReport};
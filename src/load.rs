@@ -0,0 +1,167 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use parking_lot::Mutex;
+use hashbrown::HashMap;
+
+use crate::error::UploadError;
+use crate::texture::Texture;
+
+
+struct Shared<T> {
+    result: Option<Result<T, UploadError>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the result of a load running in the background. Isn't tied to any executor, so it
+/// can be awaited from tokio, a custom executor, or anything else that polls futures.
+pub struct LoadFuture<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> LoadFuture<T> {
+    /// Whether the load has finished, without waiting for it.
+    pub fn is_finished(&self) -> bool {
+        self.shared.lock().result.is_some()
+    }
+}
+
+impl<T> Future for LoadFuture<T> {
+    type Output = Result<T, UploadError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock();
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The worker's end of a `LoadFuture`. Dropping it without completing, e.g. because the load
+/// panicked, resolves the future with `UploadError::Cancelled` instead of leaving it hanging.
+pub(crate) struct LoadPromise<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+    path: String,
+    completed: bool,
+}
+
+impl<T> LoadPromise<T> {
+    pub fn complete(mut self, result: Result<T, UploadError>) {
+        self.resolve(result);
+    }
+
+    fn resolve(&mut self, result: Result<T, UploadError>) {
+        self.completed = true;
+        let waker = {
+            let mut shared = self.shared.lock();
+            shared.result = Some(result);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for LoadPromise<T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            let path = std::mem::take(&mut self.path);
+            self.resolve(Err(UploadError::Cancelled(path)));
+        }
+    }
+}
+
+/// Creates a linked promise and future for loading `path`.
+pub(crate) fn load_channel<T>(path: &str) -> (LoadPromise<T>, LoadFuture<T>) {
+    let shared = Arc::new(Mutex::new(Shared { result: None, waker: None }));
+    (LoadPromise { shared: shared.clone(), path: path.to_string(), completed: false }, LoadFuture { shared })
+}
+
+
+// Batches /////////////////////////////////////////////////////////////////////////////////////////
+
+/// Everything a batch load produced, keyed by the paths the assets were requested with.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub textures: HashMap<String, Texture>,
+    pub failed: Vec<UploadError>,
+}
+
+impl BatchReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+struct BatchState {
+    remaining: usize,
+    report: BatchReport,
+    on_complete: Option<Box<dyn FnOnce(BatchReport) + Send>>,
+}
+
+/// Collects the results of a batch's loads, calling back once the last one finishes.
+pub(crate) struct Batch {
+    state: Mutex<BatchState>,
+}
+
+impl Batch {
+    pub fn new<F: FnOnce(BatchReport) + Send + 'static>(count: usize, on_complete: F) -> Arc<Self> {
+        // an empty batch is complete straight away
+        if count == 0 {
+            on_complete(BatchReport::default());
+            return Arc::new(Self { state: Mutex::new(BatchState { remaining: 0, report: BatchReport::default(), on_complete: None }) });
+        }
+        let on_complete: Box<dyn FnOnce(BatchReport) + Send> = Box::new(on_complete);
+        let state = BatchState { remaining: count, report: BatchReport::default(), on_complete: Some(on_complete) };
+        Arc::new(Self { state: Mutex::new(state) })
+    }
+
+    pub fn entry(self: &Arc<Self>, path: &str) -> BatchEntry {
+        BatchEntry { batch: self.clone(), path: path.to_string(), finished: false }
+    }
+
+    fn finish_one<F: FnOnce(&mut BatchReport)>(&self, record: F) {
+        let done = {
+            let mut state = self.state.lock();
+            record(&mut state.report);
+            state.remaining -= 1;
+            match state.remaining {
+                0 => state.on_complete.take().map(|f| (f, std::mem::take(&mut state.report))),
+                _ => None
+            }
+        };
+        if let Some((on_complete, report)) = done {
+            on_complete(report);
+        }
+    }
+}
+
+/// One load in a batch. Dropping it unfinished records the load as cancelled, so the batch still
+/// completes if a load panics.
+pub(crate) struct BatchEntry {
+    batch: Arc<Batch>,
+    pub path: String,
+    finished: bool,
+}
+
+impl BatchEntry {
+    pub fn finish<F: FnOnce(&mut BatchReport)>(mut self, record: F) {
+        self.finished = true;
+        self.batch.finish_one(record);
+    }
+}
+
+impl Drop for BatchEntry {
+    fn drop(&mut self) {
+        if !self.finished {
+            let path = std::mem::take(&mut self.path);
+            self.batch.finish_one(|report| report.failed.push(UploadError::Cancelled(path)));
+        }
+    }
+}
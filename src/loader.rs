@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use parking_lot::{Mutex, Condvar};
+use tracing::error;
+
+use crate::asset::AssetId;
+
+
+/// How urgently a background load is needed. Queued loads run in priority order, and in request
+/// order within the same priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LoadPriority {
+    /// Something is waiting on this right now. Jumps ahead of everything else.
+    Blocking,
+    /// Needed soon, e.g. visible on screen.
+    #[default]
+    High,
+    Low,
+    /// Speculative streaming, only loaded once nothing else is queued.
+    Prefetch,
+}
+
+impl LoadPriority {
+    pub(crate) fn rank(self) -> u8 {
+        match self {
+            LoadPriority::Blocking => 3,
+            LoadPriority::High => 2,
+            LoadPriority::Low => 1,
+            LoadPriority::Prefetch => 0,
+        }
+    }
+
+    /// The more urgent of two priorities.
+    pub fn max(self, other: Self) -> Self {
+        match self.rank() >= other.rank() {
+            true => self,
+            false => other
+        }
+    }
+
+    /// A priority for something `distance` away from the camera: `High` up to `near`, `Low` up
+    /// to `far` and `Prefetch` beyond, for streaming hints.
+    pub fn from_distance(distance: f32, near: f32, far: f32) -> Self {
+        match distance {
+            d if d <= near => LoadPriority::High,
+            d if d <= far => LoadPriority::Low,
+            _ => LoadPriority::Prefetch
+        }
+    }
+}
+
+
+struct QueuedJob {
+    priority: LoadPriority,
+    /// What the job was submitted with, before any streaming hint.
+    requested: LoadPriority,
+    /// Asset the job loads, for reprioritizing it.
+    uid: Option<AssetId>,
+    sequence: u64,
+    job: Box<dyn FnOnce() + Send>,
+}
+
+// ordered for the max-heap: highest priority first, then oldest first
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.rank().cmp(&other.priority.rank()).then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for QueuedJob {}
+
+#[derive(Default)]
+struct LoadQueue {
+    jobs: BinaryHeap<QueuedJob>,
+    next_sequence: u64,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct LoaderShared {
+    queue: Mutex<LoadQueue>,
+    available: Condvar,
+}
+
+
+/// Worker threads running background loads from a priority queue, so a flood of low priority
+/// streaming requests can't hold up the loads something is actually waiting on.
+pub struct Loader {
+    shared: Arc<LoaderShared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Loader {
+    pub fn new(threads: usize) -> Self {
+        let shared = Arc::new(LoaderShared::default());
+        let workers = (0..threads.max(1)).map(|i| {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(format!("pipedream-loader-{}", i))
+                .spawn(move || run_worker(&shared))
+                .expect("Failed to spawn loader thread")
+        }).collect();
+        Self { shared, workers }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Number of loads waiting for a worker.
+    pub fn pending(&self) -> usize {
+        self.shared.queue.lock().jobs.len()
+    }
+
+    pub fn submit<F: FnOnce() + Send + 'static>(&self, priority: LoadPriority, job: F) {
+        self.submit_for(None, priority, job);
+    }
+
+    /// `submit` for a job loading `uid`, so its priority follows the asset's streaming hints.
+    pub fn submit_for<F: FnOnce() + Send + 'static>(&self, uid: Option<AssetId>, priority: LoadPriority, job: F) {
+        let mut queue = self.shared.queue.lock();
+        let sequence = queue.next_sequence;
+        queue.next_sequence += 1;
+        queue.jobs.push(QueuedJob { priority, requested: priority, uid, sequence, job: Box::new(job) });
+        drop(queue);
+        self.shared.available.notify_one();
+    }
+
+    /// Moves queued loads of `uid` to the more urgent of what they were submitted with and `hint`.
+    pub fn reprioritize(&self, uid: AssetId, hint: Option<LoadPriority>) {
+        let mut queue = self.shared.queue.lock();
+        if !queue.jobs.iter().any(|job| job.uid == Some(uid)) {
+            return;
+        }
+        let mut jobs = std::mem::take(&mut queue.jobs).into_vec();
+        for job in jobs.iter_mut().filter(|job| job.uid == Some(uid)) {
+            job.priority = hint.map_or(job.requested, |hint| job.requested.max(hint));
+        }
+        queue.jobs = BinaryHeap::from(jobs);
+    }
+}
+
+fn run_worker(shared: &LoaderShared) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                match queue.jobs.pop() {
+                    Some(job) => break job,
+                    None => shared.available.wait(&mut queue)
+                }
+            }
+        };
+        // a panicking load resolves its future as cancelled, the worker carries on
+        if catch_unwind(AssertUnwindSafe(job.job)).is_err() {
+            error!("Background load panicked");
+        }
+    }
+}
+
+impl std::fmt::Debug for Loader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Loader({} threads, {} pending)", self.thread_count(), self.pending())
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // queued loads are dropped, which resolves their futures as cancelled
+        let jobs = {
+            let mut queue = self.shared.queue.lock();
+            queue.shutdown = true;
+            std::mem::take(&mut queue.jobs)
+        };
+        drop(jobs);
+        self.shared.available.notify_all();
+        let current = thread::current().id();
+        for worker in self.workers.drain(..) {
+            // the registry can be dropped from inside one of its own loads
+            if worker.thread().id() != current {
+                let _ = worker.join();
+            }
+        }
+    }
+}
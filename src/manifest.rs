@@ -0,0 +1,148 @@
+//! Build manifests: a record of every asset a cook produced and where it ended up, for checking
+//! shipped packs for corruption or tampering.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use hashbrown::HashMap;
+use hashbrown::hash_map::Entry;
+use serde::{Serialize, Deserialize};
+
+use crate::asset::AssetId;
+use crate::error::{AssetRegistryError, PackError};
+use crate::pack::{PackIndex, PackEntry, PackKey, entry_hash};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub uid: AssetId,
+    pub path: String,
+    pub tier: Option<String>,
+    /// Content hash of the source file the asset was cooked from.
+    pub source_hash: u64,
+    /// See `pack::entry_hash`.
+    pub derived_hash: u64,
+    /// Hash of the bytes stored in the pack, after compression and encryption.
+    pub stored_hash: u64,
+    /// Pack file holding the entry, which for patch cooks may be the previous pack.
+    pub pack: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl ManifestEntry {
+    pub fn new(entry: &PackEntry, source_hash: u64, pack: &str) -> Self {
+        Self {
+            uid: entry.uid,
+            path: entry.path.clone(),
+            tier: entry.tier.clone(),
+            source_hash,
+            derived_hash: entry.hash,
+            stored_hash: entry.stored_hash,
+            pack: pack.to_string(),
+            offset: entry.offset,
+            size: entry.size,
+        }
+    }
+}
+
+/// Every asset in a cooked build, stored as JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Why an entry failed verification.
+#[derive(Debug)]
+pub enum VerifyFailure {
+    /// The pack couldn't be opened or its index read.
+    Pack(PackError),
+    /// The pack has no entry for this asset.
+    Missing,
+    /// The pack's index disagrees with the manifest about where the entry is.
+    Moved,
+    /// The stored bytes don't match, so the pack is corrupt or was modified.
+    Corrupt,
+    /// The entry unpacked to different data than was cooked.
+    DerivedMismatch,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub failed: Vec<(ManifestEntry, VerifyFailure)>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+fn open_pack(path: &str) -> Result<(File, PackIndex), PackError> {
+    let mut file = File::open(path)?;
+    let index = PackIndex::read(&mut file)?;
+    Ok((file, index))
+}
+
+impl BuildManifest {
+    pub fn read(path: &str) -> Result<Self, AssetRegistryError> {
+        let reader = BufReader::new(File::open(path).map_err(|e| AssetRegistryError::io(path, e))?);
+        serde_json::from_reader(reader).map_err(|source| AssetRegistryError::Manifest { path: path.to_string(), source })
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), AssetRegistryError> {
+        let writer = BufWriter::new(File::create(path).map_err(|e| AssetRegistryError::io(path, e))?);
+        serde_json::to_writer_pretty(writer, self).map_err(|source| AssetRegistryError::Manifest { path: path.to_string(), source })
+    }
+
+    /// Checks every entry against the packs it lives in. Entries are unpacked and compared to
+    /// their derived hash too, unless they're encrypted and `key` is `None`. A pack that can't be
+    /// opened is reported once, with the first of its entries.
+    pub fn verify(&self, key: Option<&PackKey>) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        let mut packs: HashMap<&str, Option<(File, PackIndex)>> = HashMap::new();
+        for entry in &self.entries {
+            report.checked += 1;
+            let pack = match packs.entry(entry.pack.as_str()) {
+                Entry::Occupied(pack) => pack.into_mut(),
+                Entry::Vacant(slot) => match open_pack(&entry.pack) {
+                    Ok(pack) => slot.insert(Some(pack)),
+                    Err(e) => {
+                        report.failed.push((entry.clone(), VerifyFailure::Pack(e)));
+                        slot.insert(None)
+                    }
+                }
+            };
+            if let Some((file, index)) = pack {
+                if let Err(failure) = verify_entry(entry, file, index, key) {
+                    report.failed.push((entry.clone(), failure));
+                }
+            }
+        }
+        report
+    }
+}
+
+fn verify_entry(entry: &ManifestEntry, file: &mut File, index: &PackIndex, key: Option<&PackKey>) -> Result<(), VerifyFailure> {
+    let packed = match index.entries.iter().find(|e| e.path == entry.path && e.tier == entry.tier) {
+        Some(packed) => packed,
+        None => return Err(VerifyFailure::Missing)
+    };
+    if packed.uid != entry.uid || packed.offset != entry.offset || packed.size != entry.size {
+        return Err(VerifyFailure::Moved);
+    }
+    let mut stored = vec![0u8; entry.size as usize];
+    file.seek(SeekFrom::Start(entry.offset)).and_then(|_| file.read_exact(&mut stored))
+        .map_err(|e| VerifyFailure::Pack(PackError::Io(e)))?;
+    if twox_hash::XxHash64::oneshot(0, &stored) != entry.stored_hash {
+        return Err(VerifyFailure::Corrupt);
+    }
+    if packed.nonce.is_some() && key.is_none() {
+        return Ok(());
+    }
+    let bytes = packed.unpack(&stored, key).map_err(VerifyFailure::Pack)?;
+    match entry_hash(&packed.data, &bytes) == entry.derived_hash {
+        true => Ok(()),
+        false => Err(VerifyFailure::DerivedMismatch)
+    }
+}
@@ -0,0 +1,72 @@
+use hashbrown::HashMap;
+
+use crate::asset::{Asset, AssetId, AssetType};
+use crate::stats::parent_directories;
+
+
+/// Memory held for one asset.
+#[derive(Debug, Clone)]
+pub struct AssetMemory {
+    pub uid: AssetId,
+    /// Path relative to the content root.
+    pub path: String,
+    pub asset_type: AssetType,
+    /// Decoded data kept in CPU memory.
+    pub cpu_bytes: usize,
+    /// Cached GPU resources.
+    pub gpu_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryTotals {
+    pub cpu_bytes: usize,
+    pub gpu_bytes: usize,
+}
+
+impl MemoryTotals {
+    pub fn total(&self) -> usize {
+        self.cpu_bytes + self.gpu_bytes
+    }
+
+    fn add(&mut self, asset: &AssetMemory) {
+        self.cpu_bytes += asset.cpu_bytes;
+        self.gpu_bytes += asset.gpu_bytes;
+    }
+}
+
+
+/// Snapshot of the memory held by a registry, returned by `AssetRegistry::memory_report`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryReport {
+    /// Every asset holding any memory, largest first.
+    pub assets: Vec<AssetMemory>,
+    /// Totals for each directory, including everything below it. The content root is `""`.
+    pub by_directory: HashMap<String, MemoryTotals>,
+    pub by_type: HashMap<AssetType, MemoryTotals>,
+    pub total: MemoryTotals,
+}
+
+impl MemoryReport {
+    pub(crate) fn add(&mut self, path: &str, asset: &Asset, gpu_bytes: usize) {
+        let entry = AssetMemory {
+            uid: asset.uid,
+            path: path.to_string(),
+            asset_type: asset.data.asset_type(),
+            cpu_bytes: asset.data.cpu_bytes(),
+            gpu_bytes,
+        };
+        if entry.cpu_bytes == 0 && entry.gpu_bytes == 0 {
+            return;
+        }
+        self.total.add(&entry);
+        self.by_type.entry(entry.asset_type).or_default().add(&entry);
+        for dir in parent_directories(path) {
+            self.by_directory.entry(dir.to_string()).or_default().add(&entry);
+        }
+        self.assets.push(entry);
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.assets.sort_by_key(|a| std::cmp::Reverse(a.cpu_bytes + a.gpu_bytes));
+    }
+}
@@ -0,0 +1,80 @@
+use std::cmp::Reverse;
+use std::ops::AddAssign;
+use std::time::Duration;
+use hashbrown::HashMap;
+
+use crate::asset::AssetId;
+
+
+/// Time spent in each pipeline stage for one asset, or summed over many. Stages the pipeline
+/// doesn't perform for an asset stay at zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetTimings {
+    /// Reading the source file, hashing it, and parsing its header during a scan.
+    pub import: Duration,
+    /// Decoding the source into pixels.
+    pub decode: Duration,
+    pub compression: Duration,
+    pub mip_generation: Duration,
+    /// Creating the GPU image and copying pixels into it.
+    pub upload: Duration,
+}
+
+impl AssetTimings {
+    pub fn total(&self) -> Duration {
+        self.import + self.decode + self.compression + self.mip_generation + self.upload
+    }
+}
+
+impl AddAssign for AssetTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.import += other.import;
+        self.decode += other.decode;
+        self.compression += other.compression;
+        self.mip_generation += other.mip_generation;
+        self.upload += other.upload;
+    }
+}
+
+
+/// Per-asset pipeline timings, kept for as long as the registry is alive.
+#[derive(Debug, Clone, Default)]
+pub struct RegistryMetrics {
+    pub timings: HashMap<AssetId, AssetTimings>,
+}
+
+impl RegistryMetrics {
+    pub fn get(&self, uid: AssetId) -> Option<&AssetTimings> {
+        self.timings.get(&uid)
+    }
+
+    /// Sum of every asset's timings.
+    pub fn totals(&self) -> AssetTimings {
+        let mut totals = AssetTimings::default();
+        for timings in self.timings.values() {
+            totals += *timings;
+        }
+        totals
+    }
+
+    /// The `count` assets with the highest total time, slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<(AssetId, AssetTimings)> {
+        let mut all: Vec<(AssetId, AssetTimings)> = self.timings.iter().map(|(uid, t)| (*uid, *t)).collect();
+        all.sort_by_key(|(_, t)| Reverse(t.total()));
+        all.truncate(count);
+        all
+    }
+
+    pub fn clear(&mut self) {
+        self.timings.clear();
+    }
+
+    /// Starts a fresh record for an asset that was just (re)imported.
+    pub(crate) fn record_import(&mut self, uid: AssetId, import: Duration) {
+        self.timings.insert(uid, AssetTimings { import, ..AssetTimings::default() });
+    }
+
+    pub(crate) fn entry(&mut self, uid: AssetId) -> &mut AssetTimings {
+        self.timings.entry(uid).or_default()
+    }
+}
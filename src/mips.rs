@@ -0,0 +1,198 @@
+//! Mip chains for uploaded textures. Each texture picks where its chain is generated with
+//! `TextureMetadata::gpu_mips`: on the CPU before upload, or on the GPU with a chain of blits right
+//! after it, which is far faster for big textures.
+
+use std::sync::Arc;
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder, CommandBuffer, CommandBufferExecFuture};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{AcceptsPixels, FormatDesc};
+use vulkano::image::{Dimensions, ImageAccess, ImageLayout, ImageUsage, ImmutableImage, MipmapsCount};
+use vulkano::image::immutable::ImmutableImageInitialization;
+use vulkano::sampler::Filter;
+use vulkano::sync::NowFuture;
+use tracing::debug_span;
+
+use crate::color::resize_rgba8;
+use crate::dither::DitherMode;
+use crate::error::UploadError;
+use crate::texture::{MipGenSettings, TextureMetadata, pack_channels};
+
+
+/// Number of mip levels of a texture, down to 1x1.
+pub fn mip_count(size: [u32; 2]) -> u8 {
+    (32 - size[0].max(size[1]).max(1).leading_zeros()) as u8
+}
+
+/// Size of a mip level of a texture.
+pub fn mip_size(size: [u32; 2], mip: u8) -> [u32; 2] {
+    [(size[0] >> mip).max(1), (size[1] >> mip).max(1)]
+}
+
+/// Bytes in a mip level of block compressed data, with 4x4 texels per block.
+pub fn compressed_size(size: [u32; 2], block_bytes: usize) -> usize {
+    size[0].div_ceil(4) as usize * size[1].div_ceil(4) as usize * block_bytes
+}
+
+/// Every level of a texture's mip chain, starting with `pixels` itself, each scaled down from the
+/// one before with `filter`, back to back. `None` if `pixels` doesn't match `size`.
+pub fn generate_mips(pixels: &[u8], size: [u32; 2], srgb: bool, filter: &MipGenSettings) -> Option<Vec<u8>> {
+    let mut chain = pixels.to_vec();
+    let mut previous = 0..pixels.len();
+    for mip in 1..mip_count(size) {
+        let (from, to) = (mip_size(size, mip - 1), mip_size(size, mip));
+        let level = match filter {
+            MipGenSettings::Nearest => downscale_nearest(&chain[previous.clone()], from, to)?,
+            MipGenSettings::NoMipmaps | MipGenSettings::Linear | MipGenSettings::Sharpen | MipGenSettings::Blur => {
+                resize_rgba8(&chain[previous.clone()], from, to, srgb, DitherMode::None)?
+            }
+        };
+        previous = chain.len()..chain.len() + level.len();
+        chain.extend_from_slice(&level);
+    }
+    Some(chain)
+}
+
+/// Scales RGBA8 pixels down by picking the source pixel under each one, like a nearest blit.
+fn downscale_nearest(pixels: &[u8], from: [u32; 2], to: [u32; 2]) -> Option<Vec<u8>> {
+    if pixels.len() != (from[0] * from[1]) as usize * 4 {
+        return None;
+    }
+    let mut scaled = Vec::with_capacity((to[0] * to[1]) as usize * 4);
+    for y in 0..to[1] {
+        let source_y = ((y as u64 * 2 + 1) * from[1] as u64 / (to[1] as u64 * 2)) as u32;
+        for x in 0..to[0] {
+            let source_x = ((x as u64 * 2 + 1) * from[0] as u64 / (to[0] as u64 * 2)) as u32;
+            let start = ((source_y * from[0] + source_x) * 4) as usize;
+            scaled.extend_from_slice(&pixels[start..start + 4]);
+        }
+    }
+    Some(scaled)
+}
+
+
+/// An image and the future of the commands filling it.
+pub(crate) type ImageUpload<F> = (Arc<ImmutableImage<F>>, CommandBufferExecFuture<NowFuture, AutoCommandBuffer>);
+
+/// Uploads tightly packed RGBA8 pixels with a full mip chain, generated where the settings ask for
+/// it, keeping the first `channels` channels. The returned future isn't flushed yet.
+pub(crate) fn upload_with_mips<F>(pixels: &[u8], settings: &TextureMetadata, format: F, channels: usize, queue: &Arc<Queue>, path: &str) -> Result<ImageUpload<F>, UploadError>
+    where F: FormatDesc + AcceptsPixels<u8> + Clone + Send + Sync + 'static
+{
+    let image_error = |source| UploadError::ImageCreation { path: path.to_string(), source };
+    let command_error = |reason: &dyn std::fmt::Display| UploadError::Commands { path: path.to_string(), reason: reason.to_string() };
+    let device = queue.device();
+    let size = settings.max_ingame_size;
+    let usage = ImageUsage {
+        transfer_source: settings.gpu_mips,
+        transfer_destination: true,
+        sampled: true,
+        ..ImageUsage::none()
+    };
+    let (image, init) = ImmutableImage::uninitialized(device.clone(), settings.dimensions(), format.clone(), MipmapsCount::Log2,
+        usage, ImageLayout::ShaderReadOnlyOptimal, device.active_queue_families()).map_err(image_error)?;
+    let init = Arc::new(init);
+    let levels = image.mipmap_levels() as u8;
+    let mut builder = AutoCommandBufferBuilder::new(device.clone(), queue.family()).map_err(|e| command_error(&e))?;
+
+    match settings.gpu_mips {
+        false => {
+            let chain = match debug_span!("generate_mips", path).in_scope(|| generate_mips(pixels, size, settings.srgb, &settings.mip_gen_settings)) {
+                Some(chain) => pack_channels(&chain, channels),
+                None => return Err(UploadError::SizeMismatch(path.to_string()))
+            };
+            let buffer = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::transfer_source(), chain.into_iter())
+                .map_err(|e| image_error(e.into()))?;
+            let mut offset = 0;
+            for mip in 0..levels {
+                let [width, height] = mip_size(size, mip);
+                let length = (width * height) as usize * channels;
+                let level = buffer.clone().into_buffer_slice().slice(offset..offset + length).unwrap();
+                builder = builder.copy_buffer_to_image_dimensions(level, init.clone(), [0, 0, 0], [width, height, 1], 0, 1, mip as u32)
+                    .map_err(|e| command_error(&e))?;
+                offset += length;
+            }
+        },
+        true => {
+            let buffer = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::transfer_source(), pack_channels(pixels, channels).into_iter())
+                .map_err(|e| image_error(e.into()))?;
+            let filter = match settings.mip_gen_settings {
+                MipGenSettings::Nearest => Filter::Nearest,
+                _ => Filter::Linear
+            };
+            // blits between levels of the same image aren't supported, so each level is blitted
+            // from a copy of the one before in a temporary image
+            let mut previous = temporary_image(device, size, format.clone()).map_err(image_error)?;
+            builder = builder
+                .copy_buffer_to_image_dimensions(buffer.clone(), init.clone(), [0, 0, 0], [size[0], size[1], 1], 0, 1, 0)
+                .and_then(|builder| builder.copy_buffer_to_image_dimensions(buffer, previous.clone(), [0, 0, 0], [size[0], size[1], 1], 0, 1, 0))
+                .map_err(|e| command_error(&e))?;
+            for mip in 1..levels {
+                let from = corner(mip_size(size, mip - 1));
+                let to = corner(mip_size(size, mip));
+                builder = builder.blit_image(previous.clone(), [0, 0, 0], from, 0, 0, init.clone(), [0, 0, 0], to, 0, mip as u32, 1, filter)
+                    .map_err(|e| command_error(&e))?;
+                if mip + 1 < levels {
+                    let next = temporary_image(device, mip_size(size, mip), format.clone()).map_err(image_error)?;
+                    builder = builder.blit_image(previous, [0, 0, 0], from, 0, 0, next.clone(), [0, 0, 0], to, 0, 0, 1, filter)
+                        .map_err(|e| command_error(&e))?;
+                    previous = next;
+                }
+            }
+        }
+    }
+
+    let command_buffer = builder.build().map_err(|e| command_error(&e))?;
+    let future = command_buffer.execute(queue.clone()).map_err(|e| command_error(&e))?;
+    Ok((image, future))
+}
+
+/// Uploads block compressed data as it is. Mips can't be generated from it, so it has
+/// `num_mips` levels stored back to back, or just the full size one if that's 0.
+pub(crate) fn upload_blocks<F>(blocks: &[u8], settings: &TextureMetadata, format: F, block_bytes: usize, queue: &Arc<Queue>, path: &str) -> Result<ImageUpload<F>, UploadError>
+    where F: FormatDesc + AcceptsPixels<u8> + Send + Sync + 'static
+{
+    let image_error = |source| UploadError::ImageCreation { path: path.to_string(), source };
+    let command_error = |reason: &dyn std::fmt::Display| UploadError::Commands { path: path.to_string(), reason: reason.to_string() };
+    let device = queue.device();
+    let size = settings.max_ingame_size;
+    let levels = settings.num_mips.clamp(1, mip_count(size));
+    let level_lengths: Vec<usize> = (0..levels).map(|mip| compressed_size(mip_size(size, mip), block_bytes)).collect();
+    if level_lengths.iter().sum::<usize>() != blocks.len() {
+        return Err(UploadError::SizeMismatch(path.to_string()));
+    }
+    let usage = ImageUsage { transfer_destination: true, sampled: true, ..ImageUsage::none() };
+    let (image, init) = ImmutableImage::uninitialized(device.clone(), settings.dimensions(), format, MipmapsCount::Specific(levels as u32),
+        usage, ImageLayout::ShaderReadOnlyOptimal, device.active_queue_families()).map_err(image_error)?;
+    let init = Arc::new(init);
+    let buffer = CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::transfer_source(), blocks.iter().cloned())
+        .map_err(|e| image_error(e.into()))?;
+    let mut builder = AutoCommandBufferBuilder::new(device.clone(), queue.family()).map_err(|e| command_error(&e))?;
+    let mut offset = 0;
+    for (mip, length) in level_lengths.into_iter().enumerate() {
+        let [width, height] = mip_size(size, mip as u8);
+        let level = buffer.clone().into_buffer_slice().slice(offset..offset + length).unwrap();
+        builder = builder.copy_buffer_to_image_dimensions(level, init.clone(), [0, 0, 0], [width, height, 1], 0, 1, mip as u32)
+            .map_err(|e| command_error(&e))?;
+        offset += length;
+    }
+    let command_buffer = builder.build().map_err(|e| command_error(&e))?;
+    let future = command_buffer.execute(queue.clone()).map_err(|e| command_error(&e))?;
+    Ok((image, future))
+}
+
+/// A single level image that can be copied and blitted to and from, alive until the command
+/// buffer using it finishes.
+fn temporary_image<F>(device: &Arc<Device>, size: [u32; 2], format: F) -> Result<Arc<ImmutableImageInitialization<F>>, vulkano::image::ImageCreationError>
+    where F: FormatDesc + Send + Sync + 'static
+{
+    let usage = ImageUsage { transfer_source: true, transfer_destination: true, ..ImageUsage::none() };
+    let dimensions = Dimensions::Dim2d { width: size[0], height: size[1] };
+    let (_, init) = ImmutableImage::uninitialized(device.clone(), dimensions, format, MipmapsCount::One,
+        usage, ImageLayout::TransferSrcOptimal, device.active_queue_families())?;
+    Ok(Arc::new(init))
+}
+
+fn corner(size: [u32; 2]) -> [i32; 3] {
+    [size[0] as i32, size[1] as i32, 1]
+}
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::path::AssetPath;
+
+
+/// Separates a mount's name from the rest of a virtual path, as in `engine://textures/grid.png`.
+pub const MOUNT_SEPARATOR: &str = "://";
+
+/// A directory layered over a mount, whose files shadow the mount's files at the same path. Used
+/// for mods and patches that replace content without touching the original files.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    pub root: PathBuf,
+    /// Overlays with higher priority win over lower ones.
+    pub priority: i32,
+}
+
+/// A directory mounted into the registry's virtual tree. Paths of its assets start with the mount's
+/// prefix. The registry's base path is mounted with an empty name, so its assets have plain paths.
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub name: String,
+    pub root: PathBuf,
+    /// Highest priority first.
+    pub overlays: Vec<Overlay>,
+}
+
+impl Mount {
+    pub fn new(name: &str, root: &Path) -> Self {
+        Self { name: name.to_string(), root: root.to_path_buf(), overlays: Vec::new() }
+    }
+
+    /// Adds an overlay, replacing any existing overlay with the same root.
+    pub fn add_overlay(&mut self, root: &Path, priority: i32) {
+        self.overlays.retain(|o| o.root != root);
+        self.overlays.push(Overlay { root: root.to_path_buf(), priority });
+        // stable, so equal priorities keep the order they were added in
+        self.overlays.sort_by_key(|o| std::cmp::Reverse(o.priority));
+    }
+
+    /// Every directory contributing to the mount, from the highest priority overlay down to the
+    /// mount's own root.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.overlays.iter().map(|o| o.root.as_path()).chain(std::iter::once(self.root.as_path()))
+    }
+
+    /// The file a path below the mount resolves to: the first overlay that has it, or the mount's
+    /// own root.
+    pub fn resolve(&self, relative: &str) -> PathBuf {
+        for overlay in self.overlays.iter() {
+            let path = join_relative(&overlay.root, relative);
+            if archive::exists(&path) {
+                return path;
+            }
+        }
+        join_relative(&self.root, relative)
+    }
+
+    /// `name://`, or nothing for the base mount.
+    pub fn prefix(&self) -> String {
+        if self.name.is_empty() { String::new() } else { format!("{}{}", self.name, MOUNT_SEPARATOR) }
+    }
+
+    /// Virtual path of a file on disk below one of the mount's roots.
+    pub fn virtual_path(&self, source_path: &Path) -> Option<String> {
+        let relative = self.roots().find_map(|root| source_path.strip_prefix(root).ok())?;
+        let relative = relative.to_string_lossy().replace("\\", "/");
+        Some(format!("{}{}", self.prefix(), relative.trim_matches('/')))
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+}
+
+/// `root.join(relative)`, except that an empty `relative` doesn't add a trailing separator, which
+/// would stop archive roots from being recognized as files.
+pub fn join_relative(root: &Path, relative: &str) -> PathBuf {
+    if relative.is_empty() { root.to_path_buf() } else { root.join(relative) }
+}
+
+/// Splits a virtual path into its mount name and the path below the mount. The base mount's name
+/// is `""`.
+pub fn split_mount(path: &str) -> (&str, &str) {
+    match path.find(MOUNT_SEPARATOR) {
+        Some(i) => (&path[..i], &path[i + MOUNT_SEPARATOR.len()..]),
+        None => ("", path)
+    }
+}
+
+/// Splits a virtual path into file tree segments, the same way as `path.split('/')` except that a
+/// mount prefix is kept whole as the first segment.
+pub fn path_segments(path: &str) -> Vec<&str> {
+    let (name, rest) = split_mount(path);
+    let mut segments = Vec::new();
+    if !name.is_empty() {
+        segments.push(&path[..name.len() + MOUNT_SEPARATOR.len()]);
+    }
+    segments.extend(rest.split('/'));
+    segments
+}
+
+/// Appends a tree segment to a virtual path.
+pub fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() || prefix.ends_with(MOUNT_SEPARATOR) {
+        format!("{}{}", prefix, name)
+    }
+    else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// The canonical form of a virtual path, see `AssetPath`.
+pub fn normalize_path(path: &str) -> String {
+    AssetPath::new(path).into_string()
+}
+
+/// Whether `path` is `directory` or somewhere below it. Everything is below `""`.
+pub fn is_within(path: &str, directory: &str) -> bool {
+    if directory.is_empty() || path == directory {
+        return true;
+    }
+    if directory.ends_with(MOUNT_SEPARATOR) {
+        path.starts_with(directory)
+    }
+    else {
+        path.len() > directory.len() && path.starts_with(directory) && path[directory.len()..].starts_with('/')
+    }
+}
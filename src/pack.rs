@@ -0,0 +1,324 @@
+//! Cooked pack files: processed asset data ready to use at runtime, without the sources or the
+//! import pipeline.
+//!
+//! Layout: a fixed header, then each entry's data starting on a `PACK_ALIGNMENT` boundary, then
+//! the bincode-encoded index. The header points at the index, so a reader only has to load the
+//! index up front.
+//!
+//! An asset can have several entries, one for its base data and one per quality tier variant.
+//!
+//! A patch pack holds only the entries that changed since an earlier pack, plus the entries removed
+//! since then, and is loaded on top of it.
+//!
+//! Entries can be encrypted with XChaCha20-Poly1305 after compression. The index itself is left in
+//! the clear, so paths and UIDs stay visible.
+
+use std::convert::TryInto;
+use std::fmt::{Debug, Formatter};
+use std::hash::Hasher;
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce, KeyInit, AeadCore};
+use chacha20poly1305::aead::{Aead, OsRng};
+use serde::{Serialize, Deserialize};
+use flate2::Compression as DeflateLevel;
+use flate2::write::DeflateEncoder;
+use flate2::read::DeflateDecoder;
+use vulkano::format::FormatTy;
+use tracing::debug_span;
+
+use crate::asset::{AssetId, AssetType};
+use crate::error::PackError;
+use crate::texture::TextureMetadata;
+
+
+pub const PACK_MAGIC: [u8; 4] = *b"PDPK";
+/// Bump this whenever the pack layout or anything in the index changes.
+pub const PACK_VERSION: u32 = 11;
+/// Entry data starts on multiples of this, so it can be copied straight into GPU staging buffers.
+pub const PACK_ALIGNMENT: u64 = 256;
+pub const PACK_EXTENSION: &str = "pack";
+
+/// magic, version, index offset, index size
+pub const HEADER_SIZE: u64 = 4 + 4 + 8 + 8;
+
+
+/// How an entry's data is compressed inside the pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PackCompression {
+    None,
+    Deflate,
+    /// Fastest to decompress, for platforms where load times matter more than size.
+    Lz4,
+    /// Smallest, for builds that are downloaded.
+    Zstd,
+}
+
+impl PackCompression {
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            PackCompression::None => Ok(data.to_vec()),
+            PackCompression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), DeflateLevel::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            PackCompression::Lz4 => Ok(lz4_flex::block::compress(data)),
+            PackCompression::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8], uncompressed_size: u64) -> io::Result<Vec<u8>> {
+        match self {
+            PackCompression::None => Ok(data.to_vec()),
+            PackCompression::Deflate => {
+                let mut bytes = Vec::with_capacity(uncompressed_size as usize);
+                DeflateDecoder::new(data).read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            PackCompression::Lz4 => {
+                lz4_flex::block::decompress(data, uncompressed_size as usize)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            PackCompression::Zstd => zstd::bulk::decompress(data, uncompressed_size as usize),
+        }
+    }
+}
+
+/// Key for encrypting pack entries, supplied to the cooker and again at runtime to read them.
+#[derive(Clone)]
+pub struct PackKey([u8; 32]);
+
+impl PackKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+
+    /// Encrypts `data` under a fresh random nonce.
+    pub fn encrypt(&self, data: &[u8]) -> Result<(Vec<u8>, [u8; 24]), PackError> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let encrypted = self.cipher().encrypt(&nonce, data).map_err(|_| PackError::Decrypt)?;
+        Ok((encrypted, nonce.into()))
+    }
+
+    /// Decrypts data from `encrypt`, failing if it was tampered with or the key is wrong.
+    pub fn decrypt(&self, data: &[u8], nonce: &[u8; 24]) -> Result<Vec<u8>, PackError> {
+        self.cipher().decrypt(XNonce::from_slice(nonce), data).map_err(|_| PackError::Decrypt)
+    }
+}
+
+// keeps the key out of logs
+impl Debug for PackKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PackKey(..)")
+    }
+}
+
+/// What kind of asset an entry holds, with the settings needed to use its data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PackedData {
+    /// Tightly packed RGBA8 pixels, or for block compressed formats, the blocks of each mip level
+    /// back to back, see `TextureMetadata::format` and `num_mips`.
+    Texture(TextureMetadata),
+}
+
+impl PackedData {
+    pub fn asset_type(&self) -> AssetType {
+        match self {
+            PackedData::Texture(_) => AssetType::Texture,
+        }
+    }
+
+    /// Whether compressing this data is worth it. Block-compressed textures barely shrink and
+    /// would just cost time to decompress.
+    pub fn is_compressible(&self) -> bool {
+        match self {
+            PackedData::Texture(settings) => settings.format.ty() != FormatTy::Compressed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackEntry {
+    pub uid: AssetId,
+    /// Virtual path the asset was cooked from.
+    pub path: String,
+    /// Quality tier this variant was cooked for, or `None` for the base data.
+    pub tier: Option<String>,
+    /// Start of the entry's data from the beginning of the pack.
+    pub offset: u64,
+    /// Bytes stored in the pack.
+    pub size: u64,
+    pub uncompressed_size: u64,
+    pub compression: PackCompression,
+    /// Set if the entry is encrypted.
+    pub nonce: Option<[u8; 24]>,
+    /// See `entry_hash`.
+    pub hash: u64,
+    /// Hash of the bytes stored in the pack, after compression and encryption.
+    pub stored_hash: u64,
+    pub data: PackedData,
+}
+
+impl PackEntry {
+    /// Turns the bytes stored for this entry back into the cooked data.
+    pub fn unpack(&self, stored: &[u8], key: Option<&PackKey>) -> Result<Vec<u8>, PackError> {
+        match (&self.nonce, key) {
+            (None, _) => Ok(self.compression.decompress(stored, self.uncompressed_size)?),
+            (Some(nonce), Some(key)) => {
+                let compressed = key.decrypt(stored, nonce)?;
+                Ok(self.compression.decompress(&compressed, self.uncompressed_size)?)
+            },
+            (Some(_), None) => Err(PackError::MissingKey),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PackIndex {
+    pub entries: Vec<PackEntry>,
+    /// For patch packs, the paths and tiers of entries removed since the pack being patched.
+    pub removed: Vec<(String, Option<String>)>,
+}
+
+impl PackIndex {
+    /// Reads the index of a pack, leaving the entry data alone.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, PackError> {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        let (index_offset, index_size) = Self::read_header(&header)?;
+        // checked before allocating, so a corrupt header can't ask for more than the pack holds
+        let length = reader.seek(SeekFrom::End(0))?;
+        match index_offset.checked_add(index_size) {
+            Some(end) if end <= length => {},
+            _ => return Err(PackError::Truncated)
+        }
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut index = vec![0u8; index_size as usize];
+        reader.read_exact(&mut index)?;
+        Self::decode(&index)
+    }
+
+    /// Checks a pack's first `HEADER_SIZE` bytes, returning the offset and size of its index.
+    pub fn read_header(header: &[u8]) -> Result<(u64, u64), PackError> {
+        if header.len() < HEADER_SIZE as usize {
+            return Err(PackError::Truncated);
+        }
+        if header[0..4] != PACK_MAGIC {
+            return Err(PackError::BadMagic);
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != PACK_VERSION {
+            return Err(PackError::UnsupportedVersion(version));
+        }
+        let index_offset = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let index_size = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        Ok((index_offset, index_size))
+    }
+
+    /// Decodes an index read from where the header points.
+    pub fn decode(index: &[u8]) -> Result<Self, PackError> {
+        bincode::deserialize(index).map_err(PackError::Index)
+    }
+}
+
+/// Hash of an entry's settings and uncompressed data, for telling whether it changed between
+/// cooks. Unaffected by compression and encryption.
+pub fn entry_hash(data: &PackedData, bytes: &[u8]) -> u64 {
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    // serializing plain settings structs can't fail
+    hasher.write(&bincode::serialize(data).unwrap());
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+
+/// Writes a pack entry by entry. Nothing is readable until `finish` writes the index.
+pub struct PackWriter<W: Write + Seek> {
+    writer: W,
+    position: u64,
+    index: PackIndex,
+    key: Option<PackKey>,
+}
+
+impl<W: Write + Seek> PackWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, PackError> {
+        // the header is filled in by `finish`, once the index location is known
+        writer.write_all(&[0u8; HEADER_SIZE as usize])?;
+        Ok(Self { writer, position: HEADER_SIZE, index: PackIndex::default(), key: None })
+    }
+
+    /// Encrypts every entry added from now on.
+    pub fn set_key(&mut self, key: PackKey) {
+        self.key = Some(key);
+    }
+
+    /// Compresses (and encrypts, with a key set) `bytes` and appends them as a new entry.
+    pub fn add(&mut self, uid: AssetId, path: &str, tier: Option<&str>, data: PackedData, bytes: &[u8], compression: PackCompression) -> Result<(), PackError> {
+        let hash = entry_hash(&data, bytes);
+        let compressed = {
+            let _span = debug_span!("compress", path, bytes = bytes.len(), codec = ?compression).entered();
+            compression.compress(bytes)?
+        };
+        let (stored, nonce) = match &self.key {
+            Some(key) => {
+                let (encrypted, nonce) = key.encrypt(&compressed)?;
+                (encrypted, Some(nonce))
+            },
+            None => (compressed, None)
+        };
+        let padding = (PACK_ALIGNMENT - self.position % PACK_ALIGNMENT) % PACK_ALIGNMENT;
+        self.writer.write_all(&vec![0u8; padding as usize])?;
+        self.position += padding;
+        self.writer.write_all(&stored)?;
+        self.index.entries.push(PackEntry {
+            uid,
+            path: path.to_string(),
+            tier: tier.map(str::to_string),
+            offset: self.position,
+            size: stored.len() as u64,
+            uncompressed_size: bytes.len() as u64,
+            compression,
+            nonce,
+            hash,
+            stored_hash: twox_hash::XxHash64::oneshot(0, &stored),
+            data,
+        });
+        self.position += stored.len() as u64;
+        Ok(())
+    }
+
+    /// Records that an entry in the pack being patched no longer exists.
+    pub fn mark_removed(&mut self, path: &str, tier: Option<&str>) {
+        self.index.removed.push((path.to_string(), tier.map(str::to_string)));
+    }
+
+    /// The entries written so far.
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.index.entries
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.index.entries.len()
+    }
+
+    /// Writes the index and header, returning the total size of the pack.
+    pub fn finish(mut self) -> Result<u64, PackError> {
+        let index = bincode::serialize(&self.index).map_err(PackError::Index)?;
+        let index_offset = self.position;
+        self.writer.write_all(&index)?;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(&PACK_MAGIC);
+        header.extend_from_slice(&PACK_VERSION.to_le_bytes());
+        header.extend_from_slice(&index_offset.to_le_bytes());
+        header.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.flush()?;
+        Ok(index_offset + index.len() as u64)
+    }
+}
@@ -0,0 +1,254 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use hashbrown::HashMap;
+use memmap2::Mmap;
+use parking_lot::RwLock;
+use vulkano::device::Queue;
+use vulkano::sampler::Sampler;
+
+use crate::asset::AssetId;
+use crate::error::{AssetRegistryError, UploadError, PackError};
+use crate::mount::normalize_path;
+use crate::pack::{PackIndex, PackEntry, PackedData, PackKey};
+use crate::stream::RemotePack;
+use crate::texture::{Texture, PendingUploads, UploadFuture, upload_texture_on, pick_upload_queue};
+
+
+#[derive(Debug)]
+enum PackSource {
+    Mapped(Mmap),
+    Remote(RemotePack),
+}
+
+#[derive(Debug)]
+struct LoadedPack {
+    /// File path, or URL for remote packs.
+    path: String,
+    source: PackSource,
+    index: PackIndex,
+}
+
+/// The runtime counterpart to `AssetRegistry`, serving cooked assets straight from pack files.
+/// Adding a pack only reads its index. Nothing here walks directories, decodes source images or
+/// checks timestamps.
+///
+/// Assets with variants for the selected quality tier are served from those, and everything else
+/// from its base data.
+#[derive(Debug)]
+pub struct PackedRegistry {
+    pub queue: Arc<Queue>,
+    /// More queues to spread uploads over, see `AssetRegistry::transfer_queues`.
+    pub transfer_queues: Vec<Arc<Queue>>,
+    next_upload_queue: AtomicUsize,
+    packs: Vec<LoadedPack>,
+    /// Pack and entry index of every entry, by path and tier.
+    variants: HashMap<(String, Option<String>), (usize, usize)>,
+    tier: Option<String>,
+    /// Pack and entry index for each path and UID, for the selected tier.
+    by_path: HashMap<String, (usize, usize)>,
+    by_uid: HashMap<AssetId, (usize, usize)>,
+    cached_textures: RwLock<HashMap<AssetId, Texture>>,
+    pending_uploads: PendingUploads,
+    /// Key for encrypted packs.
+    key: Option<PackKey>,
+}
+
+impl PackedRegistry {
+    pub fn new(queue: Arc<Queue>) -> Self {
+        Self {
+            queue,
+            transfer_queues: Vec::new(),
+            next_upload_queue: AtomicUsize::new(0),
+            packs: Vec::new(),
+            variants: HashMap::new(),
+            tier: None,
+            by_path: HashMap::new(),
+            by_uid: HashMap::new(),
+            cached_textures: RwLock::new(HashMap::new()),
+            pending_uploads: PendingUploads::default(),
+            key: None,
+        }
+    }
+
+    /// Sets the key used to read encrypted entries, which must match the one they were cooked with.
+    pub fn set_key(&mut self, key: PackKey) {
+        self.key = Some(key);
+    }
+
+    /// Selects which quality tier's variants are served, or only base data for `None`.
+    pub fn set_tier(&mut self, tier: Option<&str>) {
+        self.tier = tier.map(str::to_string);
+        self.cached_textures.get_mut().clear();
+        self.select_variants();
+    }
+
+    pub fn tier(&self) -> Option<&str> {
+        self.tier.as_deref()
+    }
+
+    pub fn open(pack_path: &str, queue: Arc<Queue>) -> Result<Self, AssetRegistryError> {
+        let mut registry = Self::new(queue);
+        registry.add_pack(pack_path)?;
+        Ok(registry)
+    }
+
+    /// Adds a pack's assets. Assets in later packs replace assets with the same path or UID in
+    /// earlier ones, and patch packs also remove the assets they list as removed.
+    pub fn add_pack(&mut self, pack_path: &str) -> Result<(), AssetRegistryError> {
+        let file = File::open(pack_path).map_err(|e| AssetRegistryError::io(pack_path, e))?;
+        // Safety: packs are written once by the cooker and only read afterwards. Replacing a pack
+        // while it's mapped is unsupported, the same as with any mmap-based loader.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| AssetRegistryError::io(pack_path, e))?;
+        let index = match PackIndex::read(&mut Cursor::new(&map[..])) {
+            Ok(index) => index,
+            Err(source) => return Err(AssetRegistryError::Pack { path: pack_path.to_string(), source })
+        };
+        self.add_loaded(LoadedPack { path: pack_path.to_string(), source: PackSource::Mapped(map), index });
+        Ok(())
+    }
+
+    /// Adds a pack served over HTTP(S), downloading only its index. Entries are downloaded the
+    /// first time they're read and kept in `cache_directory`, see `RemotePack`. Layered the same
+    /// way as `add_pack`, e.g. a DLC pack over the base game's.
+    pub fn add_remote_pack(&mut self, url: &str, cache_directory: &Path) -> Result<(), AssetRegistryError> {
+        let (remote, index) = RemotePack::open(url, cache_directory)
+            .map_err(|source| AssetRegistryError::Pack { path: url.to_string(), source })?;
+        self.add_loaded(LoadedPack { path: url.to_string(), source: PackSource::Remote(remote), index });
+        Ok(())
+    }
+
+    fn add_loaded(&mut self, loaded: LoadedPack) {
+        let index = &loaded.index;
+        let pack = self.packs.len();
+        for removed in &index.removed {
+            self.variants.remove(removed);
+        }
+        for (i, entry) in index.entries.iter().enumerate() {
+            self.variants.insert((entry.path.clone(), entry.tier.clone()), (pack, i));
+        }
+        let cache = self.cached_textures.get_mut();
+        for path in index.removed.iter().map(|(path, _)| path).chain(index.entries.iter().map(|entry| &entry.path)) {
+            if let Some(location) = self.by_path.get(path) {
+                cache.remove(&self.packs[location.0].index.entries[location.1].uid);
+            }
+        }
+        self.packs.push(loaded);
+        self.select_variants();
+    }
+
+    /// Points each path at its variant for the selected tier, or its base data if it has none.
+    fn select_variants(&mut self) {
+        self.by_path.clear();
+        for ((path, tier), location) in self.variants.iter() {
+            let selected = tier.is_some() && *tier == self.tier;
+            if selected || (tier.is_none() && !self.by_path.contains_key(path)) {
+                self.by_path.insert(path.clone(), *location);
+            }
+        }
+        let by_uid = self.by_path.values().map(|location| (self.entry(*location).uid, *location)).collect();
+        self.by_uid = by_uid;
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty()
+    }
+
+    /// Every asset available, with the path it was cooked from.
+    pub fn iter_assets(&self) -> impl Iterator<Item = (&str, &PackEntry)> {
+        self.by_path.iter().map(move |(path, location)| (path.as_str(), self.entry(*location)))
+    }
+
+    pub fn get_asset(&self, path: &str) -> Option<&PackEntry> {
+        self.by_path.get(&normalize_path(path)).map(|location| self.entry(*location))
+    }
+
+    pub fn get_asset_by_uid(&self, uid: AssetId) -> Option<&PackEntry> {
+        self.by_uid.get(&uid).map(|location| self.entry(*location))
+    }
+
+    fn entry(&self, (pack, i): (usize, usize)) -> &PackEntry {
+        &self.packs[pack].index.entries[i]
+    }
+
+    /// Reads, decrypts and decompresses an asset's cooked data.
+    pub fn read_data(&self, path: &str) -> Result<Vec<u8>, UploadError> {
+        match self.by_path.get(&normalize_path(path)) {
+            Some(location) => self.read_location(*location),
+            None => Err(UploadError::NotFound(path.to_string()))
+        }
+    }
+
+    fn read_location(&self, (pack, i): (usize, usize)) -> Result<Vec<u8>, UploadError> {
+        let pack = &self.packs[pack];
+        let entry = &pack.index.entries[i];
+        let pack_error = |source| UploadError::Pack { path: pack.path.clone(), source };
+        let stored = match &pack.source {
+            PackSource::Mapped(map) => match map.get(entry.offset as usize..(entry.offset + entry.size) as usize) {
+                Some(stored) => Cow::Borrowed(stored),
+                None => return Err(pack_error(PackError::Truncated))
+            },
+            PackSource::Remote(remote) => Cow::Owned(remote.read(entry).map_err(pack_error)?)
+        };
+        entry.unpack(&stored, self.key.as_ref()).map_err(pack_error)
+    }
+
+    /// Gets the GPU texture for a cooked texture, uploading it on first use. Join `flush_uploads`
+    /// into the frame before using it.
+    pub fn get_texture(&self, path: &str) -> Result<Texture, UploadError> {
+        match self.by_path.get(&normalize_path(path)) {
+            Some(location) => self.texture_at(*location),
+            None => Err(UploadError::NotFound(path.to_string()))
+        }
+    }
+
+    pub fn get_texture_by_uid(&self, uid: AssetId) -> Result<Texture, UploadError> {
+        match self.by_uid.get(&uid) {
+            Some(location) => self.texture_at(*location),
+            None => Err(UploadError::NotFound(uid.to_string()))
+        }
+    }
+
+    fn texture_at(&self, location: (usize, usize)) -> Result<Texture, UploadError> {
+        let entry = self.entry(location);
+        if let Some(texture) = self.cached_textures.read().get(&entry.uid) {
+            return Ok(texture.clone());
+        }
+        let texture = match &entry.data {
+            PackedData::Texture(settings) => {
+                let pixels = self.read_location(location)?;
+                let queue = pick_upload_queue(&self.queue, &self.transfer_queues, &self.next_upload_queue, settings);
+                let (texture, future) = upload_texture_on(&pixels, settings, queue, &self.queue, &entry.path)?;
+                self.pending_uploads.push(future);
+                texture
+            }
+        };
+        self.cached_textures.write().insert(entry.uid, texture.clone());
+        Ok(texture)
+    }
+
+    /// A new sampler for a cooked texture, set up from its cooked settings.
+    pub fn create_sampler(&self, path: &str) -> Result<Arc<Sampler>, UploadError> {
+        let entry = match self.by_path.get(&normalize_path(path)) {
+            Some(location) => self.entry(*location),
+            None => return Err(UploadError::NotFound(path.to_string()))
+        };
+        match &entry.data {
+            PackedData::Texture(settings) => settings.create_sampler(self.queue.device())
+                .map_err(|source| UploadError::Sampler { path: entry.path.clone(), source })
+        }
+    }
+
+    /// Every upload since the last call, joined into one future for the renderer to wait on, see
+    /// `AssetRegistry::flush_uploads`.
+    pub fn flush_uploads(&self) -> UploadFuture {
+        self.pending_uploads.flush(self.queue.device())
+    }
+}
@@ -0,0 +1,131 @@
+use std::borrow::Borrow;
+use std::fmt::{Display, Formatter, Error};
+use std::ops::Deref;
+use serde::{Serialize, Deserialize};
+
+use crate::mount::{MOUNT_SEPARATOR, split_mount};
+
+
+/// A virtual path to an asset or directory, in canonical form: `/`-separated, relative to the
+/// content root, with any mount prefix (`name://`) intact and no empty, `.` or `..` segments.
+/// Every lookup normalizes through this, so any spelling of a path finds the same asset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AssetPath(String);
+
+impl AssetPath {
+    /// Normalizes a path. `..` never climbs above the content root or the path's mount.
+    pub fn new(path: &str) -> Self {
+        let path = path.replace("\\", "/");
+        let (name, rest) = split_mount(&path);
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in rest.split('/') {
+            match segment {
+                "" | "." => {},
+                ".." => { segments.pop(); },
+                segment => segments.push(segment)
+            }
+        }
+        let rest = segments.join("/");
+        if name.is_empty() { AssetPath(rest) } else { AssetPath(format!("{}{}{}", name, MOUNT_SEPARATOR, rest)) }
+    }
+
+    /// Normalizes a path given by a user, which may also be an absolute path below
+    /// `base_path_absolute`.
+    pub fn from_user(path: &str, base_path_absolute: &str) -> Self {
+        let path = path.replace("\\", "/");
+        let base = base_path_absolute.replace("\\", "/");
+        match (base.is_empty(), path.strip_prefix(base.as_str())) {
+            (false, Some(rest)) => Self::new(rest),
+            _ => Self::new(&path)
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Whether this is the root of the base mount.
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// File tree segments, with a mount prefix kept whole as the first one. Empty for the root.
+    pub fn segments(&self) -> Vec<&str> {
+        let (name, rest) = split_mount(&self.0);
+        let mut segments = Vec::new();
+        if !name.is_empty() {
+            segments.push(&self.0[..name.len() + MOUNT_SEPARATOR.len()]);
+        }
+        segments.extend(rest.split('/').filter(|s| !s.is_empty()));
+        segments
+    }
+
+    /// The last segment, or `""` for the root of a mount.
+    pub fn file_name(&self) -> &str {
+        let (_, rest) = split_mount(&self.0);
+        rest.rsplit('/').next().unwrap_or("")
+    }
+
+    /// The directory containing this path, or `None` for the root of a mount.
+    pub fn parent(&self) -> Option<AssetPath> {
+        let (_, rest) = split_mount(&self.0);
+        if rest.is_empty() {
+            return None;
+        }
+        let mount_len = self.0.len() - rest.len();
+        let end = rest.rfind('/').map_or(mount_len, |i| mount_len + i);
+        Some(AssetPath(self.0[..end].to_string()))
+    }
+
+    /// Appends a path below this one.
+    pub fn join(&self, path: &str) -> AssetPath {
+        if self.0.is_empty() || self.0.ends_with(MOUNT_SEPARATOR) {
+            AssetPath::new(&format!("{}{}", self.0, path))
+        }
+        else {
+            AssetPath::new(&format!("{}/{}", self.0, path))
+        }
+    }
+}
+
+impl Deref for AssetPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for AssetPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for AssetPath {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for AssetPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for AssetPath {
+    fn from(path: &str) -> Self {
+        AssetPath::new(path)
+    }
+}
+
+impl From<AssetPath> for String {
+    fn from(path: AssetPath) -> Self {
+        path.0
+    }
+}
@@ -1,356 +1,2367 @@
-use std::path::Path;
-use walkdir::{WalkDir, DirEntry};
-use std::fmt::{Display, Formatter, Error};
-use hashbrown::HashMap;
-use chrono::{DateTime, Local};
-use toolbelt::color::LinearColor;
-use vulkano::sampler::{SamplerAddressMode, Filter};
-use vulkano::format::Format;
-use image::{ImageDecoder, ColorType};
-use vulkano::image::ImmutableImage;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::BufWriter;
+use hashbrown::{HashMap, HashSet};
+use chrono::Local;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, Instant};
+use std::sync::mpsc::Receiver;
 use vulkano::device::Queue;
-use itertools::Itertools;
+use vulkano::sampler::Sampler;
+use rayon::prelude::*;
+use tracing::{warn, error, info_span, debug_span};
+use parking_lot::{Mutex, RwLock};
 
-use crate::texture::{TextureMetadata, CompressionMode, MipGenSettings, PowerOfTwoMode, ChannelMask, Texture};
-use crate::asset::{Asset, TextureAssetData, AssetData, FileTreeNode};
+use crate::texture::{Texture, TextureMetadata, PendingUploads, UploadFuture, upload_texture_on, pick_upload_queue};
+use crate::descriptor::{Layout, TextureSet, TextureSetKey};
+use crate::asset::{Asset, AssetData, AssetId, AssetType, FileTreeNode, DirEntryInfo, TextureAssetData, uid_from_path, hash_file, content_hash};
+use crate::database::RegistryDatabase;
+use crate::sidecar::Sidecar;
+use crate::scan::{ScanReport, ScanEntry, MovedEntry, ReimportOptions, ScanOptions, ScanProgress, CancellationToken, SymlinkMode};
+use crate::watch::{AssetWatcher, common_ancestor};
+use crate::event::{AssetEvent, EventBus};
+use crate::import::{prepare_import, prepare_import_with, read_sidecar, decode_texture, file_timestamp};
+use crate::error::{AssetRegistryError, ScanError, ImportError, UploadError};
+use crate::metrics::RegistryMetrics;
+use crate::memory::MemoryReport;
+use crate::stats::{DirectoryStats, DuplicateGroup, parent_directories};
+use crate::iter::{AssetIter, DirectoryIter, TreeVisitor, walk};
+use crate::search::{self, SearchMatch};
+use crate::collection::AssetCollection;
+use crate::dependency::DependencyGraph;
+use crate::handle::{Handle, WeakHandle, HandleState, AssetKind};
+use crate::arena::{Arena, SlotId};
+use crate::load::{LoadFuture, BatchReport, Batch, load_channel};
+use crate::loader::{Loader, LoadPriority};
+use crate::archive;
+use crate::pack::{PackWriter, PackIndex, PackEntry, PackedData, entry_hash};
+use crate::cook::{CookOptions, CookProfile, CookReport, compress_texture};
+use crate::derived::{DerivedCache, derived_key};
+use crate::manifest::ManifestEntry;
+use crate::export;
+use crate::validate::{ImportWarning, validate_pixels};
+use crate::gc::{GcOptions, GcReport};
+use crate::thumbnail::{ThumbnailCache, DEFAULT_THUMBNAIL_SIZE, downscale_rgba, thumbnail_key};
+use crate::mount::{Mount, split_mount, join_relative, path_segments, is_within, join_path};
+use crate::path::AssetPath;
+use crate::intern::PathInterner;
+use crate::defaults::{self, BUILTIN_MOUNT};
+use crate::snapshot::{RegistrySnapshot, AssetInfo};
+use crate::ignore::{IgnoreRules, IgnoreSet, IGNORE_FILE_NAME};
+use crate::jobs::{JobGraph, JobStage};
 
 
-#[derive(Debug)]
-pub enum AssetRegistryError {
-    PathDoesNotExist(String),
-    WalkDirError(walkdir::Error),
-    Other(Error)
-}
+/// Aliases followed by one lookup before giving up, in case they form a cycle.
+const MAX_ALIAS_HOPS: usize = 16;
 
-impl Display for AssetRegistryError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        match self {
-            AssetRegistryError::PathDoesNotExist(path) => {
-                write!(f, "Path does not exist: '{}'", path)?;
-            },
-            AssetRegistryError::WalkDirError(e) => {
-                write!(f, "{}", e)?;
-            },
-            AssetRegistryError::Other(e) => {
-                write!(f, "{}", e)?;
-            }
-        }
-        Ok(())
-    }
-}
-impl From<Error> for AssetRegistryError {
-    fn from(e: Error) -> Self {
-        AssetRegistryError::Other(e)
-    }
+
+/// How `rescan` decides whether a file needs to be reprocessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeDetection {
+    /// Reprocess whenever the file's modification time differs from the recorded one.
+    Timestamp,
+    /// When the modification time differs, hash the file's contents and only reprocess if they
+    /// actually changed. Avoids spurious reimports after version control checkouts or copies.
+    ContentHash,
 }
-impl From<walkdir::Error> for AssetRegistryError {
-    fn from(e: walkdir::Error) -> Self {
-        AssetRegistryError::WalkDirError(e)
-    }
+
+
+enum EntryState {
+    Unchanged,
+    /// New or changed, with the UID of the asset it replaces if there is one.
+    NeedsImport(Option<AssetId>),
 }
 
 
+/// Lookups, queries and texture uploads only need `&self`, so the registry can be shared between
+/// systems behind an `Arc`. Scanning and other changes to the tree need `&mut self`.
 #[derive(Debug)]
 pub struct AssetRegistry {
     pub base_path_relative: String,
     pub base_path_absolute: String,
-    pub queue: Arc<Queue>,
+    /// Directories mounted into the tree, starting with the base path under the empty name.
+    pub mounts: Vec<Mount>,
+    /// `None` for headless registries, which can scan and cook but not upload textures.
+    pub queue: Option<Arc<Queue>>,
+    /// More queues on the same device to spread texture uploads over, e.g. from a dedicated
+    /// transfer family, so loading a level isn't bottlenecked on `queue`. Frames on `queue` wait
+    /// for uploads on these through semaphores.
+    pub transfer_queues: Vec<Arc<Queue>>,
+    /// Which of the `transfer_queues` the next upload goes on.
+    next_upload_queue: AtomicUsize,
     pub file_tree: FileTreeNode,
-    pub cached_texture_arcs: HashMap<String, Texture>,
-    pub uid_to_path: HashMap<u64, String>,
+    /// Storage for every asset in the tree, which refers to them by slot.
+    pub assets: Arena<Asset>,
+    pub uid_to_slot: HashMap<AssetId, SlotId>,
+    /// GPU textures uploaded so far, by UID.
+    pub cached_textures: RwLock<HashMap<AssetId, Texture>>,
+    /// Uploads the renderer hasn't waited on yet, see `flush_uploads`.
+    pub pending_uploads: PendingUploads,
+    /// Descriptor sets built by `texture_set`.
+    pub cached_texture_sets: RwLock<HashMap<TextureSetKey, Arc<TextureSet>>>,
+    /// Path of every asset. Paths are interned, shared with the other maps that hold them.
+    pub uid_to_path: HashMap<AssetId, Arc<str>>,
+    pub database_path: Option<String>,
+    pub change_detection: ChangeDetection,
+    /// Which symbolic links scans walk into.
+    pub symlinks: SymlinkMode,
+    /// How deep below a mount's root scans import files, where 1 is only files directly in the
+    /// root. `None` for no limit.
+    pub max_depth: Option<usize>,
+    /// Lowercase file extensions to import, without the dot. `None` imports anything an importer
+    /// supports.
+    pub extensions: Option<HashSet<String>>,
+    /// Patterns ignored on top of `.pipedreamignore` files, see `add_ignore_pattern`.
+    ignore_patterns: IgnoreRules,
+    /// Paths that lookups redirect to other paths, see `add_alias`.
+    aliases: HashMap<String, String>,
+    /// Old path of every moved asset to where it went, so lookups by the old path still find it.
+    /// Dropped once something exists at the old path again.
+    redirects: HashMap<String, String>,
+    pub watcher: Option<AssetWatcher>,
+    pub events: EventBus,
+    /// Worker pool used to decode files during scans. `None` uses rayon's global pool.
+    pub import_pool: Option<Arc<rayon::ThreadPool>>,
+    /// Worker threads for background loads, started on first use.
+    pub loader: OnceLock<Loader>,
+    /// What gameplay code said each asset will be needed for, see `set_streaming_hint`.
+    pub streaming_hints: RwLock<HashMap<AssetId, LoadPriority>>,
+    /// Keep decoded pixels in memory after uploading a texture, instead of decoding from the
+    /// source again if the GPU copy is evicted.
+    pub keep_cpu_copies: bool,
+    /// Upload changed textures again as soon as they change, if they were uploaded before, instead
+    /// of on their next `get_texture`. Subscribers get an `AssetEvent::Swapped` for each, so a
+    /// running game picks up edited files without a restart.
+    pub hot_swap_textures: bool,
+    /// Evict cached textures nothing else holds a clone of once they've gone unused this long,
+    /// freeing their GPU memory. Checked on every `flush_uploads`. `None` keeps them until
+    /// they're evicted by hand.
+    pub texture_grace_period: Option<Duration>,
+    /// When each cached texture was first seen unused, see `release_unused_textures`.
+    texture_unused_since: Mutex<HashMap<AssetId, Instant>>,
+    pub metrics: RwLock<RegistryMetrics>,
+    pub collections: HashMap<String, AssetCollection>,
+    pub dependencies: DependencyGraph,
+    /// Assets whose dependencies changed, to be reprocessed by the next rescan.
+    pub dirty: HashSet<AssetId>,
+    /// Bumped whenever an asset's data changes, for detecting stale `WeakHandle`s.
+    pub versions: HashMap<AssetId, u64>,
+    /// Where cooked data is kept between cooks. Without one, every cook processes every asset.
+    pub derived_cache: Option<DerivedCache>,
+    /// Where `get_thumbnail` keeps generated thumbnails. Without one they're generated every time.
+    pub thumbnail_cache: Option<ThumbnailCache>,
+    /// Whether lookups ignore case, e.g. to match how Windows resolves paths. Set with
+    /// `set_case_insensitive_paths`.
+    case_insensitive_paths: bool,
+    /// Lowercased path of every asset and directory, to its casing in the tree. Only kept while
+    /// paths are case-insensitive.
+    folded_paths: HashMap<String, Arc<str>>,
+    paths: PathInterner,
+    /// Bumped whenever the tree or any asset's metadata changes.
+    generation: u64,
+    /// Built on the first `snapshot` after a change, then shared until the next one.
+    snapshot: RwLock<Option<Arc<RegistrySnapshot>>>,
 }
 
 impl AssetRegistry {
     pub fn new(base_path_relative: &str, base_path_absolute: &str, queue: Arc<Queue>) -> Result<Self, AssetRegistryError> {
+        Self::with_queue(base_path_relative, base_path_absolute, Some(queue))
+    }
+
+    /// Creates a registry without a GPU, for tools that only scan and cook content.
+    pub fn headless(base_path_relative: &str, base_path_absolute: &str) -> Result<Self, AssetRegistryError> {
+        Self::with_queue(base_path_relative, base_path_absolute, None)
+    }
+
+    pub(crate) fn with_queue(base_path_relative: &str, base_path_absolute: &str, queue: Option<Arc<Queue>>) -> Result<Self, AssetRegistryError> {
         if Path::new(base_path_relative).exists() {
-            Ok(Self {
+            let mut registry = Self {
                 queue,
+                transfer_queues: Vec::new(),
+                next_upload_queue: AtomicUsize::new(0),
                 base_path_relative: base_path_relative.to_string(),
                 base_path_absolute: base_path_absolute.to_string(),
+                mounts: vec![Mount::new("", Path::new(base_path_relative))],
                 file_tree: FileTreeNode::Directory(HashMap::new()),
-                cached_texture_arcs: HashMap::new(),
+                assets: Arena::new(),
+                uid_to_slot: HashMap::new(),
+                cached_textures: RwLock::new(HashMap::new()),
+                pending_uploads: PendingUploads::default(),
+                cached_texture_sets: RwLock::new(HashMap::new()),
                 uid_to_path: HashMap::new(),
-            })
+                database_path: None,
+                change_detection: ChangeDetection::Timestamp,
+                symlinks: SymlinkMode::default(),
+                max_depth: None,
+                extensions: None,
+                ignore_patterns: IgnoreRules::default(),
+                aliases: HashMap::new(),
+                redirects: HashMap::new(),
+                watcher: None,
+                events: EventBus::default(),
+                import_pool: None,
+                loader: OnceLock::new(),
+                streaming_hints: RwLock::new(HashMap::new()),
+                keep_cpu_copies: false,
+                hot_swap_textures: false,
+                texture_grace_period: None,
+                texture_unused_since: Mutex::new(HashMap::new()),
+                metrics: RwLock::new(RegistryMetrics::default()),
+                collections: HashMap::new(),
+                dependencies: DependencyGraph::default(),
+                dirty: HashSet::new(),
+                versions: HashMap::new(),
+                derived_cache: None,
+                thumbnail_cache: None,
+                case_insensitive_paths: false,
+                folded_paths: HashMap::new(),
+                paths: PathInterner::default(),
+                generation: 0,
+                snapshot: RwLock::new(None),
+            };
+            registry.register_defaults();
+            Ok(registry)
         }
         else {
             Err(AssetRegistryError::PathDoesNotExist(base_path_relative.to_string()))
         }
     }
 
-    pub fn rescan(&mut self) -> Result<(), AssetRegistryError> {
-        for entry in WalkDir::new(&self.base_path_relative).into_iter()
-                                                  .filter_map(Result::ok)
-                                                  .filter(|e| !e.file_type().is_dir())
-        {
-            let path_segments: Vec<String> = entry.path()
-                .to_str()
-                .unwrap()
-                .to_string()
-                .replace("\\", "/")
-                .split("/")
-                .map(|s| s.to_string())
-                .skip(1)
-                .collect();
-            let segments_copy = path_segments.clone();
-            let all_except_last = path_segments.len() - 1;
-            let path_segments: Vec<String> = path_segments.into_iter().take(all_except_last).collect();
-            let dir_node = self.get_node_and_create_if_none(path_segments);
-
-            // assuming it doesn't exist by default
-            let mut should_process = true;
-
-            // search asset directory entry for file
-            let mut new_id = None;
-            match dir_node {
-                FileTreeNode::File(_) => unreachable!(),
-                FileTreeNode::Directory(ref mut map) => {
-                    'outer: for (_, value) in map.iter() {
-                        match value {
-                            FileTreeNode::Directory(_) => continue,
-                            FileTreeNode::File(asset) => {
-                                if Path::new(&asset.path).file_name().unwrap() == entry.file_name() {
-                                    // found file with the same name
-                                    let file_time = entry.metadata().unwrap().modified().expect("This platform doesn't support file timestamps!");
-                                    let file_time = DateTime::<Local>::from(file_time);
-                                    if asset.timestamp != file_time {
-                                        // timestamps are different, reprocess (true by default)
-                                    }
-                                    else {
-                                        // else timestamps are the same, don't reprocess
-                                        should_process = false;
-                                    }
-                                    break 'outer;
-                                }
-                            }
-                        }
-                    }
-                    // if not found or newer timestamp
-                    if should_process {
-                        let filename = entry.file_name().to_str().unwrap().to_string();
-                        match process_file(&entry) {
-                            Some(new_asset) => {
-                                new_id = Some(new_asset.uid);
-                                map.insert(filename.clone(), FileTreeNode::File(new_asset));
-                            },
-                            None => {} // unsupported file
-                        }
+    /// Creates a registry backed by a database file at `database_path`. If the database exists it
+    /// is loaded, so the first `rescan` only reprocesses files that changed since it was written.
+    /// The database is written back when the registry is dropped, or explicitly via `save_database`.
+    pub fn open(base_path_relative: &str, base_path_absolute: &str, database_path: &str, queue: Arc<Queue>) -> Result<Self, AssetRegistryError> {
+        Self::new(base_path_relative, base_path_absolute, queue)?.with_database(database_path)
+    }
 
-                    }
+    /// `open` for a headless registry.
+    pub fn open_headless(base_path_relative: &str, base_path_absolute: &str, database_path: &str) -> Result<Self, AssetRegistryError> {
+        Self::headless(base_path_relative, base_path_absolute)?.with_database(database_path)
+    }
+
+    pub(crate) fn with_database(mut self, database_path: &str) -> Result<Self, AssetRegistryError> {
+        self.database_path = Some(database_path.to_string());
+        match self.load_database() {
+            Ok(()) => {},
+            Err(AssetRegistryError::Database { source, .. }) => {
+                // a corrupt database is just a cache miss, the next rescan rebuilds it
+                warn!(path = database_path, error = %source, "Discarding unreadable registry database");
+            },
+            Err(e) => return Err(e)
+        }
+        Ok(self)
+    }
+
+    pub fn load_database(&mut self) -> Result<(), AssetRegistryError> {
+        let path = match &self.database_path {
+            Some(path) => path,
+            None => return Ok(())
+        };
+        if let Some(db) = RegistryDatabase::read(path)? {
+            if db.base_path == self.base_path_relative {
+                self.file_tree = db.file_tree;
+                self.assets = db.assets;
+                self.uid_to_slot = self.assets.iter().map(|(slot, asset)| (asset.uid, slot)).collect();
+                let paths = &mut self.paths;
+                self.uid_to_path = db.uid_to_path.iter().map(|(uid, path)| (*uid, paths.intern(path))).collect();
+                self.redirects = db.redirects;
+                self.cached_textures.get_mut().clear();
+                self.cached_texture_sets.get_mut().clear();
+                // their data was never saved
+                let generated: Vec<String> = self.iter_assets().filter(|(_, a)| a.generated).map(|(path, _)| path).collect();
+                for path in generated {
+                    self.remove_asset_at(&path);
                 }
+                self.invalidate_snapshot();
+                self.register_defaults();
+                self.dependencies = DependencyGraph::build(self.iter_assets());
+                self.fold_paths();
             }
-            if let Some(id) = new_id {
-                self.uid_to_path.insert(id, segments_copy.join("/"));
+            else {
+                warn!(path = path.as_str(), base_path = db.base_path.as_str(), "Ignoring registry database written for a different base path");
             }
         }
         Ok(())
     }
 
-    fn get_node_and_create_if_none(&mut self, path_segments: Vec<String>) -> &mut FileTreeNode {
-        let mut iter = path_segments.iter();
-        let mut current_node = &mut self.file_tree;
-        while let Some(segment) = iter.next() {
-            match current_node {
-                FileTreeNode::File(_) => panic!("Directory node already exists as a file. This shouldn't be possible"),
-                FileTreeNode::Directory(ref mut map) => {
-                    if !map.contains_key(segment.as_str()) {
-                        map.insert(segment.clone(), FileTreeNode::Directory(HashMap::new()));
-                    }
-                    current_node = map.get_mut(segment.as_str()).unwrap();
+    pub fn save_database(&self) -> Result<(), AssetRegistryError> {
+        match &self.database_path {
+            Some(path) => RegistryDatabase::write(path, &self.base_path_relative, &self.file_tree, &self.assets, &self.uid_to_path, &self.redirects),
+            None => Ok(())
+        }
+    }
+
+    /// Writes every asset's path, UID, type, settings, sizes and dependencies to `path` as JSON,
+    /// for tools that can't link against this crate.
+    pub fn export_manifest(&self, path: &str) -> Result<(), AssetRegistryError> {
+        let assets: Vec<(String, &Asset)> = self.iter_assets().collect();
+        export::write_json(path, &self.base_path_relative, &assets)
+    }
+
+    pub fn rescan(&mut self) -> Result<ScanReport, AssetRegistryError> {
+        self.rescan_with("", ScanOptions::default())
+    }
+
+    /// Rescans only the directory or file at `subpath` (relative to the content root). Assets
+    /// elsewhere in the tree are left alone, and moves are only detected within the subtree.
+    pub fn rescan_path(&mut self, subpath: &str) -> Result<ScanReport, AssetRegistryError> {
+        self.rescan_with(subpath, ScanOptions::default())
+    }
+
+    /// `rescan_path` with progress reporting and cancellation.
+    pub fn rescan_with(&mut self, subpath: &str, mut options: ScanOptions) -> Result<ScanReport, AssetRegistryError> {
+        let report = self.scan_path(subpath, &mut options)?;
+        self.events.send_report(&report);
+        Ok(report)
+    }
+
+    /// Subscribes to change events for every asset, fired whenever a scan or reimport changes the tree.
+    pub fn subscribe(&mut self) -> Receiver<AssetEvent> {
+        self.events.subscribe()
+    }
+
+    fn scan_path(&mut self, subpath: &str, options: &mut ScanOptions) -> Result<ScanReport, AssetRegistryError> {
+        let _span = info_span!("scan", path = subpath).entered();
+        let scanned = self.relative_path(subpath);
+        let subpath = scanned.as_str();
+        let (walk_name, walk_rest) = split_mount(subpath);
+        let walk_mounts: Vec<Mount> = match subpath.is_empty() {
+            true => self.mounts.clone(),
+            false => match self.mounts.iter().find(|m| m.name == walk_name) {
+                Some(mount) => vec![mount.clone()],
+                None => return Err(AssetRegistryError::PathDoesNotExist(subpath.to_string()))
+            }
+        };
+        // overlays come before the roots they shadow, so the first file found for a path wins
+        let walks: Vec<(Mount, PathBuf)> = walk_mounts.iter().flat_map(|mount| {
+            mount.roots().map(move |root| (mount.clone(), join_relative(root, walk_rest)))
+        }).collect();
+        for (mount, _) in walks.iter() {
+            if !mount.root.exists() {
+                return Err(AssetRegistryError::PathDoesNotExist(mount.root.display().to_string()));
+            }
+        }
+        let mut report = ScanReport::default();
+
+        // gather everything on disk first, so moved files can be told apart from deletions + additions
+        let mut entries = Vec::new();
+        let mut seen_paths = HashSet::new();
+        let mut found_collections = Vec::new();
+        let mut bytes_discovered = 0;
+        let mut files = Vec::new();
+        let mut ignore = self.ignore_rules_above(&scanned);
+        let symlinks = self.symlinks;
+        let walked = walks.iter().flat_map(|(mount, start)| archive::walk_files(start, symlinks).map(move |file| (mount, file)));
+        for (mount, (source_path, size)) in walked {
+            if options.is_cancelled() {
+                report.cancelled = true;
+                return Ok(report);
+            }
+            let relative_path = match mount.virtual_path(&source_path) {
+                Some(path) => path,
+                None => continue
+            };
+            if !seen_paths.insert(relative_path.clone()) {
+                // shadowed by an overlay
+                continue;
+            }
+            if source_path.file_name().is_some_and(|name| name == IGNORE_FILE_NAME) {
+                let directory = AssetPath::new(&relative_path).parent().unwrap_or_default();
+                match IgnoreRules::read(&source_path, &directory) {
+                    Ok(rules) => ignore.add(rules.unwrap_or_default()),
+                    Err(e) => warn!(path = relative_path.as_str(), error = %e, "Ignoring unreadable ignore file")
                 }
+                continue;
             }
+            files.push((source_path, relative_path, size));
         }
-        current_node
-    }
+        // ignore files can turn up after the files they apply to, so only filter once all are found
+        ignore.rules.sort_by_key(|rules| AssetPath::new(&rules.directory).segments().len());
+        for (source_path, relative_path, size) in files {
+            if ignore.is_ignored(&relative_path) || !self.in_scan_limits(&relative_path) {
+                // anything imported before it was ignored is removed like a deleted file
+                seen_paths.remove(&relative_path);
+                continue;
+            }
+            if let Some(name) = AssetCollection::name_for(&source_path) {
+                match AssetCollection::read(&source_path) {
+                    Ok(mut collection) => {
+                        collection.source = Some(relative_path);
+                        found_collections.push((name, collection));
+                    },
+                    Err(e) => warn!(path = relative_path.as_str(), error = %e, "Ignoring unreadable collection")
+                }
+                continue;
+            }
+            bytes_discovered += size;
+            entries.push((source_path, relative_path, size));
+        }
+        // collection files under the scanned path that weren't found again have been deleted
+        self.collections.retain(|_, c| {
+            match &c.source {
+                Some(source) => !is_within(source, subpath),
+                None => true
+            }
+        });
+        self.collections.extend(found_collections);
 
-    pub fn get_assets_in_directory(&self, path: &str) -> Option<Vec<&Asset>> {
-        let pathstr = path.to_string().replace("\\", "/");
-        let mut split = pathstr.split("/").into_iter().peekable();
-        let mut current_node = &self.file_tree;
-        while let Some(segment) = split.next() {
-            match current_node {
-                FileTreeNode::File(_) => {
-                    // if current_node is ever a file, we reached a file before the destination
-                    return None;
+        let mut progress = ScanProgress {
+            files_discovered: entries.len(),
+            files_processed: 0,
+            current_file: "",
+            bytes_discovered,
+            bytes_processed: 0,
+        };
+        options.report_progress(&progress);
+
+        // anything in the tree that wasn't seen on disk has been moved or deleted
+        let segments = scanned.segments();
+        let mut missing: Vec<String> = match find_node(&self.file_tree, &segments) {
+            Some(node) => AssetIter::new(&self.assets, node, subpath)
+                .filter(|(path, asset)| !asset.generated && !seen_paths.contains(path))
+                .map(|(path, _)| path)
+                .collect(),
+            None => Vec::new()
+        };
+
+        // cheap checks first: unchanged files are skipped and moved files are relocated as-is
+        let mut pending = Vec::new();
+        for (source_path, relative_path, size) in entries.iter() {
+            if options.is_cancelled() {
+                report.cancelled = true;
+                self.finish_scan(&mut report);
+                return Ok(report);
+            }
+            if !missing.is_empty() && self.get_asset(relative_path).is_none() {
+                if let Some(index) = self.find_moved_asset(source_path, &missing) {
+                    let old_path = missing.swap_remove(index);
+                    if let Some(uid) = self.move_asset(&old_path, source_path, relative_path) {
+                        report.moved.push(MovedEntry { uid, from: old_path, to: relative_path.clone() });
+                    }
+                    progress.skip(relative_path, *size);
+                    options.report_progress(&progress);
+                    continue;
+                }
+            }
+            match self.check_entry(source_path, relative_path) {
+                EntryState::Unchanged => {
+                    progress.skip(relative_path, *size);
+                    options.report_progress(&progress);
                 },
-                FileTreeNode::Directory(map) => {
-                    if split.peek().is_none() {
-                        // found target dir, return assets
-                        let mut results = Vec::new();
-                        for (_, node) in map.iter() {
-                            match node {
-                                FileTreeNode::Directory(_) => {},
-                                FileTreeNode::File(asset) => results.push(asset)
-                            }
-                        }
-                        return Some(results)
+                EntryState::NeedsImport(existing_uid) => pending.push((source_path.as_path(), relative_path.as_str(), existing_uid, *size))
+            }
+        }
+
+        // then import everything that changed on the job scheduler, one `Decode` job each since
+        // importing doesn't touch pixels, and each after any changed assets its sidecar says it
+        // references. Results are merged as they come in, and only as many imports as there are
+        // threads are in flight, so cancellation stays responsive.
+        let sidecars: Vec<Sidecar> = self.run_in_import_pool(|| {
+            pending.par_iter().map(|(source_path, relative_path, _, _)| read_sidecar(source_path, relative_path)).collect()
+        });
+        let pending_jobs: HashMap<&str, usize> = pending.iter().enumerate().map(|(index, (_, relative_path, _, _))| (*relative_path, index)).collect();
+        let mut graph = JobGraph::new();
+        let mut jobs = Vec::with_capacity(pending.len());
+        for ((source_path, relative_path, _, _), sidecar) in pending.iter().zip(sidecars) {
+            let dependencies: Vec<usize> = sidecar.dependencies.iter().filter_map(|path| pending_jobs.get(path.as_str()).copied()).collect();
+            let (source_path, relative_path) = (*source_path, *relative_path);
+            jobs.push((graph.add(JobStage::Decode, move || prepare_import_with(source_path, relative_path, sidecar)), dependencies));
+        }
+        for (job, dependencies) in jobs.iter() {
+            for dependency in dependencies.iter() {
+                graph.depend(*job, jobs[*dependency].0);
+            }
+        }
+        let threads = self.import_thread_count();
+        // jobs were added in `pending` order, so a job's ID is its index there
+        let not_started = graph.run(threads, |job, prepared| {
+            let (source_path, relative_path, existing_uid, size) = pending[job.0];
+            match prepared.result {
+                Ok(Some(asset)) => {
+                    report.warnings.extend(prepared.warnings);
+                    let uid = self.finish_import(source_path, relative_path, existing_uid, prepared.sidecar, asset);
+                    self.metrics.get_mut().record_import(uid, prepared.duration);
+                    report.timings.import += prepared.duration;
+                    if existing_uid.is_some() {
+                        report.modified.push(ScanEntry::new(uid, relative_path));
                     }
                     else {
-                        match map.get(segment) {
-                            Some(node) => {
-                                current_node = node;
-                            },
-                            None => {
-                                // requested path segment not found
-                                return None;
-                            }
-                        }
+                        report.added.push(ScanEntry::new(uid, relative_path));
                     }
+                },
+                Ok(None) => {}, // unsupported file
+                Err(e) => {
+                    warn!(path = e.path(), error = %e, "Import failed");
+                    report.failed.push(e);
                 }
             }
+            progress.skip(relative_path, size);
+            options.report_progress(&progress);
+            !options.is_cancelled()
+        });
+        if !not_started.is_empty() {
+            report.cancelled = true;
+            self.finish_scan(&mut report);
+            return Ok(report);
         }
-        None
+
+        for path in missing {
+            let segments = path_segments(&path);
+            let asset = match remove_asset_node(&mut self.file_tree, &segments) {
+                Some(slot) => self.remove_slot(slot),
+                None => None
+            };
+            if let Some(asset) = asset {
+                // if the UID now points elsewhere, the file was moved along with its sidecar
+                match self.uid_to_path.get(&asset.uid) {
+                    Some(new_path) if **new_path != *path => {
+                        self.redirects.insert(path, new_path.to_string());
+                    },
+                    _ => {
+                        self.uid_to_path.remove(&asset.uid);
+                        self.metrics.get_mut().timings.remove(&asset.uid);
+                        report.removed.push(ScanEntry::new(asset.uid, &path));
+                    }
+                }
+            }
+        }
+        self.finish_scan(&mut report);
+        Ok(report)
     }
 
-    pub fn get_asset(&self, path: &str) -> Option<&Asset> {
-        let pathstr = path.to_string().replace("\\", "/");
-        let pathstr = pathstr.trim_start_matches(&self.base_path_absolute);
-        let mut split = pathstr.split("/").into_iter().filter(|s| s.len() > 0).peekable();
-        let mut current_node = &self.file_tree;
-        while let Some(segment) = split.next() {
-            match current_node {
-                FileTreeNode::File(_) => {
-                    // if current_node is ever a file, we reached a different file before the destination
-                    return None;
+    /// Updates derived state after a scan, then reprocesses every asset depending on something
+    /// the scan changed, along with any left dirty by earlier reimports. Cancelled scans only
+    /// mark dependents dirty.
+    fn finish_scan(&mut self, report: &mut ScanReport) {
+        let changed = report.changed_uids();
+        self.tree_changed(&changed);
+        // added and moved assets don't invalidate anything, but references to them and their
+        // folded paths still need updating
+        let arrived: Vec<AssetId> = report.added.iter().map(|e| e.uid).chain(report.moved.iter().map(|e| e.uid)).collect();
+        self.update_indices(&arrived);
+        if !report.removed.is_empty() || !report.moved.is_empty() {
+            self.paths.collect_unused();
+        }
+        // new assets can satisfy references that were missing before
+        let mut sources = changed;
+        sources.extend(report.added.iter().map(|e| e.uid));
+        self.mark_dependents_dirty(&sources);
+        if report.cancelled || self.dirty.is_empty() {
+            return;
+        }
+
+        let mut dirty: Vec<AssetId> = self.dirty.drain().collect();
+        dirty.sort_by_key(|uid| self.uid_to_path.get(uid).cloned());
+        let mut reimported = Vec::new();
+        for uid in self.dependencies.topological_order(&dirty) {
+            let path = match self.uid_to_path.get(&uid) {
+                Some(path) => path.clone(),
+                None => continue // removed since it was marked
+            };
+            let start = Instant::now();
+            match self.reimport_relative(&path) {
+                Ok((uid, warnings)) => {
+                    report.timings.import += start.elapsed();
+                    report.warnings.extend(warnings);
+                    report.reimported.push(ScanEntry::new(uid, &path));
+                    reimported.push(uid);
                 },
-                FileTreeNode::Directory(map) => {
-                    match map.get(segment) {
-                        Some(node) => {
-                            current_node = node;
-                            if let FileTreeNode::File(asset) = current_node {
-                                if split.peek().is_none() {
-                                    return Some(&asset);
-                                }
-                            }
-                        },
-                        None => {
-                            // requested path segment not found
-                            return None;
+                Err(e) => {
+                    warn!(path = e.path(), error = %e, "Reimport of dependent asset failed");
+                    report.failed.push(e);
+                }
+            }
+        }
+        self.tree_changed(&reimported);
+    }
+
+    /// Marks everything depending on `uids`, directly or indirectly, to be reprocessed by the
+    /// next rescan.
+    fn mark_dependents_dirty(&mut self, uids: &[AssetId]) {
+        let dependents = self.dependencies.all_referencers_of(uids);
+        self.dirty.extend(dependents);
+    }
+
+    /// Starts watching the content directory for changes. Changes are queued until `pump_events`.
+    pub fn start_watching(&mut self) -> Result<(), AssetRegistryError> {
+        if self.watcher.is_none() {
+            self.watcher = Some(AssetWatcher::new(&self.mounts)?);
+        }
+        Ok(())
+    }
+
+    pub fn stop_watching(&mut self) {
+        self.watcher = None;
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    /// Applies all filesystem changes queued since the last call, reimporting changed files and
+    /// pruning deleted ones. Does nothing if the registry isn't watching.
+    pub fn pump_events(&mut self) -> Result<ScanReport, AssetRegistryError> {
+        let paths = match &self.watcher {
+            Some(watcher) => watcher.drain_changed_paths(),
+            None => return Ok(ScanReport::default())
+        };
+        if paths.iter().any(|p| self.source_path(p).is_none_or(|p| !archive::exists(&p))) {
+            // something was deleted or moved away, so scan everything that changed in one go
+            // to recognize moves instead of reporting a removal plus an addition
+            return self.rescan_path(&common_ancestor(&paths));
+        }
+        let mut report = ScanReport::default();
+        for path in paths.iter() {
+            report.merge(self.rescan_path(path)?);
+        }
+        Ok(report)
+    }
+
+    /// Reprocesses a single asset with its current sidecar settings, even if its source file hasn't
+    /// changed, and invalidates any cached GPU texture for it.
+    pub fn reimport(&mut self, path: &str) -> Result<AssetId, AssetRegistryError> {
+        let relative_path = self.relative_path(path);
+        if !self.source_path(&relative_path).is_some_and(|p| !p.is_dir() && archive::exists(&p)) {
+            return Err(AssetRegistryError::PathDoesNotExist(relative_path.into()));
+        }
+        let (uid, warnings) = self.reimport_relative(&relative_path)?;
+        for warning in warnings {
+            warn!(path = warning.path(), "{}", warning);
+        }
+        self.tree_changed(&[uid]);
+        self.mark_dependents_dirty(&[uid]);
+        self.events.send(AssetEvent::Reimported { uid, path: relative_path.into() });
+        Ok(uid)
+    }
+
+    /// Replaces a texture's import settings, saves them to its sidecar and reimports it, which
+    /// notifies subscribers and marks its dependents dirty. Only the settings part of `metadata` is
+    /// used, the info block is recomputed from the source.
+    pub fn set_texture_metadata(&mut self, path: &str, metadata: TextureMetadata) -> Result<AssetId, AssetRegistryError> {
+        let relative_path = self.relative_path(path);
+        let (source_path, uid) = match (self.source_path(&relative_path), self.get_asset(&relative_path)) {
+            (Some(source_path), Some(asset)) => (source_path, asset.uid),
+            _ => return Err(AssetRegistryError::PathDoesNotExist(relative_path.into()))
+        };
+        let mut sidecar = Sidecar::read(&source_path)?.unwrap_or_default();
+        sidecar.uid = Some(uid);
+        sidecar.texture = Some(metadata);
+        sidecar.write(&source_path)?;
+        self.reimport(&relative_path)
+    }
+
+    /// Reprocesses every asset selected by `options`, e.g. after global defaults or the pipeline
+    /// itself changed. Failures are collected in the report rather than aborting the whole run.
+    pub fn reimport_all(&mut self, options: &ReimportOptions) -> Result<ScanReport, AssetRegistryError> {
+        let directory = self.relative_path(options.directory.as_deref().unwrap_or(""));
+        let segments = directory.segments();
+        let mut paths: HashMap<AssetId, String> = match find_node(&self.file_tree, &segments) {
+            Some(node) => AssetIter::new(&self.assets, node, &directory)
+                .filter(|(_, asset)| !asset.generated && options.asset_type.is_none_or(|t| asset.data.asset_type() == t))
+                .map(|(path, asset)| (asset.uid, path))
+                .collect(),
+            None => return Err(AssetRegistryError::PathDoesNotExist(directory.into()))
+        };
+        let mut uids: Vec<AssetId> = paths.keys().cloned().collect();
+        uids.sort_by(|a, b| paths[a].cmp(&paths[b]));
+
+        let mut report = ScanReport::default();
+        // dependencies first, so dependents see their updated data
+        for uid in self.dependencies.topological_order(&uids) {
+            let path = paths.remove(&uid).unwrap();
+            if options.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+                report.cancelled = true;
+                break;
+            }
+            let start = Instant::now();
+            match self.reimport_relative(&path) {
+                Ok((uid, warnings)) => {
+                    report.timings.import += start.elapsed();
+                    report.warnings.extend(warnings);
+                    if let (true, Some(source_path)) = (options.migrate_sidecars, self.source_path(&path)) {
+                        if let Err(e) = Sidecar::read(&source_path).and_then(|s| s.unwrap_or_default().write(&source_path)) {
+                            warn!(path = path.as_str(), error = %e, "Failed to migrate sidecar");
                         }
                     }
+                    report.reimported.push(ScanEntry::new(uid, &path));
+                },
+                Err(e) => {
+                    warn!(path = e.path(), error = %e, "Reimport failed");
+                    report.failed.push(e);
                 }
             }
         }
+        let changed = report.changed_uids();
+        self.tree_changed(&changed);
+        self.mark_dependents_dirty(&changed);
+        // dependents that were just reprocessed in order are already up to date
+        for uid in changed.iter() {
+            self.dirty.remove(uid);
+        }
+        self.events.send_report(&report);
+        Ok(report)
+    }
+
+    fn reimport_relative(&mut self, relative_path: &str) -> Result<(AssetId, Vec<ImportWarning>), ImportError> {
+        let source_path = match self.source_path(relative_path) {
+            Some(path) => path,
+            None => return Err(unmounted(relative_path))
+        };
+        let existing_uid = self.get_asset(relative_path).map(|a| a.uid);
+        match self.import_entry(&source_path, relative_path, existing_uid)? {
+            Some(imported) => Ok(imported),
+            None => Err(ImportError::UnsupportedFileType { path: relative_path.to_string() })
+        }
+    }
+
+    /// Normalizes a user-supplied asset path, which may be absolute, to a path relative to the
+    /// content root.
+    fn relative_path(&self, path: &str) -> AssetPath {
+        self.resolve_case(AssetPath::from_user(path, &self.base_path_absolute))
+    }
+
+    /// Makes lookups of `alias` find whatever is at `target`, e.g. to keep old references working
+    /// while content is reorganized. Aliasing a directory redirects everything below it. An asset
+    /// that actually exists at `alias` still wins.
+    pub fn add_alias(&mut self, alias: &str, target: &str) -> Result<(), AssetRegistryError> {
+        let alias = self.relative_path(alias);
+        let target = self.relative_path(target);
+        if alias.file_name().is_empty() {
+            return Err(AssetRegistryError::InvalidAlias { alias: alias.into(), reason: "the root of a mount can't be aliased".to_string() });
+        }
+        if is_within(&target, &alias) {
+            return Err(AssetRegistryError::InvalidAlias { alias: alias.into(), reason: format!("'{}' is below the alias itself", target) });
+        }
+        self.aliases.insert(alias.into(), target.into());
+        Ok(())
+    }
+
+    pub fn remove_alias(&mut self, alias: &str) -> bool {
+        let alias = self.relative_path(alias);
+        self.aliases.remove(alias.as_str()).is_some()
+    }
+
+    /// Every alias and its target.
+    pub fn aliases(&self) -> &HashMap<String, String> {
+        &self.aliases
+    }
+
+    /// Where each asset moved since its old path was last in use, from old path to new path.
+    pub fn redirects(&self) -> &HashMap<String, String> {
+        &self.redirects
+    }
+
+    pub fn clear_redirects(&mut self) {
+        self.redirects.clear();
+    }
+
+    /// Normalizes a path for lookup, following aliases and redirects if nothing exists at it.
+    pub fn resolve(&self, path: &str) -> AssetPath {
+        let mut path = self.relative_path(path);
+        // aliases can point at each other, so give up on cycles instead of following them forever
+        for _ in 0..MAX_ALIAS_HOPS {
+            if find_node(&self.file_tree, &path.segments()).is_some() {
+                break;
+            }
+            match self.alias_target(&path) {
+                Some(target) => path = target,
+                None => break
+            }
+        }
+        path
+    }
+
+    /// Applies the alias or redirect for `path` or its closest aliased parent directory.
+    fn alias_target(&self, path: &AssetPath) -> Option<AssetPath> {
+        let mut prefix = Some(path.clone());
+        while let Some(aliased) = prefix {
+            if let Some(target) = self.aliases.get(aliased.as_str()).or_else(|| self.redirects.get(aliased.as_str())) {
+                return Some(AssetPath::new(&format!("{}{}", target, &path[aliased.len()..])));
+            }
+            prefix = aliased.parent().filter(|p| !p.file_name().is_empty());
+        }
         None
     }
 
-    pub fn get_path_from_id(&self, id: u64) -> Option<&String> {
-        self.uid_to_path.get(&id)
+    /// Ignores files matching a gitignore-style pattern, relative to the content root, on top of
+    /// any `.pipedreamignore` files in the tree. Takes effect with the next rescan. See `ignore`
+    /// for the pattern syntax.
+    pub fn add_ignore_pattern(&mut self, pattern: &str) -> Result<(), AssetRegistryError> {
+        self.ignore_patterns.add(pattern)
     }
 
-    pub fn get_texture(&mut self, path: &str) -> Option<Texture> {
-        match self.get_asset(path) {
-            Some(asset) => {
-                match &asset.data {
-                    AssetData::Texture(tex_data) => {
-                        match self.cached_texture_arcs.get(&path.to_string()) {
-                            Some(a) => Some(a.clone()),
-                            None => {
-                                match tex_data.settings.format {
-                                    Format::R8G8B8A8Srgb => {
-                                        let (img, future) = ImmutableImage::from_iter(tex_data.data.iter().cloned(),
-                                                                  tex_data.settings.dimensions(),
-                                                                  vulkano::format::R8G8B8A8Srgb,
-                                                                  self.queue.clone()).unwrap();
-                                        self.cached_texture_arcs.insert(path.to_string(), Texture::RGBA8_Srgb(img.clone()));
-                                        drop(future);
-                                        Some(Texture::RGBA8_Srgb(img))
-                                    },
-                                    _ => unimplemented!()
-                                }
-                            }
-                        }
-                    },
-                }
-            },
-            None => None
+    pub fn clear_ignore_patterns(&mut self) {
+        self.ignore_patterns = IgnoreRules::default();
+    }
+
+    /// Whether a path is within `max_depth` and has one of the accepted `extensions`. Ignore and
+    /// collection files are always picked up, whatever their extension.
+    fn in_scan_limits(&self, relative_path: &str) -> bool {
+        let (_, rest) = split_mount(relative_path);
+        if self.max_depth.is_some_and(|depth| rest.split('/').count() > depth) {
+            return false;
+        }
+        let extension = match Path::new(rest).extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => String::new()
+        };
+        match &self.extensions {
+            Some(extensions) => extensions.contains(&extension) || AssetCollection::name_for(Path::new(rest)).is_some(),
+            None => true
         }
     }
-}
 
-fn process_file(entry: &DirEntry) -> Option<Asset> {
-    let filename = entry.file_name().to_str().unwrap().to_string();
-    if let Some(ext) = entry.path().extension() {
-        let ext = ext.to_str().unwrap();
-        if ["png", "jpg", "tga", "dds"].contains(&ext) {
-            return process_texture(entry, &filename, ext);
+    /// The programmatic ignore patterns and the ignore files of every directory above `path`,
+    /// which a scan of `path` doesn't walk itself.
+    fn ignore_rules_above(&self, path: &AssetPath) -> IgnoreSet {
+        let mut set = IgnoreSet::default();
+        set.add(self.ignore_patterns.clone());
+        let mut directories = Vec::new();
+        let mut parent = path.parent();
+        while let Some(directory) = parent {
+            parent = directory.parent();
+            directories.push(directory);
         }
+        for directory in directories.iter().rev() {
+            let source_path = match self.source_path(directory) {
+                Some(path) => path.join(IGNORE_FILE_NAME),
+                None => continue
+            };
+            match IgnoreRules::read(&source_path, directory) {
+                Ok(rules) => set.add(rules.unwrap_or_default()),
+                Err(e) => warn!(path = %source_path.display(), error = %e, "Ignoring unreadable ignore file")
+            }
+        }
+        set
     }
-    None
-}
 
-// TODO: extract asset processors to another module
-fn process_texture(entry: &DirEntry, filename: &str, ext: &str) -> Option<Asset> {
-    match ext {
-        "png" => {
-            // TODO: handle errors here
-            let reader = image::png::PNGDecoder::new(std::fs::File::open(entry.path()).unwrap()).unwrap();
-            let dimensions = [reader.dimensions().0 as u32, reader.dimensions().1 as u32];
-            let timestamp = DateTime::<Local>::from(entry.metadata().unwrap().modified().unwrap());
-
-            let format;
-            let has_channels;
-            let include_channels;
-            let colortype = reader.colortype();
-            match colortype {
-                ColorType::RGB(8) => {
-                    format = Format::R8G8B8A8Srgb;
-                    has_channels = ChannelMask::RED | ChannelMask::GREEN | ChannelMask::BLUE;
-                    include_channels = has_channels
-                },
-                ColorType::RGBA(8) => {
-                    format = Format::R8G8B8A8Srgb;
-                    has_channels = ChannelMask::all();
-                    include_channels = has_channels
-                },
-                colortype => {
-                    println!("Unsupported color type: {} - {:?}", filename, colortype);
-                    return None;
+    /// Adds an asset generated at runtime, e.g. a baked lightmap, under a virtual path. It gets a UID
+    /// and shows up in lookups and queries like any other asset, but has no source file, so scans
+    /// leave it alone, it isn't cooked, and it's dropped when the database is loaded. Registering
+    /// the same path again replaces the data and keeps the UID. Textures must come with their
+    /// pixels.
+    pub fn register_asset(&mut self, path: &str, data: AssetData) -> Result<AssetId, AssetRegistryError> {
+        let relative_path = self.relative_path(path);
+        let invalid = |reason: &str| Err(AssetRegistryError::InvalidAsset { path: relative_path.to_string(), reason: reason.to_string() });
+        let (name, _) = split_mount(&relative_path);
+        if relative_path.file_name().is_empty() {
+            return invalid("not a file path");
+        }
+        if name == BUILTIN_MOUNT {
+            return invalid("builtin:// is reserved for the default assets");
+        }
+        if !self.mounts.iter().any(|m| m.name == name) {
+            return Err(AssetRegistryError::UnknownMount(name.to_string()));
+        }
+        let mut parent = relative_path.parent();
+        while let Some(directory) = parent {
+            if let Some(FileTreeNode::File(_)) = find_node(&self.file_tree, &directory.segments()) {
+                return invalid("a parent directory is an asset");
+            }
+            parent = directory.parent();
+        }
+        match find_node(&self.file_tree, &relative_path.segments()) {
+            Some(FileTreeNode::Directory(_)) => return invalid("path is a directory"),
+            Some(FileTreeNode::File(slot)) if self.assets.get(*slot).is_some_and(|a| !a.generated) => {
+                return invalid("path belongs to an imported asset");
+            },
+            _ => {}
+        }
+        match &data {
+            AssetData::Texture(tex_data) => {
+                let size = tex_data.settings.source_size;
+                match &*tex_data.data.lock() {
+                    Some(pixels) if pixels.len() == size[0] as usize * size[1] as usize * 4 => {},
+                    Some(_) => return invalid("pixel data doesn't match the texture's source_size"),
+                    None => return invalid("texture has no pixel data")
                 }
             }
+        }
+        Ok(self.insert_generated(&relative_path, data))
+    }
 
-            let mut result_data = Vec::new();
-            let imgdata = reader.read_image().unwrap();
-            let bytes = imgdata.into_iter();
-            match colortype {
-                ColorType::RGB(8) => {
-                    for rgb in &bytes.chunks(3) {
-                        result_data.extend(rgb);
-                        result_data.push(255u8);
-                    }
-                },
-                ColorType::RGBA(8) => {
-                    result_data.extend(bytes);
-                },
-                _ => unreachable!()
-            }
-            let id: u64 = rand::random();
-
-            let texture_data = TextureMetadata {
-                source_size: dimensions,
-                max_ingame_size: dimensions,
-                data_size: [result_data.len() as u32, 0],
-                has_channels,
-                format,
-                num_mips: 0,
-                compression_mode: CompressionMode::None,
-                include_channels,
-                max_texture_size: None,
-                mip_gen_settings: MipGenSettings::NoMipmaps,
-                lod_bias: 0,
-                power_of_two_mode: PowerOfTwoMode::None,
-                padding_color: LinearColor::BLACK,
-                srgb: true,
-                x_axis_tiling: SamplerAddressMode::Repeat,
-                y_axis_tiling: SamplerAddressMode::Repeat,
-                invert_green: false,
-                filter: Filter::Linear
+    fn insert_generated(&mut self, relative_path: &str, data: AssetData) -> AssetId {
+        let existing_uid = self.get_asset(relative_path).filter(|a| a.generated).map(|a| a.uid);
+        let (content_hash, size) = match &data {
+            AssetData::Texture(tex_data) => {
+                let pixels = tex_data.data.lock();
+                let pixels = pixels.as_deref().unwrap_or_default();
+                (content_hash(pixels), pixels.len() as u64)
+            }
+        };
+        let interned = self.paths.intern(relative_path);
+        let uid = match existing_uid {
+            Some(uid) => uid,
+            None => claim_uid(&mut self.uid_to_path, uid_from_path(relative_path), interned)
+        };
+        let mut asset = Asset::new(relative_path, Local::now(), uid, content_hash, size, None, data);
+        asset.generated = true;
+        self.insert_asset(relative_path, asset);
+        self.tree_changed(&[uid]);
+        self.mark_dependents_dirty(&[uid]);
+        match existing_uid {
+            Some(_) => self.events.send(AssetEvent::Modified { uid, path: relative_path.to_string() }),
+            None => self.events.send(AssetEvent::Added { uid, path: relative_path.to_string() })
+        }
+        uid
+    }
+
+    /// Registers the engine default textures, see `defaults`.
+    fn register_defaults(&mut self) {
+        for (path, data) in defaults::default_textures() {
+            self.insert_generated(path, data);
+        }
+    }
+
+    /// Removes an asset added with `register_asset`. Returns false if there's no generated asset
+    /// at `path`.
+    pub fn unregister_asset(&mut self, path: &str) -> bool {
+        let relative_path = self.relative_path(path);
+        if split_mount(&relative_path).0 == BUILTIN_MOUNT || !self.get_asset(&relative_path).is_some_and(|asset| asset.generated) {
+            return false;
+        }
+        if let Some(asset) = self.remove_asset_at(&relative_path) {
+            self.tree_changed(&[asset.uid]);
+            self.mark_dependents_dirty(&[asset.uid]);
+            self.events.send(AssetEvent::Removed { uid: asset.uid, path: relative_path.into() });
+        }
+        true
+    }
+
+    fn remove_asset_at(&mut self, relative_path: &str) -> Option<Asset> {
+        let slot = remove_asset_node(&mut self.file_tree, &path_segments(relative_path))?;
+        let asset = self.remove_slot(slot)?;
+        if self.uid_to_path.get(&asset.uid).is_some_and(|p| **p == *relative_path) {
+            self.uid_to_path.remove(&asset.uid);
+        }
+        Some(asset)
+    }
+
+    /// Where the source file for a virtual path lives on disk, or `None` if its mount doesn't exist.
+    pub fn source_path(&self, path: &str) -> Option<PathBuf> {
+        let (name, rest) = split_mount(path);
+        self.mounts.iter().find(|m| m.name == name).map(|m| m.resolve(rest))
+    }
+
+    /// Mounts `root` into the tree under `name://`. Its contents show up with the next rescan.
+    /// Mounting over an existing name replaces it. `root` can also be a `.zip` or `.pak` archive,
+    /// which is mounted read-only.
+    pub fn mount(&mut self, name: &str, root: &str) -> Result<(), AssetRegistryError> {
+        if !Mount::is_valid_name(name) || name == BUILTIN_MOUNT {
+            return Err(AssetRegistryError::InvalidMountName(name.to_string()));
+        }
+        if !Path::new(root).is_dir() && !archive::is_archive(Path::new(root)) {
+            return Err(AssetRegistryError::PathDoesNotExist(root.to_string()));
+        }
+        self.mounts.retain(|m| m.name != name);
+        self.mounts.push(Mount::new(name, Path::new(root)));
+        self.restart_watcher()
+    }
+
+    /// Layers `root` over the mount `name` (`""` for the base path). Files in the overlay shadow
+    /// files at the same path in the mount and in lower priority overlays. Takes effect with the
+    /// next rescan.
+    pub fn add_overlay(&mut self, name: &str, root: &str, priority: i32) -> Result<(), AssetRegistryError> {
+        if !Path::new(root).is_dir() && !archive::is_archive(Path::new(root)) {
+            return Err(AssetRegistryError::PathDoesNotExist(root.to_string()));
+        }
+        match self.mounts.iter_mut().find(|m| m.name == name) {
+            Some(mount) => mount.add_overlay(Path::new(root), priority),
+            None => return Err(AssetRegistryError::UnknownMount(name.to_string()))
+        }
+        self.restart_watcher()
+    }
+
+    /// Removes an overlay from the mount `name`. The files it shadowed come back with the next rescan.
+    pub fn remove_overlay(&mut self, name: &str, root: &str) -> Result<bool, AssetRegistryError> {
+        let removed = match self.mounts.iter_mut().find(|m| m.name == name) {
+            Some(mount) => {
+                let count = mount.overlays.len();
+                mount.overlays.retain(|o| o.root != Path::new(root));
+                mount.overlays.len() != count
+            },
+            None => return Err(AssetRegistryError::UnknownMount(name.to_string()))
+        };
+        if removed {
+            self.restart_watcher()?;
+        }
+        Ok(removed)
+    }
+
+    /// Unmounts `name`. Its assets are removed by the next full rescan.
+    pub fn unmount(&mut self, name: &str) -> Result<bool, AssetRegistryError> {
+        let count = self.mounts.len();
+        self.mounts.retain(|m| m.name.is_empty() || m.name != name);
+        if self.mounts.len() == count {
+            return Ok(false);
+        }
+        self.restart_watcher()?;
+        Ok(true)
+    }
+
+    fn restart_watcher(&mut self) -> Result<(), AssetRegistryError> {
+        if self.watcher.is_some() {
+            self.watcher = Some(AssetWatcher::new(&self.mounts)?);
+        }
+        Ok(())
+    }
+
+    /// Brings derived state up to date after the tree changed, `changed` being the assets whose
+    /// data changed or disappeared.
+    fn tree_changed(&mut self, changed: &[AssetId]) {
+        for uid in changed.iter() {
+            *self.versions.entry(*uid).or_insert(0) += 1;
+        }
+        let swapped: Vec<AssetId> = match self.hot_swap_textures {
+            true => {
+                let cached = self.cached_textures.get_mut();
+                changed.iter().filter(|uid| cached.contains_key(*uid)).copied().collect()
+            },
+            false => Vec::new()
+        };
+        self.evict_cached_textures(changed);
+        let uid_to_slot = &self.uid_to_slot;
+        self.streaming_hints.get_mut().retain(|uid, _| uid_to_slot.contains_key(uid));
+        let file_tree = &self.file_tree;
+        self.redirects.retain(|from, _| find_node(file_tree, &path_segments(from)).is_none());
+        self.update_indices(changed);
+        self.invalidate_snapshot();
+        self.swap_textures(&swapped);
+    }
+
+    /// Uploads textures again right after they changed, see `hot_swap_textures`. Textures that
+    /// fail to upload stay evicted, so the error comes up again on their next `get_texture`.
+    fn swap_textures(&mut self, uids: &[AssetId]) {
+        for uid in uids {
+            let path = match self.uid_to_path.get(uid) {
+                Some(path) => path.to_string(),
+                // removed
+                None => continue
             };
+            match self.get_texture(&path) {
+                Ok(_) => self.events.send(AssetEvent::Swapped { uid: *uid, path }),
+                Err(e) => warn!(path = path.as_str(), error = %e, "Failed to hot swap texture")
+            }
+        }
+    }
 
-            Some(Asset::new(filename, timestamp, id, None, AssetData::Texture(
-                TextureAssetData::new(texture_data, result_data))
-            ))
-        },
-        _ => None
+    fn invalidate_snapshot(&mut self) {
+        self.generation += 1;
+        *self.snapshot.get_mut() = None;
+    }
+
+    /// An immutable copy of the tree and every asset's metadata, which other threads can query
+    /// while this registry keeps changing. Snapshots are shared until something changes, so
+    /// taking one again is cheap.
+    pub fn snapshot(&self) -> Arc<RegistrySnapshot> {
+        if let Some(snapshot) = &*self.snapshot.read() {
+            return snapshot.clone();
+        }
+        let assets = self.iter_assets().map(|(path, asset)| {
+            let path = self.uid_to_path.get(&asset.uid).filter(|p| ***p == *path).cloned().unwrap_or_else(|| Arc::from(path));
+            (path, AssetInfo::new(asset, self.versions.get(&asset.uid).copied().unwrap_or(0)))
+        });
+        let snapshot = Arc::new(RegistrySnapshot::new(self.generation, assets));
+        *self.snapshot.write() = Some(snapshot.clone());
+        snapshot
+    }
+
+    /// Makes lookups ignore case, so `Textures/Foo.PNG` finds `textures/foo.png`. Paths keep the
+    /// casing they have on disk everywhere they're reported. If several paths differ only in case,
+    /// an exact match wins and otherwise one of them is picked.
+    pub fn set_case_insensitive_paths(&mut self, case_insensitive: bool) {
+        self.case_insensitive_paths = case_insensitive;
+        self.fold_paths();
+    }
+
+    pub fn case_insensitive_paths(&self) -> bool {
+        self.case_insensitive_paths
+    }
+
+    /// Updates the dependency graph and case-folded paths for assets that were added, changed,
+    /// moved or removed.
+    fn update_indices(&mut self, uids: &[AssetId]) {
+        let (assets, file_tree, uid_to_slot) = (&self.assets, &self.file_tree, &self.uid_to_slot);
+        self.dependencies.update(uids,
+            |uid| uid_to_slot.get(&uid).and_then(|slot| assets.get(*slot)),
+            |path| find_slot(file_tree, &path_segments(path)).and_then(|slot| assets.get(slot)).map(|asset| asset.uid));
+        self.fold_changed_paths(uids);
+    }
+
+    /// `fold_paths` for just the given assets' current paths. Folded paths of removed assets stay
+    /// until the next full `fold_paths`, lookups through them just don't find anything.
+    fn fold_changed_paths(&mut self, uids: &[AssetId]) {
+        if !self.case_insensitive_paths {
+            return;
+        }
+        for uid in uids {
+            if let Some(path) = self.uid_to_path.get(uid).cloned() {
+                self.fold_path(&path);
+            }
+        }
+    }
+
+    fn fold_paths(&mut self) {
+        self.folded_paths.clear();
+        if !self.case_insensitive_paths {
+            return;
+        }
+        let paths: Vec<Arc<str>> = self.uid_to_path.values().cloned().collect();
+        for path in paths {
+            self.fold_path(&path);
+        }
+    }
+
+    /// Adds the path itself and each directory above it, down to its mount, to `folded_paths`.
+    fn fold_path(&mut self, path: &str) {
+        let (_, rest) = split_mount(path);
+        let mount_len = path.len() - rest.len();
+        let mut end = path.len();
+        while end > mount_len {
+            let prefix = &path[..end];
+            let paths = &mut self.paths;
+            self.folded_paths.entry(prefix.to_lowercase()).or_insert_with(|| paths.intern(prefix));
+            end = prefix[mount_len..].rfind('/').map_or(mount_len, |i| mount_len + i);
+        }
+    }
+
+    /// Gives a path the casing it has in the tree, if paths are case-insensitive. Paths that match
+    /// exactly, or don't match anything, are returned as they are.
+    fn resolve_case(&self, path: AssetPath) -> AssetPath {
+        if !self.case_insensitive_paths || find_node(&self.file_tree, &path.segments()).is_some() {
+            return path;
+        }
+        match self.folded_paths.get(&path.to_lowercase()) {
+            Some(actual) => AssetPath::new(actual),
+            None => path
+        }
+    }
+
+    /// Drops cached GPU textures for the given assets, and any no longer in the tree.
+    fn evict_cached_textures(&mut self, uids: &[AssetId]) {
+        let uid_to_slot = &self.uid_to_slot;
+        self.cached_textures.get_mut().retain(|uid, _| !uids.contains(uid) && uid_to_slot.contains_key(uid));
+        self.cached_texture_sets.get_mut().retain(|(_, _, set_uids), _| set_uids.iter().all(|uid| !uids.contains(uid) && uid_to_slot.contains_key(uid)));
+    }
+
+    /// Checks a file found on disk against the tree to see if it's new or changed.
+    fn check_entry(&mut self, source_path: &Path, relative_path: &str) -> EntryState {
+        let segments = path_segments(relative_path);
+        let asset = match find_slot(&self.file_tree, &segments) {
+            Some(slot) => self.assets.get_mut(slot),
+            None => None
+        };
+        match asset {
+            Some(asset) => {
+                let file_time = match file_timestamp(source_path) {
+                    Ok(time) => time,
+                    // let the import attempt report the error
+                    Err(_) => return EntryState::NeedsImport(Some(asset.uid))
+                };
+                if asset.timestamp == file_time {
+                    // timestamps are the same, don't reprocess
+                    return EntryState::Unchanged;
+                }
+                if self.change_detection == ChangeDetection::ContentHash {
+                    if let Ok(hash) = hash_file(source_path) {
+                        if hash == asset.content_hash {
+                            // file was only touched, remember the new timestamp and skip it
+                            asset.timestamp = file_time;
+                            return EntryState::Unchanged;
+                        }
+                    }
+                }
+                EntryState::NeedsImport(Some(asset.uid))
+            },
+            None => EntryState::NeedsImport(None)
+        }
+    }
+
+    /// Processes a file with its sidecar settings and inserts the resulting asset into the tree.
+    /// Returns `Ok(None)` for unsupported files.
+    fn import_entry(&mut self, source_path: &Path, relative_path: &str, existing_uid: Option<AssetId>) -> Result<Option<(AssetId, Vec<ImportWarning>)>, ImportError> {
+        let prepared = prepare_import(source_path, relative_path);
+        match prepared.result? {
+            Some(asset) => {
+                let uid = self.finish_import(source_path, relative_path, existing_uid, prepared.sidecar, asset);
+                self.metrics.get_mut().record_import(uid, prepared.duration);
+                Ok(Some((uid, prepared.warnings)))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Inserts a processed asset into the tree, resolving its UID from the sidecar, the asset it
+    /// replaces, or its path, in that order.
+    fn finish_import(&mut self, source_path: &Path, relative_path: &str, existing_uid: Option<AssetId>, mut sidecar: Sidecar, mut new_asset: Asset) -> AssetId {
+        if let Some(uid) = sidecar.uid {
+            if let Some(old_path) = self.uid_to_path.get(&uid) {
+                if **old_path != *relative_path && !self.source_path(old_path).is_some_and(|p| archive::exists(&p)) {
+                    // the file was moved or renamed along with its sidecar, so it keeps its identity
+                    self.uid_to_path.remove(&uid);
+                }
+            }
+        }
+        // the sidecar is authoritative, then a reprocessed file keeps the UID it already had,
+        // even if that one was re-rolled
+        let preferred_uid = sidecar.uid.or(existing_uid).unwrap_or(new_asset.uid);
+        let interned = self.paths.intern(relative_path);
+        new_asset.uid = claim_uid(&mut self.uid_to_path, preferred_uid, interned);
+        new_asset.tags = sidecar.tags.clone();
+        match &mut new_asset.data {
+            AssetData::Texture(tex_data) => tex_data.overrides = sidecar.texture_overrides.clone(),
+        }
+        // importers may already have found references in the file itself
+        new_asset.dependencies.extend(sidecar.dependencies.iter().map(|p| p.replace("\\", "/").trim_matches('/').to_string()));
+        new_asset.dependencies.sort();
+        new_asset.dependencies.dedup();
+        let uid = new_asset.uid;
+        update_sidecar_uid(&mut sidecar, source_path, relative_path, uid);
+        self.insert_asset(relative_path, new_asset);
+        uid
+    }
+
+    /// Limits import to `threads` worker threads, or uses rayon's global pool if `None`.
+    pub fn set_import_threads(&mut self, threads: Option<usize>) -> Result<(), AssetRegistryError> {
+        self.import_pool = match threads {
+            Some(n) => Some(Arc::new(rayon::ThreadPoolBuilder::new().num_threads(n).build().map_err(ScanError::from)?)),
+            None => None
+        };
+        Ok(())
+    }
+
+    /// Replaces the background loader with one running `threads` workers. Loads still queued on the
+    /// old loader are cancelled.
+    pub fn set_loader_threads(&mut self, threads: usize) {
+        self.loader = OnceLock::from(Loader::new(threads));
+    }
+
+    pub fn loader(&self) -> &Loader {
+        self.loader.get_or_init(|| Loader::new(rayon::current_num_threads().min(4)))
+    }
+
+    fn import_thread_count(&self) -> usize {
+        match &self.import_pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads()
+        }
+    }
+
+    fn run_in_import_pool<R: Send, F: FnOnce() -> R + Send>(&self, f: F) -> R {
+        match &self.import_pool {
+            Some(pool) => pool.install(f),
+            None => f()
+        }
+    }
+
+    /// Looks for a missing asset with the same contents as a newly discovered file, returning its
+    /// index in `missing`.
+    fn find_moved_asset(&self, source_path: &Path, missing: &[String]) -> Option<usize> {
+        let hash = hash_file(source_path).ok()?;
+        missing.iter().position(|path| {
+            match self.get_asset(path) {
+                Some(asset) => asset.content_hash == hash,
+                None => false
+            }
+        })
+    }
+
+    /// Moves an asset whose source file was moved or renamed, keeping its UID and processed data.
+    fn move_asset(&mut self, old_path: &str, source_path: &Path, new_path: &str) -> Option<AssetId> {
+        let segments = path_segments(old_path);
+        // the asset keeps its slot, so handles to it stay valid
+        let slot = remove_asset_node(&mut self.file_tree, &segments)?;
+        let asset = self.assets.get_mut(slot)?;
+        let uid = asset.uid;
+        asset.path = source_path.file_name()?.to_string_lossy().to_string();
+        // an unreadable timestamp just means the file gets reprocessed next scan
+        asset.timestamp = file_timestamp(source_path).unwrap_or_else(|_| Local::now());
+        self.uid_to_path.insert(uid, self.paths.intern(new_path));
+        self.redirects.insert(old_path.to_string(), new_path.to_string());
+        let mut sidecar = Sidecar::read(source_path).ok().flatten().unwrap_or_default();
+        update_sidecar_uid(&mut sidecar, source_path, new_path, uid);
+        self.place_slot(new_path, slot);
+        Some(uid)
+    }
+
+    /// Stores a processed asset at `relative_path`. An asset already there is replaced in place,
+    /// keeping its slot so existing handles see the new data.
+    fn insert_asset(&mut self, relative_path: &str, asset: Asset) -> SlotId {
+        let uid = asset.uid;
+        let segments = path_segments(relative_path);
+        if let Some(slot) = find_slot(&self.file_tree, &segments) {
+            if let Some(existing) = self.assets.get_mut(slot) {
+                if existing.uid != uid && self.uid_to_slot.get(&existing.uid) == Some(&slot) {
+                    self.uid_to_slot.remove(&existing.uid);
+                }
+                *existing = asset;
+                self.uid_to_slot.insert(uid, slot);
+                return slot;
+            }
+        }
+        let slot = self.assets.insert(asset);
+        self.uid_to_slot.insert(uid, slot);
+        self.place_slot(relative_path, slot);
+        slot
+    }
+
+    /// Removes an asset from storage once its tree node is gone.
+    fn remove_slot(&mut self, slot: SlotId) -> Option<Asset> {
+        let asset = self.assets.remove(slot)?;
+        if self.uid_to_slot.get(&asset.uid) == Some(&slot) {
+            self.uid_to_slot.remove(&asset.uid);
+        }
+        Some(asset)
+    }
+
+    fn place_slot(&mut self, relative_path: &str, slot: SlotId) {
+        let mut segments: Vec<String> = path_segments(relative_path).into_iter().map(|s| s.to_string()).collect();
+        let filename = segments.pop().unwrap();
+        match Self::get_node_and_create_if_none(&mut self.file_tree, segments) {
+            FileTreeNode::File(_) => unreachable!(),
+            FileTreeNode::Directory(map) => {
+                map.insert(filename, FileTreeNode::File(slot));
+            }
+        }
+    }
+
+    fn get_node_and_create_if_none(root: &mut FileTreeNode, path_segments: Vec<String>) -> &mut FileTreeNode {
+        let mut current_node = root;
+        for segment in path_segments.iter() {
+            match current_node {
+                FileTreeNode::File(_) => panic!("Directory node already exists as a file. This shouldn't be possible"),
+                FileTreeNode::Directory(ref mut map) => {
+                    if !map.contains_key(segment.as_str()) {
+                        map.insert(segment.clone(), FileTreeNode::Directory(HashMap::new()));
+                    }
+                    current_node = map.get_mut(segment.as_str()).unwrap();
+                }
+            }
+        }
+        current_node
+    }
+
+    /// The assets directly in a directory, not including subdirectories.
+    pub fn get_assets_in_directory(&self, path: &str) -> Option<Vec<&Asset>> {
+        let directory = self.resolve(path);
+        match find_node(&self.file_tree, &directory.segments())? {
+            FileTreeNode::Directory(map) => Some(map.values().filter_map(|node| match node {
+                FileTreeNode::File(slot) => self.assets.get(*slot),
+                FileTreeNode::Directory(_) => None
+            }).collect()),
+            FileTreeNode::File(_) => None
+        }
+    }
+
+    /// The subdirectories and assets directly in a directory, for showing a folder view.
+    /// Subdirectories come first, and both are sorted by name. Empty if the directory doesn't exist.
+    pub fn list_directory(&self, path: &str) -> Vec<DirEntryInfo<'_>> {
+        let directory = self.resolve(path);
+        let map = match find_node(&self.file_tree, &directory.segments()) {
+            Some(FileTreeNode::Directory(map)) => map,
+            _ => return Vec::new()
+        };
+        let mut entries: Vec<DirEntryInfo> = map.iter().filter_map(|(name, node)| {
+            let asset = match node {
+                FileTreeNode::File(slot) => Some(self.assets.get(*slot)?),
+                FileTreeNode::Directory(_) => None
+            };
+            Some(DirEntryInfo { name: name.clone(), path: join_path(&directory, name), asset })
+        }).collect();
+        entries.sort_by(|a, b| b.is_directory().cmp(&a.is_directory()).then_with(|| a.name.cmp(&b.name)));
+        entries
+    }
+
+    /// Every asset anywhere below a directory that `filter` accepts, with its path, in name order.
+    /// `None` if the directory doesn't exist.
+    pub fn get_assets_in_directory_recursive<F: Fn(&str, &Asset) -> bool>(&self, path: &str, filter: F) -> Option<Vec<(String, &Asset)>> {
+        let directory = self.resolve(path);
+        match find_node(&self.file_tree, &directory.segments())? {
+            node @ FileTreeNode::Directory(_) => {
+                Some(AssetIter::new(&self.assets, node, &directory).filter(|(path, asset)| filter(path, asset)).collect())
+            },
+            FileTreeNode::File(_) => None
+        }
+    }
+
+    pub fn get_asset(&self, path: &str) -> Option<&Asset> {
+        self.assets.get(self.slot_at(path)?)
+    }
+
+    fn slot_at(&self, path: &str) -> Option<SlotId> {
+        find_slot(&self.file_tree, &self.resolve(path).segments())
+    }
+
+    pub fn get_path_from_id(&self, id: AssetId) -> Option<&str> {
+        self.uid_to_path.get(&id).map(|path| &**path)
+    }
+
+    /// Takes a weak handle to the current version of an asset.
+    pub fn downgrade<T>(&self, handle: Handle<T>) -> WeakHandle<T> {
+        WeakHandle { handle, version: self.versions.get(&handle.uid).cloned().unwrap_or(0) }
+    }
+
+    /// Checks whether the asset behind a weak handle changed or was removed since it was taken.
+    pub fn check<T>(&self, weak: &WeakHandle<T>) -> HandleState {
+        if self.assets.get(weak.handle.slot).is_none_or(|a| a.uid != weak.handle.uid) {
+            return HandleState::Removed;
+        }
+        match self.versions.get(&weak.handle.uid).cloned().unwrap_or(0) == weak.version {
+            true => HandleState::Current,
+            false => HandleState::Changed
+        }
+    }
+
+    /// Gets the handle back if the asset is unchanged since the weak handle was taken.
+    pub fn upgrade<T>(&self, weak: &WeakHandle<T>) -> Option<Handle<T>> {
+        match self.check(weak) {
+            HandleState::Current => Some(weak.handle),
+            _ => None
+        }
+    }
+
+    /// Gets an asset by UID, e.g. when resolving references stored in serialized scenes.
+    pub fn get_asset_by_uid(&self, uid: AssetId) -> Option<&Asset> {
+        self.assets.get(*self.uid_to_slot.get(&uid)?)
+    }
+
+    /// Gets a typed handle to the asset at `path`, or `None` if there's no asset there or it isn't a `T`.
+    pub fn get<T: AssetKind>(&self, path: &str) -> Option<Handle<T>> {
+        let slot = self.slot_at(path)?;
+        self.handle_for_slot(slot)
+    }
+
+    /// `get` by UID.
+    pub fn get_by_uid<T: AssetKind>(&self, uid: AssetId) -> Option<Handle<T>> {
+        self.handle_for_slot(*self.uid_to_slot.get(&uid)?)
+    }
+
+    fn handle_for_slot<T: AssetKind>(&self, slot: SlotId) -> Option<Handle<T>> {
+        let asset = self.assets.get(slot)?;
+        T::from_data(&asset.data)?;
+        Some(Handle::new(asset.uid, slot))
+    }
+
+    /// Resolves a handle to the asset's processed data without any lookups. Returns `None` if the
+    /// asset was removed, even if another asset was added with the same UID since.
+    pub fn data<T: AssetKind>(&self, handle: Handle<T>) -> Option<&T> {
+        let asset = self.assets.get(handle.slot)?;
+        if asset.uid != handle.uid {
+            return None;
+        }
+        T::from_data(&asset.data)
+    }
+
+    /// Reports the CPU and GPU memory held by each asset, with totals per directory and type.
+    pub fn memory_report(&self) -> MemoryReport {
+        let gpu_bytes: HashMap<AssetId, usize> = self.cached_textures.read().iter().map(|(uid, texture)| (*uid, texture.gpu_bytes())).collect();
+        let mut report = MemoryReport::default();
+        for (path, asset) in self.iter_assets() {
+            report.add(&path, asset, gpu_bytes.get(&asset.uid).cloned().unwrap_or(0));
+        }
+        report.finish();
+        report
+    }
+
+    /// Number of assets of each type.
+    pub fn count_by_type(&self) -> HashMap<AssetType, usize> {
+        let mut counts = HashMap::new();
+        for (_, asset) in self.iter_assets() {
+            *counts.entry(asset.data.asset_type()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Combined size of every asset's source file.
+    pub fn total_source_bytes(&self) -> u64 {
+        self.iter_assets().map(|(_, asset)| asset.source_size).sum()
+    }
+
+    /// The `count` assets with the largest source files, largest first, with their relative paths.
+    pub fn largest_assets(&self, count: usize) -> Vec<(String, &Asset)> {
+        let mut assets: Vec<(String, &Asset)> = self.iter_assets().collect();
+        assets.sort_by_key(|(_, asset)| std::cmp::Reverse(asset.source_size));
+        assets.truncate(count);
+        assets
+    }
+
+    /// Asset counts and source sizes for every directory, rolled up through its parents. The
+    /// content root is `""`.
+    pub fn directory_stats(&self) -> HashMap<String, DirectoryStats> {
+        let mut stats: HashMap<String, DirectoryStats> = HashMap::new();
+        for (path, asset) in self.iter_assets() {
+            for dir in parent_directories(&path) {
+                let entry = stats.entry(dir.to_string()).or_default();
+                entry.asset_count += 1;
+                entry.source_bytes += asset.source_size;
+            }
+        }
+        stats
+    }
+
+    /// Groups assets whose source files have the same contents, most wasted space first.
+    pub fn find_duplicates(&self) -> Vec<DuplicateGroup> {
+        let mut by_content: HashMap<(u64, u64), Vec<String>> = HashMap::new();
+        for (path, asset) in self.iter_assets() {
+            by_content.entry((asset.content_hash, asset.source_size)).or_default().push(path);
+        }
+        let mut groups: Vec<DuplicateGroup> = by_content.into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|((content_hash, source_size), mut paths)| {
+                paths.sort();
+                DuplicateGroup { content_hash, source_size, paths }
+            })
+            .collect();
+        groups.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.paths.cmp(&b.paths)));
+        groups
+    }
+
+    /// Assets directly referenced by `uid`.
+    pub fn dependencies_of(&self, uid: AssetId) -> &[AssetId] {
+        self.dependencies.dependencies_of(uid)
+    }
+
+    /// Assets directly referencing `uid`, e.g. to list its usages before deleting it.
+    pub fn referencers_of(&self, uid: AssetId) -> &[AssetId] {
+        self.dependencies.referencers_of(uid)
+    }
+
+    /// Returns true if any other asset references `uid`, meaning deleting it would break them.
+    pub fn is_referenced(&self, uid: AssetId) -> bool {
+        self.referencers_of(uid).iter().any(|r| *r != uid)
+    }
+
+    /// Everything `uid` needs, directly or indirectly, e.g. for packing it into a bundle.
+    pub fn all_dependencies_of(&self, uid: AssetId) -> Vec<AssetId> {
+        self.dependencies.all_dependencies_of(uid)
+    }
+
+    /// Finds assets whose paths match `query`, best matches first. Exact and substring matches on
+    /// the filename rank highest, followed by matches elsewhere in the path and fuzzy matches.
+    pub fn search(&self, query: &str) -> Vec<SearchMatch> {
+        let mut matches: Vec<SearchMatch> = self.iter_assets()
+            .filter_map(|(path, asset)| {
+                search::score(query, &path).map(|score| SearchMatch { uid: asset.uid, path, score })
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+        matches
+    }
+
+    /// Finds assets whose relative paths match a glob pattern, in name order. `*` and `?` stay
+    /// within one path segment and `**` matches any number of directories, e.g.
+    /// `characters/**/*_albedo.png`.
+    pub fn find(&self, pattern: &str) -> Result<Vec<(String, &Asset)>, AssetRegistryError> {
+        let matcher = match globset::GlobBuilder::new(pattern.trim_start_matches('/')).literal_separator(true).build() {
+            Ok(glob) => glob.compile_matcher(),
+            Err(source) => return Err(AssetRegistryError::InvalidPattern { pattern: pattern.to_string(), source })
+        };
+        Ok(self.iter_assets().filter(|(path, _)| matcher.is_match(path)).collect())
+    }
+
+    /// Defines or replaces a collection. Collections defined this way aren't persisted.
+    pub fn define_collection(&mut self, name: &str, collection: AssetCollection) {
+        self.collections.insert(name.to_string(), collection);
+    }
+
+    pub fn remove_collection(&mut self, name: &str) -> Option<AssetCollection> {
+        self.collections.remove(name)
+    }
+
+    /// Names of every known collection, sorted.
+    pub fn collection_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.collections.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Every asset in a collection, in name order. Listed paths that don't resolve to an asset
+    /// are skipped.
+    pub fn collection_assets(&self, name: &str) -> Result<Vec<(String, &Asset)>, AssetRegistryError> {
+        let collection = match self.collections.get(name) {
+            Some(collection) => collection,
+            None => return Err(AssetRegistryError::UnknownCollection(name.to_string()))
+        };
+        let mut members = Vec::new();
+        for path in collection.assets.iter() {
+            if let Some(asset) = self.get_asset(path) {
+                members.push((self.resolve(path).into(), asset));
+            }
+        }
+        for pattern in collection.patterns.iter() {
+            members.extend(self.find(pattern)?);
+        }
+        for tag in collection.tags.iter() {
+            members.extend(self.assets_with_tag(tag));
+        }
+        members.sort_by(|a, b| a.0.cmp(&b.0));
+        members.dedup_by(|a, b| a.0 == b.0);
+        Ok(members)
+    }
+
+    /// Uploads every texture in a collection ahead of time. Textures that fail to upload are
+    /// returned, the rest of the collection is still loaded.
+    pub fn preload_collection(&self, name: &str) -> Result<Vec<UploadError>, AssetRegistryError> {
+        let paths: Vec<String> = self.collection_assets(name)?
+            .into_iter()
+            .filter(|(_, asset)| asset.data.asset_type() == AssetType::Texture)
+            .map(|(path, _)| path)
+            .collect();
+        let mut failed = Vec::new();
+        for path in paths {
+            if let Err(e) = self.get_texture(&path) {
+                failed.push(e);
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Every asset carrying `tag`, in name order.
+    pub fn assets_with_tag(&self, tag: &str) -> Vec<(String, &Asset)> {
+        self.iter_assets().filter(|(_, asset)| asset.tags.iter().any(|t| t == tag)).collect()
+    }
+
+    /// Replaces an asset's tags and saves them to its sidecar.
+    pub fn set_tags(&mut self, path: &str, tags: &[&str]) -> Result<(), AssetRegistryError> {
+        self.update_tags(path, |current| {
+            current.clear();
+            current.extend(tags.iter().map(|t| t.to_string()));
+        })
+    }
+
+    pub fn add_tag(&mut self, path: &str, tag: &str) -> Result<(), AssetRegistryError> {
+        self.update_tags(path, |current| current.push(tag.to_string()))
+    }
+
+    pub fn remove_tag(&mut self, path: &str, tag: &str) -> Result<(), AssetRegistryError> {
+        self.update_tags(path, |current| current.retain(|t| t != tag))
+    }
+
+    fn update_tags<F: FnOnce(&mut Vec<String>)>(&mut self, path: &str, f: F) -> Result<(), AssetRegistryError> {
+        let relative_path = self.relative_path(path);
+        let source_path = match self.source_path(&relative_path) {
+            Some(path) => path,
+            None => return Err(AssetRegistryError::PathDoesNotExist(relative_path.into()))
+        };
+        let asset = match find_slot(&self.file_tree, &relative_path.segments()) {
+            Some(slot) => self.assets.get_mut(slot),
+            None => None
+        };
+        let asset = match asset {
+            Some(asset) => asset,
+            None => return Err(AssetRegistryError::PathDoesNotExist(relative_path.into()))
+        };
+        let mut tags = asset.tags.clone();
+        f(&mut tags);
+        for tag in tags.iter_mut() {
+            *tag = tag.trim().to_string();
+        }
+        tags.retain(|t| !t.is_empty());
+        tags.sort();
+        tags.dedup();
+
+        let mut sidecar = Sidecar::read(&source_path)?.unwrap_or_default();
+        sidecar.uid = Some(asset.uid);
+        sidecar.tags = tags.clone();
+        sidecar.write(&source_path)?;
+        asset.tags = tags;
+        self.invalidate_snapshot();
+        Ok(())
+    }
+
+    /// Iterates every asset in name order, with its path relative to the content root.
+    pub fn iter_assets(&self) -> AssetIter<'_> {
+        AssetIter::new(&self.assets, &self.file_tree, "")
+    }
+
+    /// Iterates every directory in name order, starting with the content root as `""`.
+    pub fn iter_directories(&self) -> DirectoryIter<'_> {
+        DirectoryIter::new(&self.file_tree, "")
+    }
+
+    /// Walks the file tree with `visitor`, which can skip directories or stop the walk early.
+    pub fn visit(&self, visitor: &mut dyn TreeVisitor) {
+        walk(&self.assets, &self.file_tree, "", visitor);
+    }
+
+    /// Writes every asset's processed data into a pack file at `path`, so shipping builds can load
+    /// it without the sources or the import pipeline. Assets that fail to process are reported and
+    /// left out of the pack.
+    pub fn cook(&self, path: &str, options: &CookOptions) -> Result<CookReport, AssetRegistryError> {
+        self.cook_pack(path, options, None)
+    }
+
+    /// Writes a patch pack with only the assets that changed since the pack at `previous`, for
+    /// updating a shipped build without redistributing everything. Load it after the previous pack
+    /// (and any earlier patches) in `PackedRegistry`.
+    pub fn cook_patch(&self, path: &str, previous: &str, options: &CookOptions) -> Result<CookReport, AssetRegistryError> {
+        let mut file = File::open(previous).map_err(|e| AssetRegistryError::io(previous, e))?;
+        let index = PackIndex::read(&mut file).map_err(|source| AssetRegistryError::Pack { path: previous.to_string(), source })?;
+        self.cook_pack(path, options, Some((previous, &index)))
+    }
+
+    fn cook_pack(&self, pack_path: &str, options: &CookOptions, previous: Option<(&str, &PackIndex)>) -> Result<CookReport, AssetRegistryError> {
+        let _span = info_span!("cook", path = pack_path, platform = options.profile.platform.as_str()).entered();
+        let file = File::create(pack_path).map_err(|e| AssetRegistryError::io(pack_path, e))?;
+        let pack_error = |source| AssetRegistryError::Pack { path: pack_path.to_string(), source };
+        let mut writer = PackWriter::new(BufWriter::new(file)).map_err(pack_error)?;
+        if let Some(key) = &options.encryption {
+            writer.set_key(key.clone());
+        }
+        let variants = self.cook_variants(&options.profile);
+        let previous_entries: HashMap<(&str, Option<&str>), &PackEntry> = match previous {
+            Some((_, index)) => index.entries.iter().map(|entry| ((entry.path.as_str(), entry.tier.as_deref()), entry)).collect(),
+            None => HashMap::new()
+        };
+        let mut report = CookReport::default();
+        // processed a batch at a time, so only a batch's worth of data is in memory at once
+        let batch_size = self.import_thread_count() * 2;
+        for batch in variants.chunks(batch_size) {
+            let cooked = self.cook_batch(batch, &options.profile);
+            for ((path, asset, tier), result) in batch.iter().zip(cooked) {
+                match result {
+                    Ok((data, bytes, warnings)) => {
+                        report.warnings.extend(warnings);
+                        let unchanged = previous_entries.get(&(path.as_str(), *tier))
+                            .filter(|entry| entry.uid == asset.uid && entry.hash == entry_hash(&data, &bytes));
+                        if let (Some(entry), Some((previous_path, _))) = (unchanged, previous) {
+                            report.manifest.entries.push(ManifestEntry::new(entry, asset.content_hash, previous_path));
+                            report.unchanged += 1;
+                            continue;
+                        }
+                        let compression = options.compression_for(&data);
+                        writer.add(asset.uid, path, *tier, data, &bytes, compression).map_err(pack_error)?;
+                        if let Some(entry) = writer.entries().last() {
+                            report.manifest.entries.push(ManifestEntry::new(entry, asset.content_hash, pack_path));
+                        }
+                    },
+                    Err(e) => {
+                        warn!(path = e.path(), error = %e, "Cook failed");
+                        report.failed.push(e);
+                    }
+                }
+            }
+        }
+        if let Some((_, index)) = previous {
+            let current: HashSet<(&str, Option<&str>)> = variants.iter().map(|(path, _, tier)| (path.as_str(), *tier)).collect();
+            for entry in &index.entries {
+                if !current.contains(&(entry.path.as_str(), entry.tier.as_deref())) {
+                    writer.mark_removed(&entry.path, entry.tier.as_deref());
+                    report.removed += 1;
+                }
+            }
+        }
+        report.entries = writer.entry_count();
+        report.bytes_written = writer.finish().map_err(pack_error)?;
+        Ok(report)
+    }
+
+    /// Cooks every asset missing from the derived cache for `profile`, so later cooks only have to
+    /// copy data into packs. Does nothing without a derived cache.
+    pub fn fill_derived_cache(&self, profile: &CookProfile) -> Result<CookReport, AssetRegistryError> {
+        let mut report = CookReport::default();
+        let cache = match &self.derived_cache {
+            Some(cache) => cache,
+            None => return Ok(report)
+        };
+        let mut missing = self.cook_variants(profile);
+        missing.retain(|(_, asset, tier)| !cache.contains(derived_key(asset, profile, *tier)));
+        let batch_size = self.import_thread_count() * 2;
+        for batch in missing.chunks(batch_size) {
+            for result in self.cook_batch(batch, profile) {
+                match result {
+                    Ok((_, _, warnings)) => {
+                        report.entries += 1;
+                        report.warnings.extend(warnings);
+                    },
+                    Err(e) => {
+                        warn!(path = e.path(), error = %e, "Cook failed");
+                        report.failed.push(e);
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Keeps the derived cache up to date until `cancel` is cancelled: watches the content tree,
+    /// and every `interval` applies any changes and cooks whatever they affected. `on_update` is
+    /// called after each round that changed something, and the database is saved after each one.
+    pub fn watch_and_cook(&mut self, profile: &CookProfile, cancel: &CancellationToken, interval: Duration, on_update: &mut dyn FnMut(&ScanReport, &CookReport)) -> Result<(), AssetRegistryError> {
+        self.start_watching()?;
+        self.fill_derived_cache(profile)?;
+        while !cancel.is_cancelled() {
+            std::thread::sleep(interval);
+            let scan = self.pump_events()?;
+            if scan.is_empty() {
+                continue;
+            }
+            let cook = self.fill_derived_cache(profile)?;
+            self.save_database()?;
+            on_update(&scan, &cook);
+        }
+        Ok(())
+    }
+
+    /// Every asset with each variant `profile` cooks for it.
+    fn cook_variants<'a>(&'a self, profile: &'a CookProfile) -> Vec<(String, &'a Asset, Option<&'a str>)> {
+        let mut variants = Vec::new();
+        for (path, asset) in self.iter_assets().filter(|(_, asset)| !asset.generated) {
+            let tiers = match &asset.data {
+                AssetData::Texture(tex_data) => profile.texture_variants(tex_data),
+            };
+            variants.extend(tiers.into_iter().map(|tier| (path.clone(), asset, tier)));
+        }
+        variants
+    }
+
+    /// Produces the data stored in a pack for each variant in `batch`, in its order, on a
+    /// `JobGraph`. Each variant goes through a chain of jobs: decoding, or reading it from the
+    /// derived cache, processing it with the profile, block compressing it, then storing it in the
+    /// derived cache and uploading it to the remote cache if there are any. Variants read from the
+    /// cache pass through the rest of the chain.
+    fn cook_batch(&self, batch: &[(String, &Asset, Option<&str>)], profile: &CookProfile) -> Vec<Result<Cooked, ImportError>> {
+        let slots: Vec<Mutex<CookSlot>> = batch.iter().map(|_| Mutex::default()).collect();
+        let mut graph = JobGraph::new();
+        for ((path, asset, tier), slot) in batch.iter().zip(slots.iter()) {
+            let (path, tier) = (path.as_str(), *tier);
+            let key = derived_key(asset, profile, tier);
+            let cache = self.derived_cache.as_ref();
+            let AssetData::Texture(tex_data) = &asset.data;
+            let decode = graph.add(JobStage::Decode, move || {
+                let _span = debug_span!("cook", path, tier).entered();
+                let slot = &mut *slot.lock();
+                slot.step = Some(match cache.and_then(|cache| cache.get(key)) {
+                    Some((data, bytes)) => CookStep::Cooked(data, bytes, true),
+                    None => match self.decode_pixels(path, tex_data) {
+                        Ok(pixels) => {
+                            // the pixels are decoded here anyway, so this is where the checks needing
+                            // them run, on the base variant only so each asset is reported once
+                            if tier.is_none() {
+                                slot.warnings = validate_pixels(path, &tex_data.settings, &pixels);
+                            }
+                            CookStep::Decoded(pixels)
+                        },
+                        Err(e) => CookStep::Failed(e)
+                    }
+                });
+            });
+            let process = graph.add(JobStage::Process, move || {
+                let mut slot = slot.lock();
+                slot.step = match slot.step.take() {
+                    Some(CookStep::Decoded(pixels)) => {
+                        let overrides = profile.texture_overrides(tex_data, tier);
+                        match profile.process_texture(path, &tex_data.settings, &overrides, pixels) {
+                            Ok((settings, pixels)) => Some(CookStep::Processed(settings, pixels)),
+                            Err(e) => Some(CookStep::Failed(e))
+                        }
+                    },
+                    step => step
+                };
+            });
+            graph.depend(process, decode);
+            let compress = graph.add(JobStage::Compress, move || {
+                let mut slot = slot.lock();
+                slot.step = match slot.step.take() {
+                    Some(CookStep::Processed(settings, pixels)) => {
+                        match compress_texture(path, settings, pixels) {
+                            Ok((settings, bytes)) => Some(CookStep::Cooked(PackedData::Texture(settings), bytes, false)),
+                            Err(e) => Some(CookStep::Failed(e))
+                        }
+                    },
+                    step => step
+                };
+            });
+            graph.depend(compress, process);
+            let cache = match cache {
+                Some(cache) => cache,
+                None => continue
+            };
+            let cache_write = graph.add(JobStage::CacheWrite, move || {
+                let slot = &mut *slot.lock();
+                if let Some(CookStep::Cooked(data, bytes, false)) = &slot.step {
+                    match cache.put_local(key, data, bytes) {
+                        Ok(encoded) => slot.encoded = Some(encoded),
+                        Err(e) => warn!(path, error = %e, "Failed to store derived data")
+                    }
+                }
+            });
+            graph.depend(cache_write, compress);
+            if let Some(remote) = cache.remote.as_ref().filter(|remote| remote.upload) {
+                let upload = graph.add(JobStage::Upload, move || {
+                    let encoded = slot.lock().encoded.take();
+                    if let Some(encoded) = encoded {
+                        remote.put(key, &encoded);
+                    }
+                });
+                graph.depend(upload, cache_write);
+            }
+        }
+        graph.run(self.import_thread_count(), |_, _| true);
+        slots.into_iter().map(|slot| {
+            let slot = slot.into_inner();
+            match slot.step {
+                Some(CookStep::Cooked(data, bytes, _)) => Ok((data, bytes, slot.warnings)),
+                Some(CookStep::Failed(e)) => Err(e),
+                // nothing stops the graph early, so every chain finishes
+                _ => unreachable!("cook jobs stopped early")
+            }
+        }).collect()
+    }
+
+    /// A texture's RGBA8 pixels, from its CPU copy if it has one.
+    pub(crate) fn decode_pixels(&self, path: &str, tex_data: &TextureAssetData) -> Result<Vec<u8>, ImportError> {
+        let cpu_copy = tex_data.data.lock().clone();
+        match cpu_copy {
+            Some(pixels) => Ok(pixels),
+            None => decode_texture(&self.source_path(path).ok_or_else(|| unmounted(path))?, path, &tex_data.settings)
+        }
+    }
+
+    /// An asset's decoded RGBA8 pixels scaled down to fit in `max_size`, and their size, without
+    /// touching the GPU. For editor UIs and tools that render with their own backend. `None` if
+    /// there's no asset at `path` or it can't be decoded.
+    pub fn get_preview_rgba(&self, path: &str, max_size: u32) -> Option<(Vec<u8>, [u32; 2])> {
+        let relative_path = self.resolve(path);
+        let asset = self.get_asset(&relative_path)?;
+        match &asset.data {
+            AssetData::Texture(tex_data) => match self.decode_pixels(&relative_path, tex_data) {
+                Ok(pixels) => Some(downscale_rgba(pixels, tex_data.settings.source_size, max_size, tex_data.settings.srgb)),
+                Err(e) => {
+                    warn!(path = relative_path.as_str(), error = %e, "Failed to decode preview");
+                    None
+                }
+            }
+        }
+    }
+
+    /// A small RGBA8 preview of an asset and its size, for asset browsers. Thumbnails are cached
+    /// on disk by the source's content hash and adjustments if the registry has a thumbnail cache. `None` if
+    /// there's no asset at `path` or it can't be decoded.
+    pub fn get_thumbnail(&self, path: &str) -> Option<(Vec<u8>, [u32; 2])> {
+        let relative_path = self.resolve(path);
+        let asset = self.get_asset(&relative_path)?;
+        let cache = self.thumbnail_cache.as_ref();
+        let key = thumbnail_key(asset);
+        if let Some(thumbnail) = cache.and_then(|cache| cache.get(key)) {
+            return Some(thumbnail);
+        }
+        let (pixels, size) = self.get_preview_rgba(&relative_path, cache.map_or(DEFAULT_THUMBNAIL_SIZE, |cache| cache.size))?;
+        if let Some(cache) = cache {
+            if let Err(e) = cache.put(key, &pixels, size) {
+                warn!(path = relative_path.as_str(), error = %e, "Failed to store thumbnail");
+            }
+        }
+        Some((pixels, size))
+    }
+
+    /// Deletes cached thumbnails of sources that no longer exist, see `GcOptions` for what else.
+    /// Does nothing without a thumbnail cache.
+    pub fn collect_thumbnail_garbage(&self, options: &GcOptions) -> Result<GcReport, AssetRegistryError> {
+        match &self.thumbnail_cache {
+            Some(cache) => cache.collect_garbage(&self.assets.iter().map(|(_, asset)| thumbnail_key(asset)).collect(), options),
+            None => Ok(GcReport::default())
+        }
+    }
+
+    /// Deletes derived data that no current asset would be cooked to with any of `profiles`, i.e.
+    /// for sources or settings that no longer exist. See `GcOptions` for what else. Does nothing
+    /// without a derived cache.
+    pub fn collect_derived_garbage(&self, profiles: &[CookProfile], options: &GcOptions) -> Result<GcReport, AssetRegistryError> {
+        let cache = match &self.derived_cache {
+            Some(cache) => cache,
+            None => return Ok(GcReport::default())
+        };
+        let mut live = HashSet::new();
+        for (_, asset) in self.assets.iter().filter(|(_, asset)| !asset.generated) {
+            for profile in profiles {
+                match &asset.data {
+                    AssetData::Texture(tex_data) => {
+                        live.extend(profile.texture_variants(tex_data).into_iter().map(|tier| derived_key(asset, profile, tier)));
+                    }
+                }
+            }
+        }
+        cache.collect_garbage(&live, options)
+    }
+
+    /// Both `collect_derived_garbage` and `collect_thumbnail_garbage`.
+    pub fn collect_garbage(&self, profiles: &[CookProfile], options: &GcOptions) -> Result<GcReport, AssetRegistryError> {
+        let mut report = self.collect_derived_garbage(profiles, options)?;
+        report.merge(self.collect_thumbnail_garbage(options)?);
+        Ok(report)
+    }
+
+    /// Loads an asset on the background loader, so disk IO, decoding and upload all happen off the
+    /// calling thread. The returned future works with any executor.
+    pub fn load_async<T: AssetKind>(self: &Arc<Self>, path: &str) -> LoadFuture<T::Loaded> {
+        self.load_with_priority::<T>(path, LoadPriority::default())
+    }
+
+    /// `load_async`, queued behind any loads with a higher priority. Loads of assets with a more
+    /// urgent streaming hint are queued with that instead.
+    pub fn load_with_priority<T: AssetKind>(self: &Arc<Self>, path: &str, priority: LoadPriority) -> LoadFuture<T::Loaded> {
+        let (promise, future) = load_channel(path);
+        let registry = self.clone();
+        let uid = self.get_asset(path).map(|asset| asset.uid);
+        let priority = self.hinted_priority(uid, priority);
+        let path = path.to_string();
+        self.loader().submit_for(uid, priority, move || promise.complete(T::load(&registry, &path)));
+        future
+    }
+
+    /// Tells the loader and residency managers how soon an asset will be needed, e.g. because the
+    /// camera is heading towards it, see `LoadPriority::from_distance`. Loads already queued are
+    /// moved up to match.
+    pub fn set_streaming_hint(&self, path: &str, priority: LoadPriority) -> Result<AssetId, UploadError> {
+        let uid = match self.get_asset(path) {
+            Some(asset) => asset.uid,
+            None => return Err(UploadError::NotFound(self.resolve(path).into()))
+        };
+        self.streaming_hints.write().insert(uid, priority);
+        if let Some(loader) = self.loader.get() {
+            loader.reprioritize(uid, Some(priority));
+        }
+        Ok(uid)
+    }
+
+    /// Drops an asset's streaming hint. Returns whether it had one.
+    pub fn clear_streaming_hint(&self, uid: AssetId) -> bool {
+        let removed = self.streaming_hints.write().remove(&uid).is_some();
+        if let (true, Some(loader)) = (removed, self.loader.get()) {
+            loader.reprioritize(uid, None);
+        }
+        removed
+    }
+
+    pub fn streaming_hint(&self, uid: AssetId) -> Option<LoadPriority> {
+        self.streaming_hints.read().get(&uid).copied()
+    }
+
+    fn hinted_priority(&self, uid: Option<AssetId>, priority: LoadPriority) -> LoadPriority {
+        match uid.and_then(|uid| self.streaming_hint(uid)) {
+            Some(hint) => priority.max(hint),
+            None => priority
+        }
+    }
+
+    /// `get_texture`, but off the calling thread. See `load_async`.
+    pub fn load_texture_async(self: &Arc<Self>, path: &str) -> LoadFuture<Texture> {
+        self.load_async::<TextureAssetData>(path)
+    }
+
+    /// Loads a set of assets on the background loader, calling `on_complete` from a loader thread once
+    /// every one of them is loaded or has failed. Meant for loading screens and level streaming.
+    pub fn request_load_batch<F: FnOnce(BatchReport) + Send + 'static>(self: &Arc<Self>, paths: &[&str], on_complete: F) {
+        let batch = Batch::new(paths.len(), on_complete);
+        for path in paths.iter() {
+            let entry = batch.entry(path);
+            let registry = self.clone();
+            let uid = self.get_asset(path).map(|asset| asset.uid);
+            self.loader().submit_for(uid, self.hinted_priority(uid, LoadPriority::High), move || {
+                let asset_type = registry.get_asset(&entry.path).map(|asset| asset.data.asset_type());
+                match asset_type {
+                    Some(AssetType::Texture) => {
+                        let result = registry.get_texture(&entry.path);
+                        let path = entry.path.clone();
+                        entry.finish(|report| match result {
+                            Ok(texture) => { report.textures.insert(path, texture); },
+                            Err(e) => report.failed.push(e)
+                        });
+                    },
+                    None => {
+                        let error = UploadError::NotFound(entry.path.clone());
+                        entry.finish(|report| report.failed.push(error));
+                    }
+                }
+            });
+        }
+    }
+
+    /// `request_load_batch`, completing a future instead of calling back.
+    pub fn load_batch_async(self: &Arc<Self>, paths: &[&str]) -> LoadFuture<BatchReport> {
+        let (promise, future) = load_channel(&format!("batch of {} assets", paths.len()));
+        self.request_load_batch(paths, move |report| promise.complete(Ok(report)));
+        future
+    }
+
+    /// `get_texture` by UID.
+    pub fn get_texture_by_uid(&self, uid: AssetId) -> Result<Texture, UploadError> {
+        match self.uid_to_path.get(&uid) {
+            Some(path) => self.get_texture(path),
+            None => Err(UploadError::NotFound(uid.to_string()))
+        }
+    }
+
+    /// Gets the GPU texture for an asset, decoding and uploading it on first use. Threads asking for
+    /// the same uncached texture at once may each upload it, only one copy stays cached.
+    ///
+    /// The upload is only submitted, so join `flush_uploads` into the frame before using it. The
+    /// texture is a counted reference, and with a `texture_grace_period` it's unloaded after the
+    /// last clone outside the registry is dropped.
+    pub fn get_texture(&self, path: &str) -> Result<Texture, UploadError> {
+        let relative_path = self.resolve(path);
+        let asset = match find_slot(&self.file_tree, &relative_path.segments()).and_then(|slot| self.assets.get(slot)) {
+            Some(asset) => asset,
+            None => return Err(UploadError::NotFound(relative_path.into()))
+        };
+        let uid = asset.uid;
+        if let Some(texture) = self.cached_textures.read().get(&uid) {
+            if self.texture_grace_period.is_some() {
+                self.texture_unused_since.lock().remove(&uid);
+            }
+            return Ok(texture.clone());
+        }
+        let frame_queue = match &self.queue {
+            Some(queue) => queue,
+            None => return Err(UploadError::Headless(relative_path.into()))
+        };
+        let texture = match &asset.data {
+            AssetData::Texture(tex_data) => {
+                let decode_start = Instant::now();
+                // generated textures can't be decoded again, so they always keep their pixels
+                let cpu_copy = match asset.generated {
+                    true => tex_data.data.lock().clone(),
+                    false => tex_data.data.lock().take()
+                };
+                let pixels = match cpu_copy {
+                    Some(pixels) => pixels,
+                    None => {
+                        let source_path = match self.source_path(&relative_path) {
+                            Some(path) => path,
+                            None => return Err(UploadError::NotFound(relative_path.into()))
+                        };
+                        let pixels = decode_texture(&source_path, &relative_path, &tex_data.settings)?;
+                        self.metrics.write().entry(uid).decode = decode_start.elapsed();
+                        pixels
+                    }
+                };
+                let upload_start = Instant::now();
+                let queue = self.upload_queue(frame_queue, &tex_data.settings);
+                let (texture, future) = upload_texture_on(&pixels, &tex_data.settings, queue, frame_queue, &relative_path)?;
+                self.pending_uploads.push(future);
+                self.metrics.write().entry(uid).upload = upload_start.elapsed();
+                if self.keep_cpu_copies && !asset.generated {
+                    *tex_data.data.lock() = Some(pixels);
+                }
+                texture
+            },
+        };
+        self.cached_textures.write().insert(uid, texture.clone());
+        Ok(texture)
+    }
+
+    /// Where the next upload of a texture with these settings goes, see `transfer_queues`.
+    pub(crate) fn upload_queue<'a>(&'a self, frame_queue: &'a Arc<Queue>, settings: &TextureMetadata) -> &'a Arc<Queue> {
+        pick_upload_queue(frame_queue, &self.transfer_queues, &self.next_upload_queue, settings)
+    }
+
+    /// A new sampler for a texture asset, set up from its settings.
+    pub fn create_sampler(&self, path: &str) -> Result<Arc<Sampler>, UploadError> {
+        let relative_path = self.resolve(path);
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return Err(UploadError::Headless(relative_path.into()))
+        };
+        match self.get_asset(&relative_path).map(|asset| &asset.data) {
+            Some(AssetData::Texture(tex_data)) => tex_data.settings.create_sampler(queue.device())
+                .map_err(|source| UploadError::Sampler { path: relative_path.into(), source }),
+            None => Err(UploadError::NotFound(relative_path.into()))
+        }
+    }
+
+    /// A descriptor set binding the textures at `paths`, in order, to set `set_id` of `layout`, see
+    /// `descriptor`. Sets are cached by layout and textures, so asking again is cheap, until one of
+    /// the textures changes.
+    // TODO: take a material asset once there are any
+    pub fn texture_set(&self, layout: &Layout, set_id: usize, paths: &[&str]) -> Result<Arc<TextureSet>, UploadError> {
+        let mut uids = Vec::with_capacity(paths.len());
+        for path in paths {
+            match self.get_asset(path) {
+                Some(asset) => uids.push(asset.uid),
+                None => return Err(UploadError::NotFound(self.resolve(path).into()))
+            }
+        }
+        let key = (Arc::as_ptr(layout) as *const () as usize, set_id, uids);
+        if let Some(set) = self.cached_texture_sets.read().get(&key) {
+            return Ok(set.clone());
+        }
+        let mut textures = Vec::with_capacity(paths.len());
+        for path in paths {
+            textures.push((self.get_texture(path)?, self.create_sampler(path)?));
+        }
+        let set = Arc::new(TextureSet::new(layout.clone(), set_id, textures)?);
+        self.cached_texture_sets.write().insert(key, set.clone());
+        Ok(set)
+    }
+
+    /// Every upload since the last call, joined into one future for the renderer to wait on before
+    /// submitting a frame that uses the new textures. `None` for a headless registry. Also releases
+    /// unused textures, see `texture_grace_period`.
+    pub fn flush_uploads(&self) -> Option<UploadFuture> {
+        self.release_unused_textures();
+        self.queue.as_ref().map(|queue| self.pending_uploads.flush(queue.device()))
+    }
+
+    /// Evicts cached textures that have gone unused for `texture_grace_period`, along with the
+    /// cached texture sets using them, returning how many were evicted. A texture is unused once
+    /// the only clones left are the registry's own, which includes cached texture sets nothing
+    /// else holds.
+    pub fn release_unused_textures(&self) -> usize {
+        let grace_period = match self.texture_grace_period {
+            Some(grace_period) => grace_period,
+            None => return 0
+        };
+        let now = Instant::now();
+        let mut sets = self.cached_texture_sets.write();
+        let mut textures = self.cached_textures.write();
+        let mut unused_since = self.texture_unused_since.lock();
+        let mut held: HashMap<AssetId, usize> = HashMap::new();
+        for ((_, _, uids), set) in sets.iter() {
+            if Arc::strong_count(set) == 1 {
+                for uid in uids {
+                    *held.entry(*uid).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut released = Vec::new();
+        for (uid, texture) in textures.iter() {
+            if texture.references() > 1 + held.get(uid).copied().unwrap_or(0) {
+                unused_since.remove(uid);
+                continue;
+            }
+            let since = *unused_since.entry(*uid).or_insert(now);
+            if now.duration_since(since) >= grace_period {
+                released.push(*uid);
+            }
+        }
+        for uid in released.iter() {
+            textures.remove(uid);
+        }
+        sets.retain(|(_, _, uids), _| !uids.iter().any(|uid| released.contains(uid)));
+        unused_since.retain(|uid, _| textures.contains_key(uid));
+        released.len()
+    }
+}
+
+fn find_node<'a>(node: &'a FileTreeNode, segments: &[&str]) -> Option<&'a FileTreeNode> {
+    match (node, segments) {
+        (_, []) => Some(node),
+        (FileTreeNode::Directory(map), [name, rest @ ..]) => find_node(map.get(*name)?, rest),
+        _ => None
+    }
+}
+
+/// An asset's cooked settings and data, and the warnings from checking its pixels.
+type Cooked = (PackedData, Vec<u8>, Vec<ImportWarning>);
+
+/// How far a variant has got through the jobs of `AssetRegistry::cook_batch`.
+enum CookStep {
+    Decoded(Vec<u8>),
+    Processed(TextureMetadata, Vec<u8>),
+    /// Finished data, and whether it was read from the derived cache.
+    Cooked(PackedData, Vec<u8>, bool),
+    Failed(ImportError),
+}
+
+/// A variant's data, handed from one cook job to the next.
+#[derive(Default)]
+struct CookSlot {
+    step: Option<CookStep>,
+    warnings: Vec<ImportWarning>,
+    /// The derived cache entry, once it's stored, for uploading to the remote cache.
+    encoded: Option<Vec<u8>>,
+}
+
+fn find_slot(node: &FileTreeNode, segments: &[&str]) -> Option<SlotId> {
+    match find_node(node, segments)? {
+        FileTreeNode::File(slot) => Some(*slot),
+        FileTreeNode::Directory(_) => None
+    }
+}
+
+fn unmounted(relative_path: &str) -> ImportError {
+    ImportError::Io {
+        path: relative_path.to_string(),
+        source: std::io::Error::new(std::io::ErrorKind::NotFound, "not in any mounted directory")
+    }
+}
+
+/// Writes `uid` into the sidecar for `source_path` if it doesn't already hold it.
+fn update_sidecar_uid(sidecar: &mut Sidecar, source_path: &Path, relative_path: &str, uid: AssetId) {
+    // archives are read-only, their assets keep the UID derived from their path
+    if sidecar.uid != Some(uid) && archive::split_archive_path(source_path).is_none() {
+        sidecar.uid = Some(uid);
+        if let Err(e) = sidecar.write(source_path) {
+            warn!(path = relative_path, error = %e, "Failed to write sidecar");
+        }
+    }
+}
+
+/// Removes the asset node at `segments` from the tree, pruning any directories left empty. The
+/// asset itself stays in the arena.
+fn remove_asset_node(node: &mut FileTreeNode, segments: &[&str]) -> Option<SlotId> {
+    let map = match node {
+        FileTreeNode::File(_) => return None,
+        FileTreeNode::Directory(map) => map,
+    };
+    match segments {
+        [] => None,
+        [name] => {
+            match map.get(*name) {
+                Some(FileTreeNode::File(_)) => match map.remove(*name) {
+                    Some(FileTreeNode::File(slot)) => Some(slot),
+                    _ => unreachable!()
+                },
+                _ => None
+            }
+        },
+        [dir, rest @ ..] => {
+            let child = map.get_mut(*dir)?;
+            let removed = remove_asset_node(child, rest);
+            if let FileTreeNode::Directory(child_map) = child {
+                if child_map.is_empty() {
+                    map.remove(*dir);
+                }
+            }
+            removed
+        }
+    }
+}
+
+/// Records `relative_path` under `preferred_uid` in `uid_to_path`, unless that UID already belongs to
+/// a different path. Collisions are logged and re-rolled with a salted hash of the path until a free
+/// UID is found, so an existing mapping is never silently overwritten. The nil UID is re-rolled the
+/// same way. Returns the UID actually used.
+fn claim_uid(uid_to_path: &mut HashMap<AssetId, Arc<str>>, preferred_uid: AssetId, relative_path: Arc<str>) -> AssetId {
+    let mut uid = preferred_uid;
+    let mut salt = 0u32;
+    loop {
+        match uid_to_path.get(&uid) {
+            Some(existing) if *existing == relative_path => break,
+            Some(existing) => warn!(%uid, existing = &**existing, path = &*relative_path, "UID collision, re-rolling"),
+            None if uid.is_nil() => {},
+            None => break
+        }
+        salt += 1;
+        uid = uid_from_path(&format!("{}#{}", relative_path, salt));
+    }
+    uid_to_path.insert(uid, relative_path);
+    uid
+}
+
+// fails to compile if a field ever makes the registry unshareable between threads
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<AssetRegistry>();
+    assert_send_sync::<RegistrySnapshot>();
+};
+
+impl Drop for AssetRegistry {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_database() {
+            error!(error = %e, "Failed to save registry database");
+        }
     }
 }
@@ -0,0 +1,331 @@
+//! Streams textures onto the GPU a mip level at a time, within an upload budget per frame, so
+//! background streaming never causes a hitch.
+//!
+//! Mip level `n` is the texture scaled down by `2^n` on each side, to at least one pixel. A texture
+//! is first made resident at a small level, so there's something to draw right away, then refined
+//! one level per tick until it reaches the level it was requested at. Lowering a request swaps in
+//! the smaller level directly.
+//!
+//! Sources are decoded and scaled down to each level on the registry's background loader, and a
+//! level is uploaded by the first tick after its pixels are ready.
+
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::sync::Arc;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use tracing::debug_span;
+
+use crate::asset::{AssetData, AssetId};
+use crate::color::resize_rgba8;
+use crate::dither::DitherMode;
+use crate::loader::LoadPriority;
+use crate::error::UploadError;
+use crate::import::decode_texture;
+use crate::mips::{mip_count, mip_size};
+use crate::registry::AssetRegistry;
+use crate::texture::{Texture, TextureMetadata, upload_texture_on};
+
+
+/// Textures first become resident at the finest level no bigger than this on either side.
+const INITIAL_MIP_SIZE: u32 = 64;
+
+/// Pixels of a level, filled in by a job on the loader.
+type LevelSlot = Arc<Mutex<Option<Result<Arc<Vec<u8>>, UploadError>>>>;
+
+/// Fills a `LevelSlot`, with `Cancelled` if it's dropped first, i.e. the job panicked or the loader
+/// dropped it, so the texture fails instead of waiting on the slot forever.
+struct LevelPromise {
+    slot: LevelSlot,
+    path: String,
+    filled: bool,
+}
+
+impl LevelPromise {
+    fn fill(mut self, result: Result<Arc<Vec<u8>>, UploadError>) {
+        *self.slot.lock() = Some(result);
+        self.filled = true;
+    }
+}
+
+impl Drop for LevelPromise {
+    fn drop(&mut self) {
+        if !self.filled {
+            *self.slot.lock() = Some(Err(UploadError::Cancelled(std::mem::take(&mut self.path))));
+        }
+    }
+}
+
+/// Where a loader job gets a texture's full size pixels from.
+enum PixelSource {
+    Decoded(Arc<Vec<u8>>),
+    CpuCopy(Vec<u8>),
+    File(Option<PathBuf>),
+}
+
+#[derive(Debug)]
+struct StreamedTexture {
+    path: String,
+    /// Asset version the texture is being streamed from, see `AssetRegistry::versions`.
+    version: u64,
+    settings: TextureMetadata,
+    requested: u8,
+    resident: Option<(u8, Texture)>,
+    /// The resident texture is from an older version of the asset.
+    stale: bool,
+    /// Decoded full size pixels, kept until the requested level is resident. Shared with the
+    /// loader jobs, which fill it in.
+    pixels: Arc<Mutex<Option<Arc<Vec<u8>>>>>,
+    /// The level being prepared on the loader.
+    preparing: Option<(u8, LevelSlot)>,
+}
+
+impl StreamedTexture {
+    fn size(&self) -> [u32; 2] {
+        self.settings.max_ingame_size
+    }
+
+    /// The level to upload next, or `None` if the requested level is resident.
+    fn next_mip(&self) -> Option<u8> {
+        let initial = (0..mip_count(self.size())).find(|mip| {
+            let size = mip_size(self.size(), *mip);
+            size[0].max(size[1]) <= INITIAL_MIP_SIZE
+        }).unwrap_or(0);
+        match (&self.resident, self.stale) {
+            (None, _) | (Some(_), true) => Some(self.requested.max(initial)),
+            (Some((mip, _)), false) if *mip > self.requested => Some(mip - 1),
+            (Some((mip, _)), false) if *mip < self.requested => Some(self.requested),
+            _ => None
+        }
+    }
+}
+
+/// What a `ResidencyManager::tick` did.
+#[derive(Debug, Default)]
+pub struct TickReport {
+    pub uploaded_bytes: usize,
+    /// Textures whose resident level changed.
+    pub updated: Vec<AssetId>,
+    /// Textures still short of their requested level.
+    pub pending: usize,
+    /// Textures that failed to stream. Their requests are dropped.
+    pub failed: Vec<(AssetId, UploadError)>,
+}
+
+/// Tracks the requested and resident mip level of each streamed texture, and uploads towards the
+/// requested levels a budgeted amount at a time, see the module docs. Uploads are added to the
+/// registry's `flush_uploads`.
+#[derive(Debug)]
+pub struct ResidencyManager {
+    /// Most bytes uploaded per `tick`. A single level bigger than this still gets uploaded, alone
+    /// in its tick, so it can't get stuck.
+    pub upload_budget: usize,
+    textures: HashMap<AssetId, StreamedTexture>,
+}
+
+impl ResidencyManager {
+    pub fn new(upload_budget: usize) -> Self {
+        Self { upload_budget, textures: HashMap::new() }
+    }
+
+    /// Requests a texture at a mip level, 0 being full size. Levels past the smallest are clamped
+    /// to it. Nothing is uploaded until the next `tick`.
+    pub fn request(&mut self, registry: &AssetRegistry, path: &str, mip: u8) -> Result<AssetId, UploadError> {
+        let asset = match registry.get_asset(path) {
+            Some(asset) => asset,
+            None => return Err(UploadError::NotFound(path.to_string()))
+        };
+        let settings = match &asset.data {
+            AssetData::Texture(tex_data) => &tex_data.settings,
+        };
+        let mip = mip.min(mip_count(settings.max_ingame_size) - 1);
+        let texture = self.textures.entry(asset.uid).or_insert_with(|| StreamedTexture {
+            path: registry.resolve(path).into_string(),
+            version: registry.versions.get(&asset.uid).copied().unwrap_or(0),
+            settings: settings.clone(),
+            requested: mip,
+            resident: None,
+            stale: false,
+            pixels: Arc::default(),
+            preparing: None,
+        });
+        texture.requested = mip;
+        Ok(asset.uid)
+    }
+
+    /// Stops streaming a texture and drops it. Returns whether it was being streamed.
+    pub fn release(&mut self, uid: AssetId) -> bool {
+        self.textures.remove(&uid).is_some()
+    }
+
+    /// The texture's resident level, if any is resident yet.
+    pub fn get(&self, uid: AssetId) -> Option<&Texture> {
+        self.textures.get(&uid)?.resident.as_ref().map(|(_, texture)| texture)
+    }
+
+    pub fn requested_mip(&self, uid: AssetId) -> Option<u8> {
+        self.textures.get(&uid).map(|texture| texture.requested)
+    }
+
+    pub fn resident_mip(&self, uid: AssetId) -> Option<u8> {
+        self.textures.get(&uid)?.resident.as_ref().map(|(mip, _)| *mip)
+    }
+
+    /// Whether every texture has its requested level resident.
+    pub fn is_settled(&self) -> bool {
+        self.textures.values().all(|texture| texture.next_mip().is_none())
+    }
+
+    /// GPU memory used by the resident textures.
+    pub fn resident_bytes(&self) -> usize {
+        self.textures.values().filter_map(|texture| texture.resident.as_ref()).map(|(_, texture)| texture.gpu_bytes()).sum()
+    }
+
+    /// Uploads towards the requested levels, up to `upload_budget` bytes. Textures with nothing
+    /// resident go first, then those with the most urgent streaming hint, where no hint counts as
+    /// `Low`, then those furthest from their requested level. Levels still being prepared on the
+    /// loader are skipped until they're ready. Call once per frame.
+    ///
+    /// Textures whose asset was removed are dropped, and those whose asset changed are streamed
+    /// again, keeping the old texture resident meanwhile.
+    pub fn tick(&mut self, registry: &AssetRegistry) -> TickReport {
+        let mut report = TickReport::default();
+        self.textures.retain(|uid, texture| {
+            let asset = match registry.get_asset_by_uid(*uid) {
+                Some(asset) => asset,
+                None => return false
+            };
+            let version = registry.versions.get(uid).copied().unwrap_or(0);
+            if version != texture.version {
+                texture.version = version;
+                texture.stale = texture.resident.is_some();
+                // jobs still running for the old version fill in what's dropped here
+                texture.pixels = Arc::default();
+                texture.preparing = None;
+                match &asset.data {
+                    AssetData::Texture(tex_data) => texture.settings = tex_data.settings.clone(),
+                }
+                texture.requested = texture.requested.min(mip_count(texture.size()) - 1);
+            }
+            // follows the asset if it moved
+            if let Some(path) = registry.get_path_from_id(*uid) {
+                if texture.path != path {
+                    texture.path = path.to_string();
+                }
+            }
+            true
+        });
+
+        let mut queue: Vec<(AssetId, u8)> = self.textures.iter().filter_map(|(uid, texture)| Some((*uid, texture.next_mip()?))).collect();
+        let hints = registry.streaming_hints.read();
+        queue.sort_by_key(|(uid, _)| {
+            let texture = &self.textures[uid];
+            let resident = texture.resident.as_ref().filter(|_| !texture.stale).map(|(resident, _)| *resident);
+            let hint = hints.get(uid).copied().unwrap_or(LoadPriority::Low);
+            (resident.is_some(), Reverse(hint.rank()), Reverse(resident.map_or(0, |resident| resident.abs_diff(texture.requested))))
+        });
+        drop(hints);
+        for (uid, mip) in queue {
+            let texture = self.textures.get_mut(&uid).unwrap();
+            let ready = match &texture.preparing {
+                Some((preparing, slot)) if *preparing == mip => slot.lock().is_some(),
+                Some(_) | None => {
+                    prepare_mip(registry, uid, texture, mip);
+                    false
+                }
+            };
+            let size = mip_size(texture.size(), mip);
+            let bytes = size[0] as usize * size[1] as usize * 4;
+            if !ready || (report.uploaded_bytes > 0 && report.uploaded_bytes + bytes > self.upload_budget) {
+                report.pending += 1;
+                continue;
+            }
+            let pixels = match texture.preparing.take().and_then(|(_, slot)| slot.lock().take()) {
+                Some(pixels) => pixels,
+                None => continue
+            };
+            match pixels.and_then(|pixels| upload_mip(registry, texture, mip, &pixels)) {
+                Ok(uploaded) => {
+                    texture.resident = Some((mip, uploaded));
+                    texture.stale = false;
+                    report.uploaded_bytes += bytes;
+                    report.updated.push(uid);
+                    match texture.next_mip() {
+                        Some(next) => {
+                            prepare_mip(registry, uid, texture, next);
+                            report.pending += 1;
+                        },
+                        None => texture.pixels = Arc::default()
+                    }
+                },
+                Err(e) => {
+                    self.textures.remove(&uid);
+                    report.failed.push((uid, e));
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Starts preparing a level's pixels on the registry's loader, decoding the source first unless
+/// its pixels are kept already.
+fn prepare_mip(registry: &AssetRegistry, uid: AssetId, texture: &mut StreamedTexture, mip: u8) {
+    let decoded = texture.pixels.clone();
+    let kept = decoded.lock().clone();
+    // the job can't borrow the registry, so it takes along what it needs to decode
+    let source = match kept {
+        Some(pixels) => PixelSource::Decoded(pixels),
+        None => match registry.get_asset_by_uid(uid).map(|asset| &asset.data) {
+            Some(AssetData::Texture(tex_data)) => match tex_data.data.lock().clone() {
+                Some(pixels) => PixelSource::CpuCopy(pixels),
+                None => PixelSource::File(registry.source_path(&texture.path))
+            },
+            None => PixelSource::File(None)
+        }
+    };
+    let slot = LevelSlot::default();
+    texture.preparing = Some((mip, slot.clone()));
+    let path = texture.path.clone();
+    let settings = texture.settings.clone();
+    let priority = registry.streaming_hints.read().get(&uid).copied().unwrap_or(LoadPriority::Low);
+    let promise = LevelPromise { slot, path: path.clone(), filled: false };
+    registry.loader().submit_for(Some(uid), priority, move || {
+        let _span = debug_span!("prepare_mip", path = path.as_str(), mip).entered();
+        let full = match source {
+            PixelSource::Decoded(pixels) => Ok(pixels),
+            PixelSource::CpuCopy(pixels) => Ok(Arc::new(pixels)),
+            PixelSource::File(Some(source_path)) => decode_texture(&source_path, &path, &settings).map(Arc::new).map_err(UploadError::from),
+            PixelSource::File(None) => Err(UploadError::NotFound(path.clone()))
+        };
+        let full_size = settings.max_ingame_size;
+        // the source can have changed size since it was imported
+        let full = full.and_then(|full| match full.len() == full_size[0] as usize * full_size[1] as usize * 4 {
+            true => Ok(full),
+            false => Err(UploadError::SizeMismatch(path.clone()))
+        });
+        let level = full.and_then(|full| {
+            *decoded.lock() = Some(full.clone());
+            match mip {
+                0 => Ok(full),
+                _ => resize_rgba8(&full, full_size, mip_size(full_size, mip), settings.srgb, DitherMode::None)
+                    .map(Arc::new)
+                    .ok_or_else(|| UploadError::SizeMismatch(path.clone()))
+            }
+        });
+        promise.fill(level);
+    });
+}
+
+fn upload_mip(registry: &AssetRegistry, texture: &StreamedTexture, mip: u8, pixels: &[u8]) -> Result<Texture, UploadError> {
+    let frame_queue = match &registry.queue {
+        Some(queue) => queue,
+        None => return Err(UploadError::Headless(texture.path.clone()))
+    };
+    let size = mip_size(texture.size(), mip);
+    let settings = TextureMetadata { max_ingame_size: size, data_size: [size[0] * size[1] * 4, 0], ..texture.settings.clone() };
+    let queue = registry.upload_queue(frame_queue, &settings);
+    let (uploaded, future) = upload_texture_on(pixels, &settings, queue, frame_queue, &texture.path)?;
+    registry.pending_uploads.push(future);
+    Ok(uploaded)
+}
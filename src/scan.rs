@@ -0,0 +1,182 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::asset::{AssetId, AssetType};
+use crate::error::ImportError;
+use crate::metrics::AssetTimings;
+use crate::validate::ImportWarning;
+
+
+/// An asset touched by a scan, identified by its UID and path relative to the content root.
+#[derive(Debug, Clone)]
+pub struct ScanEntry {
+    pub uid: AssetId,
+    pub path: String,
+}
+
+impl ScanEntry {
+    pub fn new(uid: AssetId, path: &str) -> Self {
+        Self { uid, path: path.to_string() }
+    }
+}
+
+/// An asset whose source file was moved or renamed. It keeps its UID and processed data.
+#[derive(Debug, Clone)]
+pub struct MovedEntry {
+    pub uid: AssetId,
+    pub from: String,
+    pub to: String,
+}
+
+
+
+/// Summary of what a `rescan` changed in the registry.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Assets imported for the first time.
+    pub added: Vec<ScanEntry>,
+    /// Existing assets that were reprocessed because their source changed.
+    pub modified: Vec<ScanEntry>,
+    /// Assets whose source files no longer exist. They have been removed from the file tree,
+    /// `uid_to_path`, and the texture cache.
+    pub removed: Vec<ScanEntry>,
+    pub moved: Vec<MovedEntry>,
+    /// Assets that were forcibly reprocessed by `reimport_all`.
+    pub reimported: Vec<ScanEntry>,
+    /// Files that couldn't be imported. One bad file never aborts the rest of the scan.
+    pub failed: Vec<ImportError>,
+    /// Imported assets that don't meet content standards, e.g. oversized or non-power-of-two
+    /// tiling textures. Checks that need decoded pixels are reported by cooks instead, see
+    /// `validate`.
+    pub warnings: Vec<ImportWarning>,
+    /// The scan was cancelled partway through. Files processed before that are reflected in the
+    /// report, but deletions aren't pruned since not every file was looked at.
+    pub cancelled: bool,
+    /// Total time spent in each pipeline stage by the assets this scan processed.
+    pub timings: AssetTimings,
+}
+
+impl ScanReport {
+    /// Returns true if the scan didn't change anything.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+            && self.moved.is_empty() && self.reimported.is_empty() && self.failed.is_empty()
+    }
+
+    /// Appends another report's entries to this one.
+    pub fn merge(&mut self, other: ScanReport) {
+        self.added.extend(other.added);
+        self.modified.extend(other.modified);
+        self.removed.extend(other.removed);
+        self.moved.extend(other.moved);
+        self.reimported.extend(other.reimported);
+        self.failed.extend(other.failed);
+        self.warnings.extend(other.warnings);
+        self.cancelled |= other.cancelled;
+        self.timings += other.timings;
+    }
+
+    /// UIDs of every asset whose data changed or disappeared, e.g. for invalidating GPU resources.
+    pub fn changed_uids(&self) -> Vec<AssetId> {
+        self.modified.iter().chain(self.removed.iter()).chain(self.reimported.iter()).map(|e| e.uid).collect()
+    }
+}
+
+
+/// How scans treat symbolic links, and junctions on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkMode {
+    /// Links are ignored.
+    Skip,
+    /// Links to files are imported, links to directories are ignored.
+    #[default]
+    FilesOnly,
+    /// Links are followed as if they were the files and directories they point to. Links that
+    /// loop back to a directory above themselves are skipped with a warning.
+    Follow,
+}
+
+
+/// Selects which assets `reimport_all` reprocesses. The default reprocesses everything.
+#[derive(Debug, Clone, Default)]
+pub struct ReimportOptions {
+    /// Only reimport assets of this type.
+    pub asset_type: Option<AssetType>,
+    /// Only reimport assets in this directory (relative to the content root) or below it.
+    pub directory: Option<String>,
+    /// Rewrite every sidecar in the current format after reimporting, so settings added since it
+    /// was written are stored with their defaults instead of being implied.
+    pub migrate_sidecars: bool,
+    pub cancel: Option<CancellationToken>,
+}
+
+
+/// Snapshot of a scan's progress, passed to `ScanOptions::progress` once the files to scan have
+/// been discovered and again after each file is processed.
+#[derive(Debug, Clone)]
+pub struct ScanProgress<'a> {
+    pub files_discovered: usize,
+    pub files_processed: usize,
+    /// Path of the file that was just processed, relative to the content root. Empty before the first file.
+    pub current_file: &'a str,
+    pub bytes_discovered: u64,
+    pub bytes_processed: u64,
+}
+
+impl<'a> ScanProgress<'a> {
+    pub(crate) fn skip(&mut self, file: &'a str, bytes: u64) {
+        self.files_processed += 1;
+        self.bytes_processed += bytes;
+        self.current_file = file;
+    }
+}
+
+/// Optional hooks for a single `rescan_with` call.
+#[derive(Default)]
+pub struct ScanOptions<'a> {
+    pub progress: Option<&'a mut dyn FnMut(&ScanProgress)>,
+    /// Checked between files; once cancelled the scan stops and returns what it has done so far.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl<'a> ScanOptions<'a> {
+    pub fn with_progress(mut self, progress: &'a mut dyn FnMut(&ScanProgress)) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+    }
+
+    pub(crate) fn report_progress(&mut self, progress: &ScanProgress) {
+        if let Some(callback) = self.progress.as_mut() {
+            callback(progress);
+        }
+    }
+}
+
+
+/// Shared flag used to abort long-running operations (scans, reimports) from another thread.
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::asset::AssetId;
+
+
+/// An asset matched by `AssetRegistry::search`.
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    pub uid: AssetId,
+    /// Path relative to the content root.
+    pub path: String,
+    /// Higher is better. Only meaningful relative to other matches for the same query.
+    pub score: u32,
+}
+
+/// Scores how well `query` matches an asset path, or `None` if it doesn't match at all. Matching is
+/// case-insensitive. Matches on the filename beat matches elsewhere in the path, and substring
+/// matches beat fuzzy ones, where the query's characters only appear in order.
+pub fn score(query: &str, path: &str) -> Option<u32> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return None;
+    }
+    let path = path.to_lowercase();
+    let filename = path.rsplit('/').next().unwrap_or(&path);
+    let stem = filename.split('.').next().unwrap_or(filename);
+
+    if stem == query || filename == query {
+        return Some(1000);
+    }
+    if filename.starts_with(&query) {
+        return Some(900 - length_penalty(filename.len() - query.len()));
+    }
+    if filename.contains(&query) {
+        return Some(800 - length_penalty(filename.len() - query.len()));
+    }
+    if path.contains(&query) {
+        return Some(700 - length_penalty(path.len() - query.len()));
+    }
+    fuzzy_score(&query, &path).map(|s| s.min(600))
+}
+
+fn length_penalty(extra_chars: usize) -> u32 {
+    extra_chars.min(99) as u32
+}
+
+/// Subsequence match, rewarding runs of consecutive characters and matches at word starts.
+fn fuzzy_score(query: &str, path: &str) -> Option<u32> {
+    let path: Vec<char> = path.chars().collect();
+    let mut score = 0u32;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for c in query.chars() {
+        let found = path[position..].iter().position(|p| *p == c)? + position;
+        score += 10;
+        if previous_match == Some(found.wrapping_sub(1)) {
+            score += 15;
+        }
+        if found == 0 || ['/', '_', '-', '.', ' '].contains(&path[found - 1]) {
+            score += 10;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    // prefer tighter matches
+    let span = previous_match.unwrap_or(0) + 1;
+    Some(score.saturating_sub(length_penalty(span - query.chars().count())))
+}
@@ -0,0 +1,124 @@
+//! Serde shims for foreign types (vulkano, toolbelt, bitflags) that don't implement
+//! `Serialize`/`Deserialize` themselves.
+
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error as DeError;
+use vulkano::sampler::{Filter, SamplerAddressMode, BorderColor};
+use vulkano::format::Format;
+use toolbelt::color::LinearColor;
+
+use crate::texture::{ChannelMask, SUPPORTED_FORMATS};
+
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Filter")]
+pub enum FilterDef {
+    Linear,
+    Nearest,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "BorderColor")]
+pub enum BorderColorDef {
+    FloatTransparentBlack,
+    IntTransparentBlack,
+    FloatOpaqueBlack,
+    IntOpaqueBlack,
+    FloatOpaqueWhite,
+    IntOpaqueWhite,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SamplerAddressMode")]
+pub enum SamplerAddressModeDef {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder(#[serde(with = "BorderColorDef")] BorderColor),
+    MirrorClampToEdge,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "LinearColor")]
+pub struct LinearColorDef {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+/// `Format` is serialized by name, and only formats the pipeline knows how to produce are accepted.
+pub mod format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(format: &Format, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", format))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Format, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        SUPPORTED_FORMATS.iter()
+                         .find(|f| format!("{:?}", f) == name)
+                         .cloned()
+                         .ok_or_else(|| D::Error::custom(format!("Unsupported texture format: {}", name)))
+    }
+}
+
+/// `ChannelMask` is written as its channel letters (e.g. `"RGB"`) in human-readable formats like
+/// sidecars, and as the raw bits otherwise. Sidecars that stored the raw bits still load.
+pub mod channel_mask {
+    use std::fmt::Formatter;
+    use serde::de::Visitor;
+    use super::*;
+
+    const CHANNELS: [(char, ChannelMask); 4] = [
+        ('R', ChannelMask::RED),
+        ('G', ChannelMask::GREEN),
+        ('B', ChannelMask::BLUE),
+        ('A', ChannelMask::ALPHA),
+    ];
+
+    pub fn serialize<S: Serializer>(mask: &ChannelMask, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let letters: String = CHANNELS.iter().filter(|(_, channel)| mask.contains(*channel)).map(|(letter, _)| letter).collect();
+            serializer.serialize_str(&letters)
+        }
+        else {
+            serializer.serialize_u8(mask.bits())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ChannelMask, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(ChannelMaskVisitor)
+        }
+        else {
+            let bits = u8::deserialize(deserializer)?;
+            ChannelMaskVisitor.visit_u64(bits as u64)
+        }
+    }
+
+    struct ChannelMaskVisitor;
+
+    impl<'de> Visitor<'de> for ChannelMaskVisitor {
+        type Value = ChannelMask;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "channel letters like \"RGBA\"")
+        }
+
+        fn visit_u64<E: DeError>(self, bits: u64) -> Result<ChannelMask, E> {
+            let mask = if bits <= u8::MAX as u64 { ChannelMask::from_bits(bits as u8) } else { None };
+            mask.ok_or_else(|| E::custom(format!("Invalid channel mask: {:#x}", bits)))
+        }
+
+        fn visit_str<E: DeError>(self, letters: &str) -> Result<ChannelMask, E> {
+            letters.chars().try_fold(ChannelMask::empty(), |mask, letter| {
+                match CHANNELS.iter().find(|(l, _)| *l == letter.to_ascii_uppercase()) {
+                    Some((_, channel)) => Ok(mask | *channel),
+                    None => Err(E::custom(format!("Invalid channel '{}' in channel mask", letter)))
+                }
+            })
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! A local server exposing a running registry to external tools, e.g. an asset browser app or a
+//! DCC plugin, without them linking the crate.
+//!
+//! Clients connect over TCP on localhost, since the server refuses to listen anywhere else, and
+//! send one JSON request per line. Each request gets a one line JSON response, either
+//! `{"ok": true, ...}` or `{"ok": false, "error": "..."}`:
+//!
+//! - `{"cmd": "list", "path": "textures"}`: the assets and subdirectories directly in a directory
+//! - `{"cmd": "get", "path": "textures/grass.png"}`, or by `"uid"`: one asset's metadata
+//! - `{"cmd": "tagged", "tag": "ui"}`: every asset with a tag
+//! - `{"cmd": "fetch", "path": "textures/grass.png", "tier": "low"}`: cooked data from the derived
+//!   cache, `tier` being optional. The response has the entry's `data` and its `length`, and is
+//!   followed by exactly `length` bytes of raw data.
+//! - `{"cmd": "subscribe"}`: after the response, the connection gets one line per change, e.g.
+//!   `{"event": "modified", "uid": "...", "path": "..."}`, and takes no more requests.
+//!
+//! Answers come from the registry's latest snapshot, which the owner of the registry publishes by
+//! calling `AssetServer::update` after changing it, e.g. once per frame.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use hashbrown::HashMap;
+use parking_lot::RwLock;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::asset::{AssetData, AssetId};
+use crate::cook::CookProfile;
+use crate::derived::{DerivedCache, derived_key};
+use crate::error::AssetRegistryError;
+use crate::event::AssetEvent;
+use crate::pack::PackedData;
+use crate::path::AssetPath;
+use crate::registry::AssetRegistry;
+use crate::snapshot::{AssetInfo, RegistrySnapshot};
+
+
+/// Slow subscribers are dropped rather than holding up `update`: once this many event lines are
+/// waiting to be written, or a write takes longer than the timeout.
+const SUBSCRIBER_QUEUE_LENGTH: usize = 1024;
+const SUBSCRIBER_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+
+struct ServerState {
+    snapshot: Arc<RegistrySnapshot>,
+    derived_cache: Option<DerivedCache>,
+    /// Derived cache key of every cooked variant, by path and tier.
+    derived_keys: HashMap<(String, Option<String>), u64>,
+    /// Event lines for each subscriber, written by its connection's thread.
+    subscribers: Vec<SyncSender<Arc<str>>>,
+}
+
+/// Serves a registry to other processes, see the module docs for the protocol. Stops when dropped.
+pub struct AssetServer {
+    pub address: SocketAddr,
+    /// Profile that `fetch` requests get cooked data for.
+    pub profile: CookProfile,
+    state: Arc<RwLock<ServerState>>,
+    events: Receiver<AssetEvent>,
+    running: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl AssetServer {
+    /// Starts serving on `address`, e.g. `127.0.0.1:7878`, or port 0 for any free port. Clients
+    /// aren't authenticated, so addresses other than loopback ones are refused.
+    pub fn start(registry: &mut AssetRegistry, address: &str, profile: CookProfile) -> Result<Self, AssetRegistryError> {
+        let resolved: Vec<SocketAddr> = address.to_socket_addrs().map_err(|e| AssetRegistryError::io(address, e))?.collect();
+        // checked before binding, so nothing else can connect even briefly
+        if let Some(remote) = resolved.iter().find(|resolved| !resolved.ip().is_loopback()) {
+            return Err(AssetRegistryError::InvalidConfig(format!("asset server address {} isn't a loopback address", remote)));
+        }
+        let listener = TcpListener::bind(&resolved[..]).map_err(|e| AssetRegistryError::io(address, e))?;
+        let address = listener.local_addr().map_err(|e| AssetRegistryError::io(address, e))?;
+        let state = Arc::new(RwLock::new(ServerState {
+            snapshot: Arc::new(RegistrySnapshot::default()),
+            derived_cache: None,
+            derived_keys: HashMap::new(),
+            subscribers: Vec::new(),
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+        let accept_thread = {
+            let state = state.clone();
+            let running = running.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            warn!(error = %e, "Failed to accept asset server connection");
+                            continue;
+                        }
+                    };
+                    let state = state.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = handle_connection(stream, &state) {
+                            debug!(error = %e, "Asset server connection closed");
+                        }
+                    });
+                }
+            })
+        };
+        let mut server = Self {
+            address,
+            profile,
+            state,
+            events: registry.subscribe(),
+            running,
+            accept_thread: Some(accept_thread),
+        };
+        server.update(registry);
+        Ok(server)
+    }
+
+    /// Publishes the registry's current state to clients and sends subscribers the events since
+    /// the last update.
+    pub fn update(&mut self, registry: &AssetRegistry) {
+        let snapshot = registry.snapshot();
+        let mut state = self.state.write();
+        if !Arc::ptr_eq(&snapshot, &state.snapshot) {
+            let mut derived_keys = HashMap::new();
+            for (path, asset) in registry.iter_assets().filter(|(_, asset)| !asset.generated) {
+                match &asset.data {
+                    AssetData::Texture(tex_data) => {
+                        for tier in self.profile.texture_variants(tex_data) {
+                            derived_keys.insert((path.clone(), tier.map(str::to_string)), derived_key(asset, &self.profile, tier));
+                        }
+                    }
+                }
+            }
+            state.snapshot = snapshot;
+            state.derived_keys = derived_keys;
+        }
+        state.derived_cache = registry.derived_cache.clone();
+
+        drop(state);
+
+        let lines: Vec<Arc<str>> = self.events.try_iter().map(|event| Arc::from(format!("{}\n", event_json(&event)))).collect();
+        if !lines.is_empty() {
+            // only queued here, each subscriber's connection thread does the writing
+            self.state.write().subscribers.retain(|subscriber| lines.iter().all(|line| subscriber.try_send(line.clone()).is_ok()));
+        }
+    }
+}
+
+impl Drop for AssetServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        // wakes the accept loop up so it sees it should stop
+        let _ = TcpStream::connect(self.address);
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+        // their connection threads close the connections once the queues are gone
+        self.state.write().subscribers.clear();
+    }
+}
+
+
+fn handle_connection(stream: TcpStream, state: &RwLock<ServerState>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                write_line(&mut writer, &error(format!("Invalid request: {}", e)))?;
+                continue;
+            }
+        };
+        match request["cmd"].as_str() {
+            Some("subscribe") => {
+                write_line(&mut writer, &json!({ "ok": true }))?;
+                writer.set_write_timeout(Some(SUBSCRIBER_WRITE_TIMEOUT))?;
+                let (sender, receiver) = sync_channel::<Arc<str>>(SUBSCRIBER_QUEUE_LENGTH);
+                state.write().subscribers.push(sender);
+                // a failed write drops the receiver, and `update` drops the subscriber with it
+                for line in receiver {
+                    writer.write_all(line.as_bytes())?;
+                }
+                return writer.shutdown(std::net::Shutdown::Both);
+            },
+            Some("fetch") => {
+                match fetch(state, &request) {
+                    Ok((data, bytes)) => {
+                        write_line(&mut writer, &json!({ "ok": true, "data": data, "length": bytes.len() }))?;
+                        writer.write_all(&bytes)?;
+                    },
+                    Err(e) => write_line(&mut writer, &error(e))?
+                }
+            },
+            _ => {
+                let response = respond(&state.read(), &request);
+                write_line(&mut writer, &response)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn respond(state: &ServerState, request: &Value) -> Value {
+    let snapshot = &state.snapshot;
+    match request["cmd"].as_str() {
+        Some("list") => {
+            let path = request["path"].as_str().unwrap_or("");
+            let assets: Vec<Value> = snapshot.assets_in_directory(path).into_iter().map(|(path, info)| asset_json(path, info)).collect();
+            json!({ "ok": true, "directories": snapshot.directories_in(path), "assets": assets })
+        },
+        Some("get") => {
+            let path = match (request["path"].as_str(), request["uid"].as_str()) {
+                (Some(path), _) => Some(AssetPath::new(path).into_string()),
+                (None, Some(uid)) => uid.parse::<AssetId>().ok().and_then(|uid| snapshot.get_path_from_id(uid)).map(str::to_string),
+                (None, None) => return error("Expected a path or uid".to_string())
+            };
+            match path.as_deref().and_then(|path| Some((path, snapshot.get(path)?))) {
+                Some((path, info)) => json!({ "ok": true, "asset": asset_json(path, info) }),
+                None => error("No such asset".to_string())
+            }
+        },
+        Some("tagged") => {
+            let tag = match request["tag"].as_str() {
+                Some(tag) => tag,
+                None => return error("Expected a tag".to_string())
+            };
+            let assets: Vec<Value> = snapshot.iter().filter(|(_, info)| info.tags.iter().any(|t| t == tag)).map(|(path, info)| asset_json(path, info)).collect();
+            json!({ "ok": true, "assets": assets })
+        },
+        Some(cmd) => error(format!("Unknown command '{}'", cmd)),
+        None => error("Expected a cmd".to_string())
+    }
+}
+
+fn fetch(state: &RwLock<ServerState>, request: &Value) -> Result<(PackedData, Vec<u8>), String> {
+    let path = match request["path"].as_str() {
+        Some(path) => AssetPath::new(path).into_string(),
+        None => return Err("Expected a path".to_string())
+    };
+    let tier = request["tier"].as_str().map(str::to_string);
+    // the cache may go over the network, so don't hold up updates meanwhile
+    let (cache, key) = {
+        let state = state.read();
+        let cache = state.derived_cache.clone().ok_or_else(|| "The registry has no derived cache".to_string())?;
+        match state.derived_keys.get(&(path, tier)) {
+            Some(key) => (cache, *key),
+            None => return Err("No such asset or tier".to_string())
+        }
+    };
+    cache.get(key).ok_or_else(|| "Not cooked yet".to_string())
+}
+
+fn asset_json(path: &str, info: &AssetInfo) -> Value {
+    json!({
+        "path": path,
+        "uid": info.uid.to_string(),
+        "type": info.asset_type,
+        "content_hash": info.content_hash,
+        "source_size": info.source_size,
+        "tags": info.tags,
+        "dependencies": info.dependencies,
+        "generated": info.generated,
+        "version": info.version,
+        "texture": info.texture,
+    })
+}
+
+fn event_json(event: &AssetEvent) -> Value {
+    match event {
+        AssetEvent::Added { uid, path } => json!({ "event": "added", "uid": uid.to_string(), "path": path }),
+        AssetEvent::Modified { uid, path } => json!({ "event": "modified", "uid": uid.to_string(), "path": path }),
+        AssetEvent::Removed { uid, path } => json!({ "event": "removed", "uid": uid.to_string(), "path": path }),
+        AssetEvent::Moved { uid, from, to } => json!({ "event": "moved", "uid": uid.to_string(), "from": from, "to": to }),
+        AssetEvent::Reimported { uid, path } => json!({ "event": "reimported", "uid": uid.to_string(), "path": path }),
+        AssetEvent::Swapped { uid, path } => json!({ "event": "swapped", "uid": uid.to_string(), "path": path }),
+    }
+}
+
+fn error(message: String) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+fn write_line(writer: &mut impl Write, value: &Value) -> std::io::Result<()> {
+    writeln!(writer, "{}", value)
+}
@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use hashbrown::HashMap;
+use serde::{Serialize, Deserialize};
+use serde::de::Error as DeError;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::error::AssetRegistryError;
+use crate::asset::AssetId;
+use crate::texture::{TextureMetadata, TextureOverride, TexturePreset};
+use crate::archive;
+
+
+pub const SIDECAR_EXTENSION: &str = "meta";
+/// Bump this and add a migration to `MIGRATIONS` whenever the sidecar format changes in a way
+/// older sidecars can't be read as.
+pub const SIDECAR_VERSION: u32 = 1;
+
+
+// Migrations //////////////////////////////////////////////////////////////////////////////////////
+
+/// Upgrades a sidecar's JSON from the version at its index to the next one.
+type Migration = fn(&mut Value);
+
+const MIGRATIONS: [Migration; SIDECAR_VERSION as usize] = [
+    migrate_channel_masks,
+];
+
+/// Version 0 sidecars stored channel masks as raw bits.
+fn migrate_channel_masks(sidecar: &mut Value) {
+    const CHANNELS: [(u64, char); 4] = [(1, 'R'), (2, 'G'), (4, 'B'), (8, 'A')];
+    for field in ["has_channels", "include_channels"].iter() {
+        if let Some(mask) = sidecar.pointer_mut(&format!("/texture/{}", field)) {
+            if let Some(bits) = mask.as_u64() {
+                *mask = Value::String(CHANNELS.iter().filter(|(bit, _)| bits & bit != 0).map(|(_, letter)| letter).collect());
+            }
+        }
+    }
+}
+
+/// Brings sidecar JSON up to `SIDECAR_VERSION`, returning whether anything changed.
+fn migrate(sidecar: &mut Value) -> Result<bool, serde_json::Error> {
+    let version = sidecar.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > SIDECAR_VERSION as u64 {
+        return Err(serde_json::Error::custom(format!("sidecar version {} is newer than this build supports ({})", version, SIDECAR_VERSION)));
+    }
+    for migration in MIGRATIONS[version as usize..].iter() {
+        migration(sidecar);
+    }
+    if let Some(fields) = sidecar.as_object_mut() {
+        fields.insert("version".to_string(), Value::from(SIDECAR_VERSION));
+    }
+    Ok(version < SIDECAR_VERSION as u64)
+}
+
+
+/// Per-asset settings stored next to the source file as `<filename>.meta`. Sidecars travel with
+/// their source file when it's moved or renamed, so anything stored here survives reorganization.
+/// Sidecars from older versions of the crate are migrated when they're read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// Format version the sidecar was written with. Missing before versioning was introduced.
+    #[serde(default)]
+    pub version: u32,
+    pub uid: Option<AssetId>,
+    /// Import settings for textures. The info block (sizes, channels, format) is ignored on import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<TextureMetadata>,
+    /// Applied over the texture settings on import. Without one, a preset is guessed from the
+    /// filename when there are no texture settings either, see `TexturePreset::from_filename`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<TexturePreset>,
+    /// Texture settings for specific platforms and quality tiers, by name. See `CookProfile`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub texture_overrides: HashMap<String, TextureOverride>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Paths of other assets this one references, relative to the content root, for file types
+    /// that can't declare them themselves.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+impl Default for Sidecar {
+    fn default() -> Self {
+        Self {
+            version: SIDECAR_VERSION,
+            uid: None,
+            texture: None,
+            preset: None,
+            texture_overrides: HashMap::new(),
+            tags: Vec::new(),
+            dependencies: Vec::new(),
+        }
+    }
+}
+
+impl Sidecar {
+    pub fn path_for(source_path: &Path) -> PathBuf {
+        let mut path = source_path.as_os_str().to_os_string();
+        path.push(".");
+        path.push(SIDECAR_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    /// Reads the sidecar for `source_path`, if it has one. Sidecars from older versions are
+    /// migrated and written back in the current format, unless they're inside an archive.
+    pub fn read(source_path: &Path) -> Result<Option<Self>, AssetRegistryError> {
+        let path = Self::path_for(source_path);
+        if !archive::exists(&path) {
+            return Ok(None);
+        }
+        let bytes = archive::read(&path).map_err(|e| AssetRegistryError::io(path.display(), e))?;
+        let sidecar_error = |source| AssetRegistryError::Sidecar { path: path.display().to_string(), source };
+        let mut json: Value = serde_json::from_slice(&bytes).map_err(sidecar_error)?;
+        let migrated = migrate(&mut json).map_err(sidecar_error)?;
+        let sidecar: Sidecar = serde_json::from_value(json).map_err(sidecar_error)?;
+        if migrated && archive::split_archive_path(source_path).is_none() {
+            if let Err(e) = sidecar.write(source_path) {
+                warn!(path = %path.display(), error = %e, "Failed to write migrated sidecar");
+            }
+        }
+        Ok(Some(sidecar))
+    }
+
+    pub fn write(&self, source_path: &Path) -> Result<(), AssetRegistryError> {
+        let path = Self::path_for(source_path);
+        let writer = BufWriter::new(File::create(&path).map_err(|e| AssetRegistryError::io(path.display(), e))?);
+        match serde_json::to_writer_pretty(writer, self) {
+            Ok(()) => Ok(()),
+            Err(source) => Err(AssetRegistryError::Sidecar { path: path.display().to_string(), source })
+        }
+    }
+}
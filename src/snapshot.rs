@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+use chrono::{DateTime, Local};
+use hashbrown::HashMap;
+
+use crate::asset::{Asset, AssetData, AssetId, AssetType};
+use crate::mount::MOUNT_SEPARATOR;
+use crate::path::AssetPath;
+use crate::scan::{ScanEntry, MovedEntry};
+use crate::texture::TextureMetadata;
+
+
+/// An asset's metadata as of a snapshot. Doesn't include decoded data or GPU textures.
+#[derive(Debug, Clone)]
+pub struct AssetInfo {
+    pub uid: AssetId,
+    pub asset_type: AssetType,
+    pub timestamp: DateTime<Local>,
+    pub content_hash: u64,
+    pub source_size: u64,
+    pub tags: Vec<String>,
+    pub dependencies: Vec<String>,
+    pub generated: bool,
+    /// Bumped whenever the asset's data changes, as in `AssetRegistry::versions`.
+    pub version: u64,
+    /// Import settings, for textures.
+    pub texture: Option<TextureMetadata>,
+}
+
+impl AssetInfo {
+    /// Whether anything that affects the processed asset differs: the source's contents, the
+    /// import settings, tags or dependencies. Paths and timestamps aren't compared.
+    pub fn differs_from(&self, other: &AssetInfo) -> bool {
+        // serializing plain settings structs can't fail
+        self.content_hash != other.content_hash
+            || self.asset_type != other.asset_type
+            || self.tags != other.tags
+            || self.dependencies != other.dependencies
+            || bincode::serialize(&self.texture).unwrap() != bincode::serialize(&other.texture).unwrap()
+    }
+
+
+    pub fn new(asset: &Asset, version: u64) -> Self {
+        Self {
+            uid: asset.uid,
+            asset_type: asset.data.asset_type(),
+            timestamp: asset.timestamp,
+            content_hash: asset.content_hash,
+            source_size: asset.source_size,
+            tags: asset.tags.clone(),
+            dependencies: asset.dependencies.clone(),
+            generated: asset.generated,
+            version,
+            texture: match &asset.data {
+                AssetData::Texture(tex_data) => Some(tex_data.settings.clone()),
+            },
+        }
+    }
+}
+
+
+/// An immutable copy of the registry's tree and asset metadata at one point in time. Other threads
+/// can query it while the registry keeps rescanning, see `AssetRegistry::snapshot`.
+///
+/// Lookups only normalize paths: aliases, redirects and case-insensitive matching are left to the
+/// registry.
+#[derive(Debug, Default)]
+pub struct RegistrySnapshot {
+    /// Counts changes to the registry, so two snapshots with the same generation are identical.
+    pub generation: u64,
+    assets: BTreeMap<Arc<str>, AssetInfo>,
+    uid_to_path: HashMap<AssetId, Arc<str>>,
+}
+
+impl RegistrySnapshot {
+    pub fn new(generation: u64, assets: impl Iterator<Item = (Arc<str>, AssetInfo)>) -> Self {
+        let assets: BTreeMap<Arc<str>, AssetInfo> = assets.collect();
+        let uid_to_path = assets.iter().map(|(path, info)| (info.uid, path.clone())).collect();
+        Self { generation, assets, uid_to_path }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&AssetInfo> {
+        self.assets.get(AssetPath::new(path).as_str())
+    }
+
+    pub fn get_by_uid(&self, uid: AssetId) -> Option<&AssetInfo> {
+        self.assets.get(self.uid_to_path.get(&uid)?)
+    }
+
+    pub fn get_path_from_id(&self, uid: AssetId) -> Option<&str> {
+        self.uid_to_path.get(&uid).map(|path| &**path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Every asset with its path, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AssetInfo)> {
+        self.assets.iter().map(|(path, info)| (&**path, info))
+    }
+
+    /// The assets directly in a directory, sorted by path.
+    pub fn assets_in_directory(&self, path: &str) -> Vec<(&str, &AssetInfo)> {
+        let prefix_len = Self::prefix(path).map_or(0, |prefix| prefix.len());
+        self.assets_under(path).into_iter().filter(|(p, _)| !p[prefix_len..].contains('/')).collect()
+    }
+
+    /// Names of the directories directly in a directory that contain assets, sorted.
+    pub fn directories_in(&self, path: &str) -> Vec<String> {
+        let prefix_len = Self::prefix(path).map_or(0, |prefix| prefix.len());
+        let mut directories: Vec<String> = self.assets_under(path).into_iter().filter_map(|(p, _)| {
+            let rest = &p[prefix_len..];
+            // mounts are directories of the root, kept whole with their separator
+            match (prefix_len, rest.find(MOUNT_SEPARATOR), rest.find('/')) {
+                (0, Some(i), _) => Some(rest[..i + MOUNT_SEPARATOR.len()].to_string()),
+                (_, _, Some(i)) => Some(rest[..i].to_string()),
+                _ => None
+            }
+        }).collect();
+        directories.dedup();
+        directories
+    }
+
+    /// Every asset anywhere below a directory, sorted by path.
+    pub fn assets_under(&self, path: &str) -> Vec<(&str, &AssetInfo)> {
+        let prefix = match Self::prefix(path) {
+            Some(prefix) => prefix,
+            None => return self.iter().collect()
+        };
+        self.assets.range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|(p, _)| p.starts_with(&prefix))
+            .map(|(p, info)| (&**p, info))
+            .collect()
+    }
+
+    /// What changed from this snapshot to `newer`, e.g. across a branch switch. Assets are matched
+    /// by UID, so a moved asset is reported as moved rather than removed and added.
+    pub fn diff(&self, newer: &RegistrySnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+        for (path, info) in newer.iter() {
+            match self.uid_to_path.get(&info.uid) {
+                Some(old_path) => {
+                    if **old_path != *path {
+                        diff.moved.push(MovedEntry { uid: info.uid, from: old_path.to_string(), to: path.to_string() });
+                    }
+                    if self.assets[old_path].differs_from(info) {
+                        diff.modified.push(ScanEntry::new(info.uid, path));
+                    }
+                },
+                None => diff.added.push(ScanEntry::new(info.uid, path))
+            }
+        }
+        for (path, info) in self.iter() {
+            if !newer.uid_to_path.contains_key(&info.uid) {
+                diff.removed.push(ScanEntry::new(info.uid, path));
+            }
+        }
+        diff
+    }
+
+    /// What paths below a directory start with, or `None` for the content root.
+    fn prefix(path: &str) -> Option<String> {
+        let path = AssetPath::new(path);
+        match (path.is_root(), path.ends_with(MOUNT_SEPARATOR)) {
+            (true, _) => None,
+            (false, true) => Some(path.into_string()),
+            (false, false) => Some(format!("{}/", path))
+        }
+    }
+}
+
+
+/// Differences between two snapshots, see `RegistrySnapshot::diff`. Every list is sorted by path.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    pub added: Vec<ScanEntry>,
+    pub removed: Vec<ScanEntry>,
+    /// Assets whose contents, settings, tags or dependencies changed, at their new path.
+    pub modified: Vec<ScanEntry>,
+    /// A moved asset is also in `modified` if it changed as well.
+    pub moved: Vec<MovedEntry>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty() && self.moved.is_empty()
+    }
+
+    /// UIDs of every asset that was added, removed, modified or moved.
+    pub fn changed_uids(&self) -> Vec<AssetId> {
+        let mut uids: Vec<AssetId> = self.added.iter().chain(&self.removed).chain(&self.modified).map(|e| e.uid)
+            .chain(self.moved.iter().map(|e| e.uid))
+            .collect();
+        uids.sort();
+        uids.dedup();
+        uids
+    }
+}
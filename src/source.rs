@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+use memmap2::Mmap;
+
+use crate::archive::{self, split_archive_path};
+
+
+/// Files at least this large are memory-mapped instead of read into a buffer.
+pub const MMAP_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+
+/// The raw bytes of a source file, either read into memory or memory-mapped for large files.
+/// Mapping avoids holding a second full copy of big sources (DDS, KTX, heightmaps) during import
+/// and lets the OS page data in as it's actually touched.
+pub enum SourceBytes {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl SourceBytes {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        if split_archive_path(path).is_some() {
+            return Ok(SourceBytes::Owned(archive::read(path)?));
+        }
+        let file = File::open(path)?;
+        if file.metadata()?.len() >= MMAP_THRESHOLD {
+            // Safety: the mapping is only read for the duration of an import. If another process
+            // truncates the file meanwhile the read can fault, which is the same trade-off every
+            // mmap-based loader makes; the file is rehashed on the next scan either way.
+            let map = unsafe { Mmap::map(&file)? };
+            Ok(SourceBytes::Mapped(map))
+        }
+        else {
+            Ok(SourceBytes::Owned(std::fs::read(path)?))
+        }
+    }
+}
+
+impl Deref for SourceBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            SourceBytes::Owned(bytes) => bytes,
+            SourceBytes::Mapped(map) => map,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+/// Asset count and source size of a directory, including everything below it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirectoryStats {
+    pub asset_count: usize,
+    pub source_bytes: u64,
+}
+
+/// Assets whose source files have identical contents.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub content_hash: u64,
+    /// Size of each copy.
+    pub source_size: u64,
+    /// Relative paths of every copy, sorted.
+    pub paths: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// Bytes that would be saved by keeping only one copy.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.source_size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Every directory containing `path`, from the content root (`""`) down to its immediate parent.
+pub(crate) fn parent_directories(path: &str) -> Vec<&str> {
+    let mut dirs = vec![""];
+    dirs.extend(path.match_indices('/').map(|(i, _)| &path[..i]));
+    dirs
+}
@@ -0,0 +1,140 @@
+//! Packs served over HTTP(S), for streaming optional content like DLC or high resolution texture
+//! packs instead of shipping it with the game.
+//!
+//! Only the header and index are downloaded up front. Entries are fetched with range requests the
+//! first time they're read and kept in a local cache directory, so each is only downloaded once.
+//! Any server that supports range requests on static files works.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::error::PackError;
+use crate::pack::{PackIndex, PackEntry, HEADER_SIZE};
+
+
+/// Indexes bigger than this are never downloaded, in case a misconfigured server returns
+/// something else entirely.
+const MAX_INDEX_SIZE: u64 = 1 << 28;
+
+
+/// A pack read over HTTP, see the module docs. Usually used through `PackedRegistry::add_remote_pack`.
+#[derive(Debug)]
+pub struct RemotePack {
+    pub url: String,
+    /// Where the index and downloaded entries are kept. Can be shared between packs.
+    pub cache_directory: PathBuf,
+    agent: ureq::Agent,
+}
+
+impl RemotePack {
+    /// Downloads the pack's index. If the server can't be reached, the index cached the last time
+    /// the pack was opened is used instead, so content that was already downloaded stays playable
+    /// offline.
+    pub fn open(url: &str, cache_directory: &Path) -> Result<(Self, PackIndex), PackError> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(5))
+            .timeout(Duration::from_secs(60))
+            .build();
+        let pack = Self { url: url.to_string(), cache_directory: cache_directory.to_path_buf(), agent };
+        let index_path = pack.index_path();
+        let index = match pack.download_index() {
+            Ok(index) => {
+                if let Err(e) = pack.write_cached(&index_path, &index) {
+                    warn!(path = %index_path.display(), error = %e, "Failed to cache pack index");
+                }
+                index
+            },
+            Err(e @ PackError::Http { .. }) => match fs::read(&index_path) {
+                Ok(index) => {
+                    warn!(url, error = %e, "Pack server is unreachable, using the cached index");
+                    index
+                },
+                Err(_) => return Err(e)
+            },
+            Err(e) => return Err(e)
+        };
+        let index = PackIndex::decode(&index)?;
+        Ok((pack, index))
+    }
+
+    /// Reads an entry's stored bytes, from the local cache or else from the server.
+    pub fn read(&self, entry: &PackEntry) -> Result<Vec<u8>, PackError> {
+        let path = self.entry_path(entry);
+        if let Ok(stored) = fs::read(&path) {
+            if twox_hash::XxHash64::oneshot(0, &stored) == entry.stored_hash {
+                return Ok(stored);
+            }
+            warn!(path = %path.display(), "Ignoring corrupt cached pack entry");
+        }
+        let stored = self.download_range(entry.offset, entry.size)?;
+        if twox_hash::XxHash64::oneshot(0, &stored) != entry.stored_hash {
+            return Err(PackError::Corrupt);
+        }
+        if let Err(e) = self.write_cached(&path, &stored) {
+            warn!(path = %path.display(), error = %e, "Failed to cache pack entry");
+        }
+        Ok(stored)
+    }
+
+    /// Whether an entry has already been downloaded.
+    pub fn is_cached(&self, entry: &PackEntry) -> bool {
+        self.entry_path(entry).exists()
+    }
+
+    /// Cached entries are named by the hash of their stored bytes, so packs and patches sharing
+    /// an entry also share its download.
+    fn entry_path(&self, entry: &PackEntry) -> PathBuf {
+        let name = format!("{:016x}", entry.stored_hash);
+        self.cache_directory.join(&name[..2]).join(name)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.cache_directory.join(format!("{:016x}.index", twox_hash::XxHash64::oneshot(0, self.url.as_bytes())))
+    }
+
+    fn download_index(&self) -> Result<Vec<u8>, PackError> {
+        let header = self.download_range(0, HEADER_SIZE)?;
+        let (index_offset, index_size) = PackIndex::read_header(&header)?;
+        if index_size > MAX_INDEX_SIZE {
+            return Err(PackError::Truncated);
+        }
+        self.download_range(index_offset, index_size)
+    }
+
+    fn download_range(&self, offset: u64, size: u64) -> Result<Vec<u8>, PackError> {
+        let http_error = |reason: String| PackError::Http { url: self.url.clone(), reason };
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let range = format!("bytes={}-{}", offset, offset + size - 1);
+        let response = match self.agent.get(&self.url).set("Range", &range).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(416, _)) => return Err(PackError::Truncated),
+            Err(ureq::Error::Status(status, _)) => return Err(http_error(format!("status {}", status))),
+            Err(ureq::Error::Transport(e)) => return Err(http_error(e.to_string()))
+        };
+        // a plain 200 means the server ignored the range and is sending the whole pack
+        if response.status() != 206 {
+            return Err(http_error("the server doesn't support range requests".to_string()));
+        }
+        let mut bytes = Vec::with_capacity(size as usize);
+        response.into_reader().take(size).read_to_end(&mut bytes).map_err(|e| http_error(e.to_string()))?;
+        match bytes.len() as u64 == size {
+            true => Ok(bytes),
+            false => Err(PackError::Truncated)
+        }
+    }
+
+    fn write_cached(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // written beside the file and renamed into place, so readers never see half of it
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, bytes)?;
+        fs::rename(&temp_path, path)
+    }
+}
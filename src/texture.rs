@@ -1,14 +1,98 @@
-use vulkano::sampler::{Filter, SamplerAddressMode};
-use vulkano::format::{Format, R8G8B8A8Srgb};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode, SamplerCreationError};
+use vulkano::format::{Format, FormatDesc, AcceptsPixels, R8G8B8A8Srgb, R8G8B8A8Unorm, R8G8Unorm, R8Unorm};
+use vulkano::format::{BC1_RGBASrgbBlock, BC1_RGBAUnormBlock, BC3SrgbBlock, BC3UnormBlock};
 use toolbelt::color::LinearColor;
+use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
-use vulkano::image::{ImmutableImage, Dimensions};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use vulkano::image::{ImmutableImage, Dimensions, ImageViewAccess};
+use vulkano::device::{Device, Queue};
+use vulkano::sync::GpuFuture;
+use parking_lot::Mutex;
+use tracing::debug_span;
+use serde::{Serialize, Deserialize};
+
+use crate::color::ColorPrimaries;
+use crate::dither::DitherMode;
+use crate::serde_shims::{FilterDef, SamplerAddressModeDef, LinearColorDef};
+use crate::error::UploadError;
+use crate::mips::{ImageUpload, upload_blocks, upload_with_mips};
+
+
+pub const SUPPORTED_FORMATS: &[Format] = &[
+    Format::R8G8B8A8Srgb,
+    Format::R8G8B8A8Unorm,
+    Format::R8G8Unorm,
+    Format::R8Unorm,
+    Format::BC1_RGBASrgbBlock,
+    Format::BC1_RGBAUnormBlock,
+    Format::BC3SrgbBlock,
+    Format::BC3UnormBlock,
+];
 
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone)]
 pub enum Texture {
     RGBA8_Srgb(Arc<ImmutableImage<R8G8B8A8Srgb>>),
+    RGBA8_Unorm(Arc<ImmutableImage<R8G8B8A8Unorm>>),
+    RG8_Unorm(Arc<ImmutableImage<R8G8Unorm>>),
+    R8_Unorm(Arc<ImmutableImage<R8Unorm>>),
+    BC1_Srgb(Arc<ImmutableImage<BC1_RGBASrgbBlock>>),
+    BC1_Unorm(Arc<ImmutableImage<BC1_RGBAUnormBlock>>),
+    BC3_Srgb(Arc<ImmutableImage<BC3SrgbBlock>>),
+    BC3_Unorm(Arc<ImmutableImage<BC3UnormBlock>>),
+}
+
+impl Texture {
+    /// How many clones of the texture are alive, including the registry's cached one.
+    pub fn references(&self) -> usize {
+        match self {
+            Texture::RGBA8_Srgb(img) => Arc::strong_count(img),
+            Texture::RGBA8_Unorm(img) => Arc::strong_count(img),
+            Texture::RG8_Unorm(img) => Arc::strong_count(img),
+            Texture::R8_Unorm(img) => Arc::strong_count(img),
+            Texture::BC1_Srgb(img) => Arc::strong_count(img),
+            Texture::BC1_Unorm(img) => Arc::strong_count(img),
+            Texture::BC3_Srgb(img) => Arc::strong_count(img),
+            Texture::BC3_Unorm(img) => Arc::strong_count(img),
+        }
+    }
+
+    /// The image as a view, for binding it.
+    pub fn view(&self) -> &dyn ImageViewAccess {
+        match self {
+            Texture::RGBA8_Srgb(img) => &**img,
+            Texture::RGBA8_Unorm(img) => &**img,
+            Texture::RG8_Unorm(img) => &**img,
+            Texture::R8_Unorm(img) => &**img,
+            Texture::BC1_Srgb(img) => &**img,
+            Texture::BC1_Unorm(img) => &**img,
+            Texture::BC3_Srgb(img) => &**img,
+            Texture::BC3_Unorm(img) => &**img,
+        }
+    }
+
+    /// Approximate GPU memory used by the image, including its mip chain.
+    pub fn gpu_bytes(&self) -> usize {
+        // block compressed formats store 4x4 texels per block
+        let (dimensions, mip_levels, block_size, block_bytes) = match self {
+            Texture::RGBA8_Srgb(img) => (img.dimensions(), img.mipmap_levels(), 1, 4),
+            Texture::RGBA8_Unorm(img) => (img.dimensions(), img.mipmap_levels(), 1, 4),
+            Texture::RG8_Unorm(img) => (img.dimensions(), img.mipmap_levels(), 1, 2),
+            Texture::R8_Unorm(img) => (img.dimensions(), img.mipmap_levels(), 1, 1),
+            Texture::BC1_Srgb(img) => (img.dimensions(), img.mipmap_levels(), 4, 8),
+            Texture::BC1_Unorm(img) => (img.dimensions(), img.mipmap_levels(), 4, 8),
+            Texture::BC3_Srgb(img) => (img.dimensions(), img.mipmap_levels(), 4, 16),
+            Texture::BC3_Unorm(img) => (img.dimensions(), img.mipmap_levels(), 4, 16),
+        };
+        let layers = dimensions.depth() * dimensions.array_layers_with_cube();
+        (0..mip_levels).map(|level| {
+            let width = (dimensions.width() >> level).max(1).div_ceil(block_size) as usize;
+            let height = (dimensions.height() >> level).max(1).div_ceil(block_size) as usize;
+            width * height * layers as usize * block_bytes
+        }).sum()
+    }
 }
 
 bitflags! {
@@ -20,12 +104,13 @@ bitflags! {
   }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompressionMode {
     None,
     DXT1,
     DXT1Cutout,
-    DXT5
+    DXT5,
+    ASTC4x4,
 }
 impl std::fmt::Display for CompressionMode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -34,12 +119,154 @@ impl std::fmt::Display for CompressionMode {
             CompressionMode::DXT1 => "DXT1",
             CompressionMode::DXT1Cutout => "DXT1 w/ 1-bit Alpha",
             CompressionMode::DXT5 => "DXT5",
+            CompressionMode::ASTC4x4 => "ASTC 4x4",
         };
         write!(f, "{}", formatstr)
     }
 }
 
-#[derive(Debug, Clone)]
+/// A submitted upload. The texture can't be used until it's finished.
+pub type UploadFuture = Box<dyn GpuFuture + Send + Sync>;
+
+/// Uploads tightly packed RGBA8 pixels as a texture in the format its settings ask for, dropping
+/// any channels the format doesn't have. For block compressed formats, `pixels` is the block data
+/// instead, see `upload_blocks`. The upload is submitted right away, and the returned future has
+/// to be joined before the texture is used.
+pub fn upload_texture(pixels: &[u8], settings: &TextureMetadata, queue: &Arc<Queue>, path: &str) -> Result<(Texture, UploadFuture), UploadError> {
+    upload_texture_on(pixels, settings, queue, queue, path)
+}
+
+/// `upload_texture`, submitted on `queue` for frames submitted on `frame_queue`. When the two
+/// differ the upload signals a semaphore, which the frame waits on once the future is joined into
+/// it.
+pub fn upload_texture_on(pixels: &[u8], settings: &TextureMetadata, queue: &Arc<Queue>, frame_queue: &Arc<Queue>, path: &str) -> Result<(Texture, UploadFuture), UploadError> {
+    let _span = debug_span!("upload", path, format = ?settings.format, size = ?settings.max_ingame_size).entered();
+    let (texture, future) = match settings.format {
+        Format::R8G8B8A8Srgb => {
+            let (img, future) = upload_image(pixels, settings, R8G8B8A8Srgb, 4, queue, path)?;
+            (Texture::RGBA8_Srgb(img), future)
+        },
+        Format::R8G8B8A8Unorm => {
+            let (img, future) = upload_image(pixels, settings, R8G8B8A8Unorm, 4, queue, path)?;
+            (Texture::RGBA8_Unorm(img), future)
+        },
+        Format::R8G8Unorm => {
+            let (img, future) = upload_image(pixels, settings, R8G8Unorm, 2, queue, path)?;
+            (Texture::RG8_Unorm(img), future)
+        },
+        Format::R8Unorm => {
+            let (img, future) = upload_image(pixels, settings, R8Unorm, 1, queue, path)?;
+            (Texture::R8_Unorm(img), future)
+        },
+        Format::BC1_RGBASrgbBlock => {
+            let (img, future) = upload_blocks(pixels, settings, BC1_RGBASrgbBlock, 8, queue, path)?;
+            (Texture::BC1_Srgb(img), future)
+        },
+        Format::BC1_RGBAUnormBlock => {
+            let (img, future) = upload_blocks(pixels, settings, BC1_RGBAUnormBlock, 8, queue, path)?;
+            (Texture::BC1_Unorm(img), future)
+        },
+        Format::BC3SrgbBlock => {
+            let (img, future) = upload_blocks(pixels, settings, BC3SrgbBlock, 16, queue, path)?;
+            (Texture::BC3_Srgb(img), future)
+        },
+        Format::BC3UnormBlock => {
+            let (img, future) = upload_blocks(pixels, settings, BC3UnormBlock, 16, queue, path)?;
+            (Texture::BC3_Unorm(img), future)
+        },
+        format => return Err(UploadError::UnsupportedFormat { path: path.to_string(), format })
+    };
+    let submit_error = |source| UploadError::Submit { path: path.to_string(), source };
+    match queue.is_same(frame_queue) {
+        true => {
+            future.flush().map_err(submit_error)?;
+            Ok((texture, Box::new(future)))
+        },
+        false => Ok((texture, Box::new(future.then_signal_semaphore_and_flush().map_err(submit_error)?)))
+    }
+}
+
+/// Whether textures with these settings can be uploaded on `queue`. Every queue family can copy,
+/// but generating mips on the GPU blits, which needs a graphics queue.
+pub fn can_upload_on(queue: &Queue, settings: &TextureMetadata) -> bool {
+    let family = queue.family();
+    let transfers = family.supports_graphics() || family.supports_compute() || family.explicitly_supports_transfers();
+    transfers && (!settings.gpu_mips || family.supports_graphics())
+}
+
+/// Where the next upload of a texture with these settings goes: the `transfer` queues that can
+/// take it in turn, counting with `next`, or `frame_queue` if none can.
+pub(crate) fn pick_upload_queue<'a>(frame_queue: &'a Arc<Queue>, transfer: &'a [Arc<Queue>], next: &AtomicUsize, settings: &TextureMetadata) -> &'a Arc<Queue> {
+    let candidates: Vec<&Arc<Queue>> = transfer.iter().filter(|queue| can_upload_on(queue, settings)).collect();
+    match candidates.len() {
+        0 => frame_queue,
+        len => candidates[next.fetch_add(1, Ordering::Relaxed) % len]
+    }
+}
+
+/// Uploads RGBA8 pixels, checking they match the settings first, since vulkano panics on a copy
+/// that doesn't fit the image. They won't if the source changed size since it was imported.
+fn upload_image<F>(pixels: &[u8], settings: &TextureMetadata, format: F, channels: usize, queue: &Arc<Queue>, path: &str) -> Result<ImageUpload<F>, UploadError>
+    where F: FormatDesc + AcceptsPixels<u8> + Clone + Send + Sync + 'static
+{
+    let [width, height] = settings.max_ingame_size;
+    if pixels.len() != width as usize * height as usize * 4 {
+        return Err(UploadError::SizeMismatch(path.to_string()));
+    }
+    match settings.mip_gen_settings {
+        MipGenSettings::NoMipmaps => {
+            ImmutableImage::from_iter(pack_channels(pixels, channels).into_iter(), settings.dimensions(), format, queue.clone())
+                .map_err(|source| UploadError::ImageCreation { path: path.to_string(), source })
+        },
+        _ => upload_with_mips(pixels, settings, format, channels, queue, path)
+    }
+}
+
+/// Keeps the first `channels` channels of each RGBA8 pixel.
+pub fn pack_channels(pixels: &[u8], channels: usize) -> Vec<u8> {
+    match channels {
+        4 => pixels.to_vec(),
+        _ => pixels.chunks_exact(4).flat_map(|pixel| pixel[..channels].iter().cloned()).collect()
+    }
+}
+
+/// Uploads that haven't been joined into a frame yet, see `AssetRegistry::flush_uploads`.
+///
+/// Dropping an upload's future waits for the whole queue to go idle, so they're kept here until
+/// the renderer can wait on them properly instead.
+#[derive(Default)]
+pub struct PendingUploads {
+    futures: Mutex<Vec<UploadFuture>>,
+}
+
+impl PendingUploads {
+    pub fn push(&self, future: UploadFuture) {
+        self.futures.lock().push(future);
+    }
+
+    pub fn len(&self) -> usize {
+        self.futures.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.futures.lock().is_empty()
+    }
+
+    /// Takes every pending upload, joined into one future. It's `now` on `device` when nothing
+    /// is pending.
+    pub fn flush(&self, device: &Arc<Device>) -> UploadFuture {
+        let futures = std::mem::take(&mut *self.futures.lock());
+        futures.into_iter().fold(Box::new(vulkano::sync::now(device.clone())), |joined, future| Box::new(joined.join(future)))
+    }
+}
+
+impl Debug for PendingUploads {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PendingUploads({})", self.len())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TextureSize {
     _8x8 = 8,
     _16x16 = 16,
@@ -54,7 +281,38 @@ pub enum TextureSize {
     _8192x8192 = 8192,
 }
 
-#[derive(Debug, Clone)]
+/// Settings replaced when cooking for one platform or quality tier, e.g. half resolution on "low".
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TextureOverride {
+    #[serde(default)]
+    pub max_texture_size: Option<TextureSize>,
+    /// Divides both sides of the texture, e.g. 2 for half resolution.
+    #[serde(default)]
+    pub downscale: Option<u32>,
+    #[serde(default)]
+    pub compression_mode: Option<CompressionMode>,
+}
+
+impl TextureSize {
+    pub fn pixels(&self) -> u32 {
+        self.clone() as u32
+    }
+
+    /// The size with exactly this many pixels per side.
+    pub fn from_pixels(pixels: u32) -> Option<Self> {
+        let sizes = [
+            TextureSize::_8x8, TextureSize::_16x16, TextureSize::_32x32, TextureSize::_64x64,
+            TextureSize::_128x128, TextureSize::_256x256, TextureSize::_512x512, TextureSize::_1024x1024,
+            TextureSize::_2048x2048, TextureSize::_4096x4096, TextureSize::_8192x8192,
+        ];
+        sizes.iter().find(|size| size.pixels() == pixels).cloned()
+    }
+}
+
+/// How mip levels are filtered, the same whether they're generated on the CPU or the GPU. GPU
+/// blits can only filter linearly or pick the nearest texel, so `Sharpen` and `Blur` are filtered
+/// like `Linear` on both.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MipGenSettings {
     NoMipmaps,
     Linear,
@@ -63,50 +321,190 @@ pub enum MipGenSettings {
     Blur
 }
 
-#[derive(Debug, Clone)]
+/// What a texture holds, for picking the import settings that suit it. Data textures are stored
+/// linearly, since decoding them as sRGB would skew their values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TexturePreset {
+    Color,
+    Normal,
+    Roughness,
+    AmbientOcclusion,
+}
+
+impl TexturePreset {
+    /// Guesses a texture's preset from the usual suffixes, e.g. `brick_normal.png` or `brick_ao.png`.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let stem = match filename.rfind('.') {
+            Some(dot) => &filename[..dot],
+            None => filename
+        };
+        let (_, suffix) = stem.rsplit_once('_')?;
+        match suffix.to_lowercase().as_str() {
+            "n" | "nrm" | "normal" => Some(TexturePreset::Normal),
+            "rough" | "roughness" => Some(TexturePreset::Roughness),
+            "ao" | "occlusion" => Some(TexturePreset::AmbientOcclusion),
+            _ => None
+        }
+    }
+
+    pub fn apply(&self, settings: &mut TextureMetadata) {
+        match self {
+            TexturePreset::Color => settings.srgb = true,
+            TexturePreset::Normal | TexturePreset::Roughness | TexturePreset::AmbientOcclusion => settings.srgb = false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PowerOfTwoMode {
     None,
     PadToPowerOfTwo,
     PadToSquarePowerOfTwo,
 }
 
-#[derive(Debug, Clone)]
+/// Color corrections applied to a texture's pixels whenever its source is decoded, so the source
+/// file itself is never touched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureAdjustments {
+    /// Added to each color channel, from -1 to 1.
+    pub brightness: f32,
+    /// Scales colors away from mid grey. 1 leaves them unchanged.
+    pub contrast: f32,
+    /// 0 is greyscale, 1 leaves colors unchanged.
+    pub saturation: f32,
+    /// Rotates hues by this many degrees.
+    pub hue_shift: f32,
+    /// Multiplies each of the red, green, blue and alpha channels.
+    pub channel_gain: [f32; 4],
+}
+
+impl Default for TextureAdjustments {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            hue_shift: 0.0,
+            channel_gain: [1.0; 4],
+        }
+    }
+}
+
+impl TextureAdjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Adjusts tightly packed RGBA8 pixels in place. Gain is applied first, then brightness,
+    /// contrast, saturation and hue.
+    pub fn apply(&self, pixels: &mut [u8]) {
+        if self.is_identity() {
+            return;
+        }
+        // hue shift is a rotation around the grey axis
+        let (sin, cos) = self.hue_shift.to_radians().sin_cos();
+        let (a, b) = ((1.0 - cos) / 3.0, (1.0f32 / 3.0).sqrt() * sin);
+        let hue = [
+            [cos + a, a - b, a + b],
+            [a + b, cos + a, a - b],
+            [a - b, a + b, cos + a],
+        ];
+        let weights = ColorPrimaries::Rec709.luma_weights();
+        for pixel in pixels.chunks_exact_mut(4) {
+            let mut rgb = [0.0f32; 3];
+            for c in 0..3 {
+                let value = pixel[c] as f32 / 255.0 * self.channel_gain[c] + self.brightness;
+                rgb[c] = (value - 0.5) * self.contrast + 0.5;
+            }
+            let luma = weights[0] * rgb[0] + weights[1] * rgb[1] + weights[2] * rgb[2];
+            for value in rgb.iter_mut() {
+                *value = luma + (*value - luma) * self.saturation;
+            }
+            for c in 0..3 {
+                let value = hue[c][0] * rgb[0] + hue[c][1] * rgb[1] + hue[c][2] * rgb[2];
+                pixel[c] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+            pixel[3] = (pixel[3] as f32 * self.channel_gain[3]).clamp(0.0, 255.0).round() as u8;
+        }
+    }
+}
+
+/// Fields missing from serialized settings take their default values, so settings written before a
+/// field existed still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TextureMetadata {
     // info block:
     pub source_size: [u32; 2],
     pub max_ingame_size: [u32; 2],
     // bytes, uncompressed and compressed
     pub data_size: [u32; 2],
+    #[serde(with = "crate::serde_shims::channel_mask")]
     pub has_channels: ChannelMask,
+    #[serde(with = "crate::serde_shims::format")]
     pub format: Format,
     pub num_mips: u8,
 
     // compresion block:
     pub compression_mode: CompressionMode,
+    #[serde(with = "crate::serde_shims::channel_mask")]
     pub include_channels: ChannelMask,
     pub max_texture_size: Option<TextureSize>,
+    /// Dithering used when pixels lose precision while cooking, to avoid banding in gradients.
+    pub dithering: DitherMode,
     pub mip_gen_settings: MipGenSettings,
-    pub lod_bias: u8,
+    /// Generate mips on the GPU right after upload instead of on the CPU, see `mips`.
+    pub gpu_mips: bool,
+    /// Added to the mip level picked when sampling. Negative sharpens, positive blurs. Clamped to
+    /// what the device supports.
+    pub lod_bias: f32,
 
     // texture block:
     pub power_of_two_mode: PowerOfTwoMode,
+    #[serde(with = "LinearColorDef")]
     pub padding_color: LinearColor,
     pub srgb: bool,
+    /// Primaries of the source's colors. Anything other than Rec.709 is converted on import.
+    pub primaries: ColorPrimaries,
+    #[serde(with = "SamplerAddressModeDef")]
     pub x_axis_tiling: SamplerAddressMode,
+    #[serde(with = "SamplerAddressModeDef")]
     pub y_axis_tiling: SamplerAddressMode,
     pub invert_green: bool,
+    #[serde(with = "FilterDef")]
     pub filter: Filter,
+    /// Anisotropic filtering, as the most samples taken per lookup, e.g. 16 for floors seen at
+    /// grazing angles. Clamped to what the device supports, and ignored if it doesn't enable it.
+    pub anisotropy: Option<f32>,
 
     // adjustments block
-    // TODO: texture adjustments
+    pub adjustments: TextureAdjustments,
 }
 impl TextureMetadata {
+    /// Size of the pixel data, which is smaller than the source if it was scaled down when cooked.
     pub fn dimensions(&self) -> Dimensions {
         Dimensions::Dim2d {
-            width: self.source_size[0],
-            height: self.source_size[1]
+            width: self.max_ingame_size[0],
+            height: self.max_ingame_size[1]
         }
     }
+
+    /// A sampler for the texture, with its filtering and tiling.
+    pub fn create_sampler(&self, device: &Arc<Device>) -> Result<Arc<Sampler>, SamplerCreationError> {
+        let mipmap_mode = match self.filter {
+            Filter::Nearest => MipmapMode::Nearest,
+            Filter::Linear => MipmapMode::Linear
+        };
+        let max_lod_bias = device.physical_device().limits().max_sampler_lod_bias();
+        let lod_bias = self.lod_bias.clamp(-max_lod_bias, max_lod_bias);
+        let max_anisotropy = match (self.anisotropy, device.enabled_features().sampler_anisotropy) {
+            (Some(anisotropy), true) => anisotropy.clamp(1.0, device.physical_device().limits().max_sampler_anisotropy()),
+            _ => 1.0
+        };
+        Sampler::new(device.clone(), self.filter, self.filter, mipmap_mode, self.x_axis_tiling, self.y_axis_tiling,
+            SamplerAddressMode::Repeat, lod_bias, max_anisotropy, 0.0, 1000.0)
+    }
 }
 impl Default for TextureMetadata {
     fn default() -> Self {
@@ -120,15 +518,20 @@ impl Default for TextureMetadata {
             compression_mode: CompressionMode::None,
             include_channels: ChannelMask::all(),
             max_texture_size: None,
+            dithering: DitherMode::None,
             mip_gen_settings: MipGenSettings::NoMipmaps,
-            lod_bias: 0,
+            gpu_mips: false,
+            lod_bias: 0.0,
             power_of_two_mode: PowerOfTwoMode::None,
             padding_color: LinearColor::BLACK,
             srgb: true,
+            primaries: ColorPrimaries::Rec709,
             x_axis_tiling: SamplerAddressMode::Repeat,
             y_axis_tiling: SamplerAddressMode::Repeat,
             invert_green: false,
             filter: Filter::Linear,
+            anisotropy: None,
+            adjustments: TextureAdjustments::default(),
         }
     }
 }
@@ -0,0 +1,98 @@
+//! Small previews of assets for editors and asset browsers, cached on disk by the content hash of
+//! their source so they survive restarts and are only regenerated when the source or its
+//! adjustments change.
+
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use hashbrown::HashSet;
+use image::{ColorType, ImageDecoder};
+use image::png::{PNGEncoder, PNGDecoder};
+
+use crate::asset::{Asset, AssetData};
+use crate::color::{ColorPrimaries, resize_rgba8};
+use crate::dither::DitherMode;
+use crate::error::AssetRegistryError;
+use crate::gc::{CacheFile, GcOptions, GcReport, collect_garbage, read_dir};
+
+
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 128;
+
+/// Key of an asset's thumbnail in a `ThumbnailCache`. This is the source's content hash, mixed with
+/// any settings that change how it looks.
+pub fn thumbnail_key(asset: &Asset) -> u64 {
+    match &asset.data {
+        AssetData::Texture(tex_data) if !tex_data.settings.adjustments.is_identity() || tex_data.settings.primaries != ColorPrimaries::Rec709 => {
+            let mut hasher = twox_hash::XxHash64::with_seed(asset.content_hash);
+            // serializing plain settings structs can't fail
+            hasher.write(&bincode::serialize(&tex_data.settings.adjustments).unwrap());
+            hasher.write(&bincode::serialize(&tex_data.settings.primaries).unwrap());
+            hasher.finish()
+        }
+        _ => asset.content_hash
+    }
+}
+
+/// Scales tightly packed RGBA8 pixels down to fit in `max_size` on both sides, keeping the aspect
+/// ratio, filtering in linear space if they're sRGB encoded. Images that already fit are returned
+/// as they are.
+pub fn downscale_rgba(pixels: Vec<u8>, size: [u32; 2], max_size: u32, srgb: bool) -> (Vec<u8>, [u32; 2]) {
+    let [width, height] = size;
+    if width.max(height) <= max_size {
+        return (pixels, size);
+    }
+    let scale = max_size as f64 / width.max(height) as f64;
+    let scaled = [((width as f64 * scale) as u32).max(1), ((height as f64 * scale) as u32).max(1)];
+    match resize_rgba8(&pixels, size, scaled, srgb, DitherMode::None) {
+        Some(pixels) => (pixels, scaled),
+        None => (Vec::new(), [0, 0])
+    }
+}
+
+/// A directory of PNG thumbnails, named by source content hash and size.
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    pub root: PathBuf,
+    /// Largest side of generated thumbnails, in pixels.
+    pub size: u32,
+}
+
+impl ThumbnailCache {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf(), size: DEFAULT_THUMBNAIL_SIZE }
+    }
+
+    pub fn path_for(&self, content_hash: u64) -> PathBuf {
+        self.root.join(format!("{:016x}-{}.png", content_hash, self.size))
+    }
+
+    /// Reads a cached thumbnail as RGBA8 pixels and their size.
+    pub fn get(&self, content_hash: u64) -> Option<(Vec<u8>, [u32; 2])> {
+        let file = fs::read(self.path_for(content_hash)).ok()?;
+        let decoder = PNGDecoder::new(&file[..]).ok()?;
+        let (width, height) = decoder.dimensions();
+        match decoder.colortype() {
+            ColorType::RGBA(8) => Some((decoder.read_image().ok()?, [width as u32, height as u32])),
+            _ => None
+        }
+    }
+
+    pub fn put(&self, content_hash: u64, pixels: &[u8], size: [u32; 2]) -> Result<(), AssetRegistryError> {
+        let path = self.path_for(content_hash);
+        fs::create_dir_all(&self.root).map_err(|e| AssetRegistryError::io(self.root.display(), e))?;
+        let mut encoded = Vec::new();
+        PNGEncoder::new(&mut encoded).encode(pixels, size[0], size[1], ColorType::RGBA(8))
+            .map_err(|e| AssetRegistryError::io(path.display(), e))?;
+        fs::write(&path, encoded).map_err(|e| AssetRegistryError::io(path.display(), e))
+    }
+
+    /// Deletes every thumbnail whose key isn't in `live`, whatever its size, and any past the
+    /// limits in `options`.
+    pub fn collect_garbage(&self, live: &HashSet<u64>, options: &GcOptions) -> Result<GcReport, AssetRegistryError> {
+        let files = read_dir(&self.root)?.into_iter().filter_map(|path| {
+            let key = path.file_name()?.to_str()?.split('-').next().and_then(|key| u64::from_str_radix(key, 16).ok());
+            CacheFile::new(path, key)
+        }).collect();
+        collect_garbage(files, live, options)
+    }
+}
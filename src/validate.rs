@@ -0,0 +1,89 @@
+//! Content standards checked on import. Assets that break them still import, but the problems
+//! are collected in the scan report so they can be caught automatically, e.g. failing a build.
+//! Checks that need decoded pixels would slow every scan down, so they're done when assets are
+//! cooked and end up in the cook report instead.
+
+use std::fmt::{Display, Formatter, Error};
+use vulkano::sampler::SamplerAddressMode;
+
+use crate::texture::{TextureMetadata, ChannelMask};
+
+
+/// Textures bigger than this on either side are flagged as too large.
+pub const MAX_TEXTURE_DIMENSION: u32 = 8192;
+
+/// Filename endings that mark a texture as a normal map, before the extension.
+const NORMAL_MAP_SUFFIXES: &[&str] = &["_n", "_nrm", "_normal"];
+
+
+/// A problem with an imported asset. Paths are relative to the content root.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportWarning {
+    /// The texture tiles but isn't a power of two on a tiling side, so it can't mip or wrap cleanly.
+    NonPowerOfTwo { path: String, size: [u32; 2] },
+    /// The texture is bigger than `MAX_TEXTURE_DIMENSION` on a side.
+    TooLarge { path: String, size: [u32; 2] },
+    /// The texture has an alpha channel, but every pixel is fully opaque. Only checked when the
+    /// texture is cooked, as that's when its pixels are decoded, so it's in `CookReport::warnings`
+    /// rather than the scan report.
+    OpaqueAlpha { path: String },
+    /// The texture looks like a normal map but is sampled as sRGB.
+    SrgbNormalMap { path: String },
+}
+
+impl ImportWarning {
+    pub fn path(&self) -> &str {
+        match self {
+            ImportWarning::NonPowerOfTwo { path, .. } => path,
+            ImportWarning::TooLarge { path, .. } => path,
+            ImportWarning::OpaqueAlpha { path } => path,
+            ImportWarning::SrgbNormalMap { path } => path,
+        }
+    }
+}
+
+impl Display for ImportWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "'{}': ", self.path())?;
+        match self {
+            ImportWarning::NonPowerOfTwo { size, .. } => write!(f, "Tiling texture is not a power of two ({}x{})", size[0], size[1]),
+            ImportWarning::TooLarge { size, .. } => write!(f, "Texture is larger than {} pixels ({}x{})", MAX_TEXTURE_DIMENSION, size[0], size[1]),
+            ImportWarning::OpaqueAlpha { .. } => write!(f, "Alpha channel is fully opaque"),
+            ImportWarning::SrgbNormalMap { .. } => write!(f, "Normal map is sampled as sRGB"),
+        }
+    }
+}
+
+/// Normal maps are recognized by their green channel being flipped or by their filename.
+pub fn is_normal_map(relative_path: &str, settings: &TextureMetadata) -> bool {
+    let name = relative_path.rsplit('/').next().unwrap_or(relative_path).to_lowercase();
+    let stem = name.split('.').next().unwrap_or(&name);
+    settings.invert_green || NORMAL_MAP_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix))
+}
+
+/// Checks an imported texture's settings.
+pub fn validate_texture(relative_path: &str, settings: &TextureMetadata) -> Vec<ImportWarning> {
+    let mut warnings = Vec::new();
+    let path = relative_path.to_string();
+    let size = settings.source_size;
+    let tiles = |mode: SamplerAddressMode| matches!(mode, SamplerAddressMode::Repeat | SamplerAddressMode::MirroredRepeat);
+    if (tiles(settings.x_axis_tiling) && !size[0].is_power_of_two()) || (tiles(settings.y_axis_tiling) && !size[1].is_power_of_two()) {
+        warnings.push(ImportWarning::NonPowerOfTwo { path: path.clone(), size });
+    }
+    if size[0].max(size[1]) > MAX_TEXTURE_DIMENSION {
+        warnings.push(ImportWarning::TooLarge { path: path.clone(), size });
+    }
+    if settings.srgb && is_normal_map(relative_path, settings) {
+        warnings.push(ImportWarning::SrgbNormalMap { path });
+    }
+    warnings
+}
+
+/// Checks a texture's decoded RGBA8 pixels, for checks too expensive to do on every import.
+pub fn validate_pixels(relative_path: &str, settings: &TextureMetadata, pixels: &[u8]) -> Vec<ImportWarning> {
+    let mut warnings = Vec::new();
+    if settings.include_channels.contains(ChannelMask::ALPHA) && pixels.chunks_exact(4).all(|p| p[3] == 255) {
+        warnings.push(ImportWarning::OpaqueAlpha { path: relative_path.to_string() });
+    }
+    warnings
+}
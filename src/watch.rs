@@ -0,0 +1,94 @@
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher, Event, EventKind};
+use tracing::warn;
+use parking_lot::Mutex;
+
+use crate::error::{AssetRegistryError, ScanError};
+use crate::sidecar::SIDECAR_EXTENSION;
+use crate::mount::{Mount, path_segments, join_path};
+
+
+/// Watches the content directory and queues filesystem events until they're drained by
+/// `AssetRegistry::pump_events`.
+pub struct AssetWatcher {
+    // events stop as soon as the watcher is dropped
+    _watcher: RecommendedWatcher,
+    /// Canonical root of each watched mount, with the mount's path prefix.
+    roots: Vec<(PathBuf, String)>,
+    // locked so the registry stays `Sync`, only `pump_events` ever drains it
+    events: Mutex<Receiver<notify::Result<Event>>>,
+}
+
+impl AssetWatcher {
+    pub fn new(mounts: &[Mount]) -> Result<Self, AssetRegistryError> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(ScanError::from)?;
+        let mut roots = Vec::new();
+        for mount in mounts.iter() {
+            for root in mount.roots() {
+                let root = root.canonicalize().map_err(|e| AssetRegistryError::io(root.display(), e))?;
+                watcher.watch(&root, RecursiveMode::Recursive).map_err(ScanError::from)?;
+                roots.push((root, mount.prefix()));
+            }
+        }
+        // nested mounts claim their own files
+        roots.sort_by_key(|(root, _)| std::cmp::Reverse(root.as_os_str().len()));
+        Ok(Self { _watcher: watcher, roots, events: Mutex::new(events) })
+    }
+
+    /// Drains all queued events, returning the distinct paths they touched, relative to the
+    /// content root. Changes to a sidecar are reported as changes to the asset it belongs to.
+    pub fn drain_changed_paths(&self) -> Vec<String> {
+        let sidecar_suffix = format!(".{}", SIDECAR_EXTENSION);
+        let mut paths = Vec::new();
+        for event in self.events.lock().try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!(error = %e, "File watcher error");
+                    continue;
+                }
+            };
+            if let EventKind::Access(_) = event.kind {
+                continue;
+            }
+            for path in event.paths.iter() {
+                let found = self.roots.iter().find_map(|(root, prefix)| Some((path.strip_prefix(root).ok()?, prefix)));
+                if let Some((relative, prefix)) = found {
+                    let relative = relative.to_string_lossy().replace("\\", "/");
+                    let relative = relative.trim_end_matches(sidecar_suffix.as_str());
+                    // a mounted archive changing means its whole mount needs rescanning
+                    if !relative.is_empty() || !prefix.is_empty() {
+                        paths.push(format!("{}{}", prefix, relative));
+                    }
+                }
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+impl Debug for AssetWatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let roots: Vec<String> = self.roots.iter().map(|(root, _)| root.display().to_string()).collect();
+        write!(f, "AssetWatcher({})", roots.join(", "))
+    }
+}
+
+/// The deepest directory containing every path in `paths`, or `""` for the content root.
+pub fn common_ancestor(paths: &[String]) -> String {
+    let mut common: Option<Vec<&str>> = None;
+    for path in paths.iter() {
+        let mut parent: Vec<&str> = path_segments(path);
+        parent.pop();
+        common = Some(match common {
+            None => parent,
+            Some(common) => common.into_iter().zip(parent).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+        });
+    }
+    common.unwrap_or_default().into_iter().fold(String::new(), |path, segment| join_path(&path, segment))
+}